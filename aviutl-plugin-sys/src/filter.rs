@@ -110,7 +110,7 @@ pub struct FrameStatus {
     pub audio: c_int,
     /// The interlace mode of a frame.
     pub inter: FrameInterlace,
-    /// Unused.
+    /// The position of a frame in the 3:2 pulldown cycle on 24fps conversion.
     pub index24fps: c_int,
     /// The identifier of the profile environment of a frame.
     pub config: c_int,
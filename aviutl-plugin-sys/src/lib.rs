@@ -1,27 +1,16 @@
 //! This sys crate provides FFI data definition for AviUtl Plugin DLL (Win32).
 #![warn(missing_docs)]
 
-use derive_more::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
-use std::os::raw::{c_int, c_short, c_void};
+use std::{
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign},
+    os::raw::{c_int, c_short, c_void},
+};
 
 /// YCbCr pixel data. These values may go out from its range.
-#[derive(
-    Debug,
-    Default,
-    Clone,
-    Copy,
-    PartialEq,
-    Eq,
-    Hash,
-    Add,
-    Sub,
-    Mul,
-    Div,
-    AddAssign,
-    SubAssign,
-    MulAssign,
-    DivAssign,
-)]
+///
+/// Arithmetic operators on this saturate at the bounds of `c_short` instead of overflowing. Use `checked_*` methods
+/// to detect an overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub struct PixelYc {
     /// Luma data, between 0 and 4096.
@@ -32,6 +21,86 @@ pub struct PixelYc {
     pub cr: c_short,
 }
 
+impl PixelYc {
+    /// The minimum value of documented luma range.
+    pub const Y_MIN: c_short = 0;
+    /// The maximum value of documented luma range.
+    pub const Y_MAX: c_short = 4096;
+    /// The minimum value of documented chroma range.
+    pub const C_MIN: c_short = -2048;
+    /// The maximum value of documented chroma range.
+    pub const C_MAX: c_short = 2048;
+
+    /// Clamps the components into the documented ranges.
+    pub fn clamp_to_range(self) -> Self {
+        Self {
+            y: self.y.clamp(Self::Y_MIN, Self::Y_MAX),
+            cb: self.cb.clamp(Self::C_MIN, Self::C_MAX),
+            cr: self.cr.clamp(Self::C_MIN, Self::C_MAX),
+        }
+    }
+}
+
+macro_rules! impl_component_ops {
+    ($(($checked:ident, $saturating:ident, $rhs:ty, |$r:ident| ($ry:expr, $rcb:expr, $rcr:expr))),* $(,)?) => {
+        impl PixelYc {
+            $(
+                /// Computes component-wise, returning `None` if any component overflowed.
+                pub fn $checked(self, $r: $rhs) -> Option<Self> {
+                    Some(Self {
+                        y: self.y.$checked($ry)?,
+                        cb: self.cb.$checked($rcb)?,
+                        cr: self.cr.$checked($rcr)?,
+                    })
+                }
+
+                /// Computes component-wise, saturating at the bounds of `c_short`.
+                pub fn $saturating(self, $r: $rhs) -> Self {
+                    Self {
+                        y: self.y.$saturating($ry),
+                        cb: self.cb.$saturating($rcb),
+                        cr: self.cr.$saturating($rcr),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+impl_component_ops!(
+    (checked_add, saturating_add, Self, |rhs| (
+        rhs.y, rhs.cb, rhs.cr
+    )),
+    (checked_sub, saturating_sub, Self, |rhs| (
+        rhs.y, rhs.cb, rhs.cr
+    )),
+    (checked_mul, saturating_mul, c_short, |rhs| (rhs, rhs, rhs)),
+    (checked_div, saturating_div, c_short, |rhs| (rhs, rhs, rhs)),
+);
+
+macro_rules! impl_saturating_op {
+    ($op:ident, $method:ident, $assign_op:ident, $assign_method:ident, $rhs:ty, $saturating:ident) => {
+        impl $op<$rhs> for PixelYc {
+            type Output = Self;
+
+            fn $method(self, rhs: $rhs) -> Self {
+                self.$saturating(rhs)
+            }
+        }
+
+        impl $assign_op<$rhs> for PixelYc {
+            fn $assign_method(&mut self, rhs: $rhs) {
+                *self = self.$saturating(rhs);
+            }
+        }
+    };
+}
+
+impl_saturating_op!(Add, add, AddAssign, add_assign, Self, saturating_add);
+impl_saturating_op!(Sub, sub, SubAssign, sub_assign, Self, saturating_sub);
+impl_saturating_op!(Mul, mul, MulAssign, mul_assign, c_short, saturating_mul);
+impl_saturating_op!(Div, div, DivAssign, div_assign, c_short, saturating_div);
+
 /// Definition of multi thread function callback.
 ///
 /// # Parameters
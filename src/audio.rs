@@ -0,0 +1,395 @@
+//! Typed audio samples shared by the plugins.
+//!
+//! Audio is interleaved, so sample `i` of channel `c` is at `i * channels + c`. [`AudioBuf`] owns such samples with
+//! their [`ChannelLayout`], and [`Channel`] and [`ChannelMut`] view one channel of them.
+
+use crate::{AviUtlError, Result};
+use std::iter::StepBy;
+use windows::Win32::Media::Audio::{WAVEFORMATEX, WAVE_FORMAT_PCM};
+
+pub mod analysis;
+pub mod events;
+pub mod loudness;
+pub mod remix;
+pub mod resample;
+
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleFormat {
+    /// Signed 16-bit integer PCM.
+    I16,
+    /// 32-bit float PCM between -1.0 and 1.0.
+    F32,
+}
+
+impl SampleFormat {
+    pub const fn bytes(self) -> usize {
+        match self {
+            SampleFormat::I16 => 2,
+            SampleFormat::F32 => 4,
+        }
+    }
+}
+
+/// A type of samples, converted through `f32` between -1.0 and 1.0.
+pub trait Sample: Copy + Default + PartialEq + Send + Sync + 'static {
+    const FORMAT: SampleFormat;
+
+    fn to_f32(self) -> f32;
+
+    /// Converts from `f32`, saturating values out of range.
+    fn from_f32(value: f32) -> Self;
+
+    fn convert<T: Sample>(self) -> T {
+        T::from_f32(self.to_f32())
+    }
+}
+
+impl Sample for i16 {
+    const FORMAT: SampleFormat = SampleFormat::I16;
+
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value * 32768.0).round().clamp(-32768.0, 32767.0) as i16
+    }
+}
+
+impl Sample for f32 {
+    const FORMAT: SampleFormat = SampleFormat::F32;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+/// Arrangement of the channels in the order of `WAVEFORMATEXTENSIBLE`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelLayout {
+    Mono,
+    #[default]
+    Stereo,
+    /// Front left, front right, back left and back right.
+    Quad,
+    /// Front left, front right, center, LFE, back left and back right.
+    Surround51,
+    /// [`ChannelLayout::Surround51`] followed by side left and side right.
+    Surround71,
+    /// Channels without speaker positions.
+    Discrete(u16),
+}
+
+impl ChannelLayout {
+    /// The usual layout of `channels` channels.
+    pub const fn from_channels(channels: u16) -> Self {
+        match channels {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            4 => ChannelLayout::Quad,
+            6 => ChannelLayout::Surround51,
+            8 => ChannelLayout::Surround71,
+            _ => ChannelLayout::Discrete(channels),
+        }
+    }
+
+    pub const fn channels(self) -> usize {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Quad => 4,
+            ChannelLayout::Surround51 => 6,
+            ChannelLayout::Surround71 => 8,
+            ChannelLayout::Discrete(channels) => channels as usize,
+        }
+    }
+
+    /// Speaker positions as the `dwChannelMask` of `WAVEFORMATEXTENSIBLE`, which is zero for
+    /// [`ChannelLayout::Discrete`].
+    pub const fn mask(self) -> u32 {
+        match self {
+            ChannelLayout::Mono => 0x4,
+            ChannelLayout::Stereo => 0x3,
+            ChannelLayout::Quad => 0x33,
+            ChannelLayout::Surround51 => 0x3f,
+            ChannelLayout::Surround71 => 0x63f,
+            ChannelLayout::Discrete(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub layout: ChannelLayout,
+    pub sample_format: SampleFormat,
+}
+
+impl AudioFormat {
+    /// Bytes of the samples of all channels at a time.
+    pub const fn block_align(&self) -> usize {
+        self.layout.channels() * self.sample_format.bytes()
+    }
+
+    pub fn to_waveformatex(&self) -> WAVEFORMATEX {
+        let (tag, bytes) = match self.sample_format {
+            SampleFormat::I16 => (WAVE_FORMAT_PCM as u16, 2),
+            SampleFormat::F32 => (WAVE_FORMAT_IEEE_FLOAT, 4),
+        };
+        WAVEFORMATEX {
+            wFormatTag: tag,
+            nChannels: self.layout.channels() as u16,
+            nSamplesPerSec: self.sample_rate,
+            nAvgBytesPerSec: self.sample_rate * self.block_align() as u32,
+            nBlockAlign: self.block_align() as u16,
+            wBitsPerSample: bytes * 8,
+            cbSize: 0,
+        }
+    }
+
+    /// Reads 16-bit integer or 32-bit float PCM. The layout is guessed from the number of channels.
+    pub fn from_waveformatex(raw: &WAVEFORMATEX) -> Result<Self> {
+        let (tag, bits, channels) = (raw.wFormatTag, raw.wBitsPerSample, raw.nChannels);
+        let sample_format = match (tag, bits) {
+            (tag, 16) if tag == WAVE_FORMAT_PCM as u16 => SampleFormat::I16,
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+            _ => {
+                return Err(AviUtlError::Unsupported(format!(
+                    "wave format {} of {} bits",
+                    tag, bits
+                )))
+            }
+        };
+        if channels == 0 {
+            return Err(AviUtlError::Unsupported(
+                "wave format of no channels".into(),
+            ));
+        }
+        Ok(Self {
+            sample_rate: raw.nSamplesPerSec,
+            layout: ChannelLayout::from_channels(channels),
+            sample_format,
+        })
+    }
+}
+
+/// Interleaved samples of all channels.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AudioBuf<S> {
+    samples: Vec<S>,
+    layout: ChannelLayout,
+}
+
+impl<S: Sample> AudioBuf<S> {
+    /// Silence of `frames` samples per channel.
+    pub fn new(layout: ChannelLayout, frames: usize) -> Self {
+        Self {
+            samples: vec![S::default(); frames * layout.channels()],
+            layout,
+        }
+    }
+
+    /// It fails if the length of `samples` is not a multiple of the channels.
+    pub fn from_interleaved(layout: ChannelLayout, samples: Vec<S>) -> Result<Self> {
+        if layout.channels() == 0 || !samples.len().is_multiple_of(layout.channels()) {
+            return Err(AviUtlError::Unsupported(format!(
+                "{} samples in {} channels",
+                samples.len(),
+                layout.channels()
+            )));
+        }
+        Ok(Self { samples, layout })
+    }
+
+    /// Interleaves the channels in `planes`, which must be as many as the channels of `layout` and of the same
+    /// length.
+    pub fn from_planar(layout: ChannelLayout, planes: &[impl AsRef<[S]>]) -> Result<Self> {
+        let frames = planes.first().map_or(0, |plane| plane.as_ref().len());
+        if planes.len() != layout.channels()
+            || planes.iter().any(|plane| plane.as_ref().len() != frames)
+        {
+            return Err(AviUtlError::Unsupported(format!(
+                "{} planes in {} channels",
+                planes.len(),
+                layout.channels()
+            )));
+        }
+        let mut buf = Self::new(layout, frames);
+        for (channel, plane) in planes.iter().enumerate() {
+            buf.channel_mut(channel).copy_from_slice(plane.as_ref());
+        }
+        Ok(buf)
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    pub fn channels(&self) -> usize {
+        self.layout.channels()
+    }
+
+    /// Number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len().checked_div(self.channels()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Changes the number of samples per channel, padding with silence.
+    pub fn resize(&mut self, frames: usize) {
+        self.samples
+            .resize(frames * self.layout.channels(), S::default());
+    }
+
+    /// Changes the layout, padding with silence to keep the number of samples per channel.
+    pub fn set_layout(&mut self, layout: ChannelLayout) {
+        if layout.channels() != self.layout.channels() {
+            let frames = self.frames();
+            self.samples.clear();
+            self.samples
+                .resize(frames * layout.channels(), S::default());
+        }
+        self.layout = layout;
+    }
+
+    pub fn interleaved(&self) -> &[S] {
+        &self.samples
+    }
+
+    pub fn interleaved_mut(&mut self) -> &mut [S] {
+        &mut self.samples
+    }
+
+    pub fn into_interleaved(self) -> Vec<S> {
+        self.samples
+    }
+
+    pub fn channel(&self, channel: usize) -> Channel<'_, S> {
+        Channel::new(&self.samples, self.channels(), channel)
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> ChannelMut<'_, S> {
+        let channels = self.channels();
+        ChannelMut::new(&mut self.samples, channels, channel)
+    }
+
+    /// Samples of each channel.
+    pub fn to_planar(&self) -> Vec<Vec<S>> {
+        (0..self.channels())
+            .map(|channel| self.channel(channel).iter().copied().collect())
+            .collect()
+    }
+
+    pub fn convert<T: Sample>(&self) -> AudioBuf<T> {
+        AudioBuf {
+            samples: self.samples.iter().map(|sample| sample.convert()).collect(),
+            layout: self.layout,
+        }
+    }
+}
+
+/// Samples of a channel in interleaved samples.
+#[derive(Debug, Clone, Copy)]
+pub struct Channel<'a, S> {
+    samples: &'a [S],
+    channels: usize,
+}
+
+impl<'a, S> Channel<'a, S> {
+    /// Views `channel` of `samples` interleaving `channels` channels.
+    pub fn new(samples: &'a [S], channels: usize, channel: usize) -> Self {
+        assert!(channel < channels);
+        Self {
+            samples: &samples[channel.min(samples.len())..],
+            channels,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len().div_ceil(self.channels)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a S> {
+        self.samples.get(index * self.channels)
+    }
+
+    pub fn iter(&self) -> StepBy<std::slice::Iter<'a, S>> {
+        self.samples.iter().step_by(self.channels)
+    }
+}
+
+/// Mutable samples of a channel in interleaved samples.
+#[derive(Debug)]
+pub struct ChannelMut<'a, S> {
+    samples: &'a mut [S],
+    channels: usize,
+}
+
+impl<'a, S> ChannelMut<'a, S> {
+    /// Views `channel` of `samples` interleaving `channels` channels.
+    pub fn new(samples: &'a mut [S], channels: usize, channel: usize) -> Self {
+        assert!(channel < channels);
+        let start = channel.min(samples.len());
+        Self {
+            samples: &mut samples[start..],
+            channels,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len().div_ceil(self.channels)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&S> {
+        self.samples.get(index * self.channels)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut S> {
+        self.samples.get_mut(index * self.channels)
+    }
+
+    pub fn iter(&self) -> StepBy<std::slice::Iter<'_, S>> {
+        self.samples.iter().step_by(self.channels)
+    }
+
+    pub fn iter_mut(&mut self) -> StepBy<std::slice::IterMut<'_, S>> {
+        self.samples.iter_mut().step_by(self.channels)
+    }
+
+    pub fn fill(&mut self, value: S)
+    where
+        S: Copy,
+    {
+        for sample in self.iter_mut() {
+            *sample = value;
+        }
+    }
+
+    /// Copies `src`, which must be as long as the channel.
+    pub fn copy_from_slice(&mut self, src: &[S])
+    where
+        S: Copy,
+    {
+        assert_eq!(self.len(), src.len());
+        for (sample, &value) in self.iter_mut().zip(src) {
+            *sample = value;
+        }
+    }
+}
@@ -0,0 +1,627 @@
+//! Audio analysis for display: min/max/RMS peaks and STFT spectrograms.
+//!
+//! [`PeakTable`] summarizes audio into bins at resolutions halving from a base size, and [`PeakCache`] keeps the
+//! tables of frames read from [`Editing::get_audio`] so that views around the playhead are redrawn quickly.
+//! [`Spectrogram`] holds magnitudes in dBFS. Both can be rendered into an [`OwnedFrame`] of their natural size.
+
+use super::{AudioBuf, Sample};
+use crate::{
+    colorspace::gray,
+    filter::{editing::Editing, Frame, OwnedFrame},
+    AviUtlError, Result, Size,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::PI,
+    ops::RangeInclusive,
+};
+
+/// Summary of the samples in a bin of one channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PeakBin {
+    pub min: f32,
+    pub max: f32,
+    sum_squares: f32,
+    count: u32,
+}
+
+impl PeakBin {
+    fn push(&mut self, sample: f32) {
+        if self.count == 0 {
+            (self.min, self.max) = (sample, sample);
+        } else {
+            self.min = self.min.min(sample);
+            self.max = self.max.max(sample);
+        }
+        self.sum_squares += sample * sample;
+        self.count += 1;
+    }
+
+    /// The bin of the samples of both.
+    pub fn merge(self, other: Self) -> Self {
+        if self.count == 0 {
+            return other;
+        }
+        if other.count == 0 {
+            return self;
+        }
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            sum_squares: self.sum_squares + other.sum_squares,
+            count: self.count + other.count,
+        }
+    }
+
+    /// Number of samples in the bin.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            (self.sum_squares / self.count as f32).sqrt()
+        }
+    }
+}
+
+/// Peaks of every channel, at levels of bins doubling in size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakTable {
+    channels: usize,
+    samples: usize,
+    base: usize,
+    /// Bins of each level, interleaved by channel.
+    levels: Vec<Vec<PeakBin>>,
+}
+
+impl PeakTable {
+    /// Summarizes `audio` into bins of `base` samples per channel and coarser levels, up to a bin for the whole.
+    pub fn new<S: Sample>(audio: &AudioBuf<S>, base: usize) -> Self {
+        let (channels, samples, base) = (audio.channels(), audio.frames(), base.max(1));
+        let mut level = vec![PeakBin::default(); samples.div_ceil(base) * channels];
+        for (i, frame) in audio
+            .interleaved()
+            .chunks_exact(channels.max(1))
+            .enumerate()
+        {
+            for (channel, sample) in frame.iter().enumerate() {
+                level[i / base * channels + channel].push(sample.to_f32());
+            }
+        }
+        let mut levels = vec![level];
+        while levels.last().unwrap().len() > channels {
+            let fine = levels.last().unwrap();
+            let coarse = fine
+                .chunks(2 * channels)
+                .flat_map(|pair| {
+                    (0..channels).map(move |channel| {
+                        pair[channel]
+                            .merge(pair.get(channels + channel).copied().unwrap_or_default())
+                    })
+                })
+                .collect();
+            levels.push(coarse);
+        }
+        Self {
+            channels,
+            samples,
+            base,
+            levels,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Number of samples per channel.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Samples per channel in a bin of `level`.
+    pub fn bin_samples(&self, level: usize) -> usize {
+        self.base << level
+    }
+
+    /// The coarsest level whose bins are not larger than `samples`.
+    pub fn level_for(&self, samples: usize) -> usize {
+        (0..self.levels())
+            .rev()
+            .find(|&level| self.bin_samples(level) <= samples)
+            .unwrap_or(0)
+    }
+
+    /// Bins of `channel` at `level`.
+    pub fn bins(&self, level: usize, channel: usize) -> impl Iterator<Item = PeakBin> + '_ {
+        self.levels[level][channel..]
+            .iter()
+            .step_by(self.channels)
+            .copied()
+    }
+
+    /// Summarizes `channel` into `bins` bins of equal length.
+    pub fn peaks(&self, channel: usize, bins: usize) -> Vec<PeakBin> {
+        let mut peaks = vec![PeakBin::default(); bins];
+        accumulate(&mut peaks, self, channel, 0, self.samples);
+        peaks
+    }
+}
+
+/// Merges the bins of `channel` in `table`, which starts at `offset` of `total` samples, into `peaks` spanning all.
+fn accumulate(
+    peaks: &mut [PeakBin],
+    table: &PeakTable,
+    channel: usize,
+    offset: usize,
+    total: usize,
+) {
+    if peaks.is_empty() || total == 0 || table.channels <= channel {
+        return;
+    }
+    let level = table.level_for(total / peaks.len());
+    let size = table.bin_samples(level);
+    for (i, bin) in table.bins(level, channel).enumerate() {
+        let center = (offset + i * size + size / 2).min(total - 1);
+        let peak = &mut peaks[center * peaks.len() / total];
+        *peak = peak.merge(bin);
+    }
+}
+
+/// [`PeakTable`]s of frames, read from [`Editing::get_audio`] on demand in the layout of the editing file.
+#[derive(Debug, Clone)]
+pub struct PeakCache {
+    base: usize,
+    capacity: usize,
+    tables: HashMap<usize, PeakTable>,
+    /// Cached frames from the least recently used.
+    order: VecDeque<usize>,
+}
+
+impl PeakCache {
+    /// Keeps up to `capacity` frames, summarized in bins of `base` samples.
+    pub fn new(base: usize, capacity: usize) -> Self {
+        Self {
+            base,
+            capacity,
+            tables: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Forgets all frames, such as after the audio is edited.
+    pub fn invalidate(&mut self) {
+        self.tables.clear();
+        self.order.clear();
+    }
+
+    pub fn invalidate_frame(&mut self, frame: usize) {
+        if self.tables.remove(&frame).is_some() {
+            self.order.retain(|&cached| cached != frame);
+        }
+    }
+
+    /// Reads `frame` if not cached, and makes it the most recently used.
+    fn load(&mut self, editing: &Editing, frame: usize, buf: &mut AudioBuf<i16>) -> Result<()> {
+        if !self.tables.contains_key(&frame) {
+            editing.get_audio(frame, buf)?;
+            self.tables.insert(frame, PeakTable::new(buf, self.base));
+        }
+        self.order.retain(|&cached| cached != frame);
+        self.order.push_back(frame);
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        while self.order.len() > self.capacity.max(1) {
+            let frame = self.order.pop_front().unwrap();
+            self.tables.remove(&frame);
+        }
+    }
+
+    /// The table of `frame`, read if not cached.
+    pub fn get(&mut self, editing: &Editing, frame: usize) -> Result<&PeakTable> {
+        let mut buf = AudioBuf::default();
+        self.load(editing, frame, &mut buf)?;
+        self.trim();
+        Ok(&self.tables[&frame])
+    }
+
+    /// Summarizes `channel` of `frames` into `bins` bins of equal length.
+    pub fn peaks(
+        &mut self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+        channel: usize,
+        bins: usize,
+    ) -> Result<Vec<PeakBin>> {
+        let mut buf = AudioBuf::default();
+        for frame in frames.clone() {
+            self.load(editing, frame, &mut buf)?;
+        }
+        let total = frames
+            .clone()
+            .map(|frame| self.tables[&frame].samples())
+            .sum();
+        let mut peaks = vec![PeakBin::default(); bins];
+        let mut offset = 0;
+        for frame in frames {
+            let table = &self.tables[&frame];
+            accumulate(&mut peaks, table, channel, offset, total);
+            offset += table.samples();
+        }
+        self.trim();
+        Ok(peaks)
+    }
+}
+
+/// Renders `peaks` from left to right with the range in gray and RMS in white, in a frame of `height`.
+pub fn render_peaks(peaks: &[PeakBin], height: u32) -> OwnedFrame {
+    let mut frame = OwnedFrame::new(Size {
+        width: peaks.len() as u32,
+        height,
+    });
+    if height == 0 {
+        return frame;
+    }
+    let last = height.saturating_sub(1) as f32;
+    let row = |value: f32| ((1.0 - value.clamp(-1.0, 1.0)) * 0.5 * last).round() as usize;
+    for (x, peak) in peaks.iter().enumerate() {
+        if peak.count() == 0 {
+            continue;
+        }
+        let rms = peak.rms();
+        for y in row(peak.max)..=row(peak.min) {
+            *frame.pixel_mut(x, y) = gray(0.5);
+        }
+        for y in row(rms.min(peak.max))..=row((-rms).max(peak.min)) {
+            *frame.pixel_mut(x, y) = gray(1.0);
+        }
+    }
+    frame
+}
+
+/// Window function applied to each segment of STFT.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    /// Periodic coefficients for a segment of `size`.
+    pub fn coefficients(self, size: usize) -> Vec<f32> {
+        (0..size)
+            .map(|i| {
+                let t = 2.0 * PI * i as f32 / size as f32;
+                match self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => 0.5 - 0.5 * t.cos(),
+                    Window::Hamming => 0.54 - 0.46 * t.cos(),
+                    Window::Blackman => 0.42 - 0.5 * t.cos() + 0.08 * (2.0 * t).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StftSettings {
+    /// Samples in a segment, which must be a power of two.
+    pub size: usize,
+    /// Samples between the starts of segments.
+    pub hop: usize,
+    pub window: Window,
+}
+
+impl Default for StftSettings {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            hop: 256,
+            window: Window::default(),
+        }
+    }
+}
+
+/// In-place radix-2 FFT of a power-of-two length.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                (re[b], im[b]) = (re[a] - tr, im[a] - ti);
+                (re[a], im[a]) = (re[a] + tr, im[a] + ti);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Magnitudes of STFT in dBFS, where a full-scale sine peaks at 0 dB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+    settings: StftSettings,
+    columns: usize,
+    /// Bins of each column from the lowest frequency.
+    magnitudes: Vec<f32>,
+}
+
+impl Spectrogram {
+    /// Analyzes `channel` of `audio`, or the average of all channels if `None`.
+    pub fn new<S: Sample>(
+        audio: &AudioBuf<S>,
+        channel: Option<usize>,
+        settings: StftSettings,
+    ) -> Result<Self> {
+        if !settings.size.is_power_of_two() || settings.size < 2 || settings.hop == 0 {
+            return Err(AviUtlError::Unsupported(format!(
+                "STFT of {} samples by {}",
+                settings.size, settings.hop
+            )));
+        }
+        let channels = audio.channels();
+        if channel.is_some_and(|channel| channel >= channels) {
+            return Err(AviUtlError::Unsupported(format!(
+                "channel {} of {}",
+                channel.unwrap(),
+                channels
+            )));
+        }
+        let signal: Vec<f32> = match channel {
+            Some(channel) => audio.channel(channel).iter().map(|s| s.to_f32()).collect(),
+            None => audio
+                .interleaved()
+                .chunks_exact(channels.max(1))
+                .map(|frame| frame.iter().map(|s| s.to_f32()).sum::<f32>() / channels as f32)
+                .collect(),
+        };
+        let window = settings.window.coefficients(settings.size);
+        let gain = 2.0 / window.iter().sum::<f32>();
+        let bins = settings.size / 2 + 1;
+        let columns = signal.len().div_ceil(settings.hop);
+        let mut magnitudes = Vec::with_capacity(columns * bins);
+        let (mut re, mut im) = (vec![0.0; settings.size], vec![0.0; settings.size]);
+        for column in 0..columns {
+            let start = column * settings.hop;
+            for (i, (re, im)) in re.iter_mut().zip(&mut im).enumerate() {
+                *re = signal.get(start + i).map_or(0.0, |&s| s * window[i]);
+                *im = 0.0;
+            }
+            fft(&mut re, &mut im);
+            magnitudes.extend(
+                re.iter()
+                    .zip(&im)
+                    .take(bins)
+                    .map(|(re, im)| 20.0 * (re.hypot(*im) * gain).max(1e-10).log10()),
+            );
+        }
+        Ok(Self {
+            settings,
+            columns,
+            magnitudes,
+        })
+    }
+
+    pub fn settings(&self) -> StftSettings {
+        self.settings
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Number of frequency bins, from 0 to the Nyquist frequency.
+    pub fn bins(&self) -> usize {
+        self.settings.size / 2 + 1
+    }
+
+    /// Center frequency of `bin` in Hz.
+    pub fn frequency(&self, bin: usize, sample_rate: u32) -> f32 {
+        bin as f32 * sample_rate as f32 / self.settings.size as f32
+    }
+
+    pub fn get(&self, column: usize, bin: usize) -> f32 {
+        self.magnitudes[column * self.bins() + bin]
+    }
+
+    /// Renders magnitudes from `floor` dB to 0 dB as brightness, with time to the right and the lowest frequency at
+    /// the bottom.
+    pub fn render(&self, floor: f32) -> OwnedFrame {
+        let bins = self.bins();
+        let mut frame = OwnedFrame::new(Size {
+            width: self.columns as u32,
+            height: bins as u32,
+        });
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                let db = self.get(x, bins - 1 - y);
+                *pixel = gray((db - floor) / -floor);
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ChannelLayout;
+
+    fn mono(samples: Vec<f32>) -> AudioBuf<f32> {
+        AudioBuf::from_interleaved(ChannelLayout::Mono, samples).unwrap()
+    }
+
+    /// Samples of `frames` per channel of a sine completing `cycles` over `period` samples.
+    fn sine(amplitude: f32, cycles: usize, period: usize, frames: usize) -> AudioBuf<f32> {
+        mono(
+            (0..frames)
+                .map(|i| amplitude * (2.0 * PI * (cycles * i) as f32 / period as f32).sin())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn table_levels_merge_pairs() {
+        let table = PeakTable::new(&mono((0..10).map(|i| i as f32 / 10.0).collect()), 2);
+        assert_eq!(table.levels(), 4);
+        let counts = |level| {
+            table
+                .bins(level, 0)
+                .map(|bin| bin.count())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(counts(0), [2; 5]);
+        assert_eq!(counts(1), [4, 4, 2]);
+        assert_eq!(counts(2), [8, 2]);
+        assert_eq!(counts(3), [10]);
+        let first = table.bins(1, 0).next().unwrap();
+        assert_eq!((first.min, first.max), (0.0, 0.3));
+        let whole = table.bins(3, 0).next().unwrap();
+        assert_eq!((whole.min, whole.max), (0.0, 0.9));
+        assert!((whole.rms() - 0.534).abs() < 1e-3);
+    }
+
+    #[test]
+    fn table_keeps_channels_apart() {
+        let samples = (0..8)
+            .flat_map(|i| [i as f32 / 8.0, -(i as f32) / 8.0])
+            .collect();
+        let audio = AudioBuf::from_interleaved(ChannelLayout::Stereo, samples).unwrap();
+        let table = PeakTable::new(&audio, 4);
+        let last = table.levels() - 1;
+        let left = table.bins(last, 0).next().unwrap();
+        let right = table.bins(last, 1).next().unwrap();
+        assert_eq!((left.min, left.max), (0.0, 0.875));
+        assert_eq!((right.min, right.max), (-0.875, 0.0));
+    }
+
+    #[test]
+    fn level_for_picks_coarsest_fitting() {
+        let table = PeakTable::new(&mono(vec![0.0; 16]), 2);
+        assert_eq!(table.levels(), 4);
+        assert_eq!(table.level_for(1), 0);
+        assert_eq!(table.level_for(2), 0);
+        assert_eq!(table.level_for(7), 1);
+        assert_eq!(table.level_for(8), 2);
+        assert_eq!(table.level_for(1000), 3);
+    }
+
+    #[test]
+    fn accumulate_maps_frames_to_their_bins() {
+        let first = PeakTable::new(&mono(vec![0.25; 8]), 2);
+        let second = PeakTable::new(&mono(vec![-0.5; 8]), 2);
+        for bins in [2, 4] {
+            let mut peaks = vec![PeakBin::default(); bins];
+            accumulate(&mut peaks, &first, 0, 0, 16);
+            accumulate(&mut peaks, &second, 0, 8, 16);
+            for (i, peak) in peaks.iter().enumerate() {
+                let expected = if i < bins / 2 { 0.25 } else { -0.5 };
+                assert_eq!(
+                    (peak.min, peak.max, peak.count()),
+                    (expected, expected, 16 / bins as u32)
+                );
+            }
+        }
+        // Channels out of the table leave the peaks empty.
+        let mut peaks = vec![PeakBin::default(); 2];
+        accumulate(&mut peaks, &first, 1, 0, 8);
+        assert!(peaks.iter().all(|peak| peak.count() == 0));
+    }
+
+    #[test]
+    fn fft_of_cosine_has_two_bins() {
+        let n = 64;
+        let mut re: Vec<f32> = (0..n)
+            .map(|i| (2.0 * PI * (5 * i) as f32 / n as f32).cos())
+            .collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        for k in 0..n {
+            let magnitude = re[k].hypot(im[k]);
+            let expected = if k == 5 || k == n - 5 {
+                n as f32 / 2.0
+            } else {
+                0.0
+            };
+            assert!(
+                (magnitude - expected).abs() < 1e-3,
+                "bin {}: {}",
+                k,
+                magnitude
+            );
+        }
+    }
+
+    #[test]
+    fn spectrogram_of_full_scale_sine_peaks_at_0db() {
+        for window in [Window::Rectangular, Window::Hann] {
+            let settings = StftSettings {
+                size: 256,
+                hop: 256,
+                window,
+            };
+            let full = Spectrogram::new(&sine(1.0, 16, 256, 1024), None, settings).unwrap();
+            assert_eq!((full.columns(), full.bins()), (4, 129));
+            assert!(full.get(0, 16).abs() < 0.01, "{:?}", window);
+            assert!(full.get(0, 64) < -60.0, "{:?}", window);
+            let half = Spectrogram::new(&sine(0.5, 16, 256, 1024), Some(0), settings).unwrap();
+            assert!((half.get(0, 16) + 6.02).abs() < 0.01, "{:?}", window);
+        }
+        let silence =
+            Spectrogram::new(&mono(vec![0.0; 256]), None, StftSettings::default()).unwrap();
+        assert_eq!(silence.get(0, 0), -200.0);
+    }
+
+    #[test]
+    fn spectrogram_rejects_missing_channel() {
+        let audio = AudioBuf::<f32>::new(ChannelLayout::Stereo, 16);
+        assert!(Spectrogram::new(&audio, Some(2), StftSettings::default()).is_err());
+        assert!(Spectrogram::new(&audio, Some(1), StftSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn render_peaks_of_no_height() {
+        let mut peak = PeakBin::default();
+        peak.push(0.5);
+        peak.push(-0.25);
+        let frame = render_peaks(&[peak, PeakBin::default()], 0);
+        assert_eq!(
+            frame.frame_size(),
+            Size {
+                width: 2,
+                height: 0
+            }
+        );
+        let frame = render_peaks(&[peak], 1);
+        assert_eq!(frame.pixel(0, 0), gray(1.0));
+    }
+}
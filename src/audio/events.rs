@@ -0,0 +1,311 @@
+//! Detection of silence and transients in timeline audio, to mark cut candidates.
+//!
+//! [`AudioEventDetector`] measures the RMS of short windows, taking the loudest channel. Runs of windows under a
+//! threshold long enough are silence, and windows rising sharply over the recent average are transients. Events are
+//! located by frame, and [`AudioEventDetector::mark`] puts [`EditFlag::MARK_FRAME`] on their boundaries.
+
+use super::{AudioBuf, ChannelLayout, Sample};
+use crate::{
+    filter::{editing::Editing, EditFlag},
+    Result,
+};
+use std::{collections::VecDeque, ops::RangeInclusive};
+
+/// Windows averaged as the level before a transient.
+const HISTORY_WINDOWS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AudioEvent {
+    /// Frames whose audio stays under the threshold, including the frames where silence starts and ends.
+    Silence {
+        frames: RangeInclusive<usize>,
+    },
+    Transient {
+        frame: usize,
+    },
+}
+
+impl AudioEvent {
+    /// Frames where the audio changes, at both ends of silence.
+    pub fn boundaries(&self) -> Vec<usize> {
+        match self {
+            AudioEvent::Silence { frames } => {
+                if frames.start() == frames.end() {
+                    vec![*frames.start()]
+                } else {
+                    vec![*frames.start(), *frames.end()]
+                }
+            }
+            AudioEvent::Transient { frame } => vec![*frame],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioEventDetector {
+    /// Level in dBFS under which audio is silent.
+    pub silence_threshold: f32,
+    /// Shortest silence to report, in seconds.
+    pub min_silence: f64,
+    /// Rise in dB over the recent level to report as a transient, or `None` not to detect transients.
+    pub transient_rise: Option<f32>,
+    /// Shortest interval between transients, in seconds.
+    pub min_transient_gap: f64,
+    /// Length of the windows to measure, in seconds.
+    pub window: f64,
+}
+
+impl Default for AudioEventDetector {
+    fn default() -> Self {
+        Self {
+            silence_threshold: -50.0,
+            min_silence: 1.0,
+            transient_rise: Some(12.0),
+            min_transient_gap: 0.1,
+            window: 0.01,
+        }
+    }
+}
+
+/// State of a scan over consecutive frames.
+#[derive(Debug)]
+pub struct AudioEventScan<'d> {
+    detector: &'d AudioEventDetector,
+    window: usize,
+    min_silence: u64,
+    min_transient_gap: u64,
+    /// Samples per channel so far.
+    position: u64,
+    /// Start of the current silence by sample and frame.
+    silence: Option<(u64, usize)>,
+    last_frame: Option<usize>,
+    history: VecDeque<f64>,
+    last_transient: Option<u64>,
+    events: Vec<AudioEvent>,
+}
+
+impl AudioEventDetector {
+    /// Starts a scan of audio at `sample_rate`.
+    pub fn start(&self, sample_rate: u32) -> AudioEventScan<'_> {
+        let samples = |seconds: f64| (seconds.max(0.0) * sample_rate as f64).round() as u64;
+        AudioEventScan {
+            detector: self,
+            window: samples(self.window).max(1) as usize,
+            min_silence: samples(self.min_silence),
+            min_transient_gap: samples(self.min_transient_gap),
+            position: 0,
+            silence: None,
+            last_frame: None,
+            history: VecDeque::new(),
+            last_transient: None,
+            events: vec![],
+        }
+    }
+
+    /// Scans the source audio of `frames`.
+    pub fn scan(
+        &self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+    ) -> Result<Vec<AudioEvent>> {
+        let info = editing.get_file_info()?;
+        let mut scan = self.start(info.audio_rate as u32);
+        let mut buf = AudioBuf::new(ChannelLayout::from_channels(info.audio_channels as u16), 0);
+        for frame in frames {
+            editing.get_audio(frame, &mut buf)?;
+            scan.push(frame, &buf);
+        }
+        Ok(scan.finish())
+    }
+
+    /// Puts [`EditFlag::MARK_FRAME`] on the boundaries of `events`, after saving the undo state.
+    pub fn mark(editing: &Editing, events: &[AudioEvent]) -> Result<()> {
+        editing.insert_edit_flag(
+            events.iter().flat_map(AudioEvent::boundaries),
+            EditFlag::MARK_FRAME,
+        )?;
+        Ok(())
+    }
+
+    /// Scans `frames` and marks the events found, which are returned.
+    pub fn scan_and_mark(
+        &self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+    ) -> Result<Vec<AudioEvent>> {
+        let events = self.scan(editing, frames)?;
+        if !events.is_empty() {
+            Self::mark(editing, &events)?;
+        }
+        Ok(events)
+    }
+}
+
+impl AudioEventScan<'_> {
+    /// Analyzes the audio of `frame`, following the previous one.
+    pub fn push<S: Sample>(&mut self, frame: usize, audio: &AudioBuf<S>) {
+        let channels = audio.channels().max(1);
+        for window in audio.interleaved().chunks(self.window * channels) {
+            let mut sums = vec![0.0f64; channels];
+            for (i, sample) in window.iter().enumerate() {
+                sums[i % channels] += (sample.to_f32() as f64).powi(2);
+            }
+            let len = (window.len() / channels).max(1);
+            let power = sums.into_iter().fold(0.0, f64::max) / len as f64;
+            self.push_window(frame, power);
+            self.position += len as u64;
+        }
+        self.last_frame = Some(frame);
+    }
+
+    fn push_window(&mut self, frame: usize, power: f64) {
+        let level = 10.0 * power.log10();
+        if level < self.detector.silence_threshold as f64 {
+            self.silence.get_or_insert((self.position, frame));
+        } else {
+            self.end_silence(frame);
+            if let Some(rise) = self.detector.transient_rise {
+                let full = self.history.len() == HISTORY_WINDOWS;
+                let average = self.history.iter().sum::<f64>() / self.history.len().max(1) as f64;
+                // Rises out of silence are measured from the threshold, as digital silence has no level.
+                let average = (10.0 * average.log10()).max(self.detector.silence_threshold as f64);
+                let apart = self
+                    .last_transient
+                    .is_none_or(|last| self.position - last >= self.min_transient_gap);
+                if full && apart && level - average >= rise as f64 {
+                    self.events.push(AudioEvent::Transient { frame });
+                    self.last_transient = Some(self.position);
+                }
+            }
+        }
+        if self.history.len() == HISTORY_WINDOWS {
+            self.history.pop_front();
+        }
+        self.history.push_back(power);
+    }
+
+    /// Reports the current silence if long enough, which ends at `frame`.
+    fn end_silence(&mut self, frame: usize) {
+        if let Some((start, start_frame)) = self.silence.take() {
+            if self.position - start >= self.min_silence {
+                self.events.push(AudioEvent::Silence {
+                    frames: start_frame..=frame,
+                });
+            }
+        }
+    }
+
+    /// Ends the scan, reporting silence lasting until the last frame.
+    pub fn finish(mut self) -> Vec<AudioEvent> {
+        if let Some(last) = self.last_frame {
+            self.end_silence(last);
+        }
+        self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 1000;
+    /// Windows of the default 10 ms in a frame of 100 ms.
+    const WINDOWS_PER_FRAME: usize = 10;
+    const SILENT: f32 = f32::NEG_INFINITY;
+
+    /// Scans mono audio whose windows are at `levels` in dBFS, grouped into frames.
+    fn scan(detector: &AudioEventDetector, levels: &[f32]) -> Vec<AudioEvent> {
+        let window = (detector.window * SAMPLE_RATE as f64) as usize;
+        let mut scan = detector.start(SAMPLE_RATE);
+        for (frame, levels) in levels.chunks(WINDOWS_PER_FRAME).enumerate() {
+            let samples = levels
+                .iter()
+                .flat_map(|&level| std::iter::repeat_n(10f32.powf(level / 20.0), window))
+                .collect();
+            scan.push(
+                frame,
+                &AudioBuf::from_interleaved(ChannelLayout::Mono, samples).unwrap(),
+            );
+        }
+        scan.finish()
+    }
+
+    /// Windows of `frames` frames at `level`.
+    fn frames(level: f32, frames: usize) -> Vec<f32> {
+        vec![level; frames * WINDOWS_PER_FRAME]
+    }
+
+    fn silence_only() -> AudioEventDetector {
+        AudioEventDetector {
+            transient_rise: None,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn short_silence_is_ignored() {
+        let levels = [frames(-20.0, 5), frames(SILENT, 5), frames(-20.0, 5)].concat();
+        assert!(scan(&silence_only(), &levels).is_empty());
+    }
+
+    #[test]
+    fn long_silence_is_reported() {
+        let levels = [frames(-20.0, 3), frames(-60.0, 12), frames(-20.0, 3)].concat();
+        assert_eq!(
+            scan(&silence_only(), &levels),
+            [AudioEvent::Silence { frames: 3..=15 }]
+        );
+    }
+
+    #[test]
+    fn silence_until_finish_is_reported() {
+        let levels = [frames(-20.0, 3), frames(SILENT, 12)].concat();
+        assert_eq!(
+            scan(&silence_only(), &levels),
+            [AudioEvent::Silence { frames: 3..=14 }]
+        );
+    }
+
+    #[test]
+    fn transient_after_silence_rises_from_threshold() {
+        let detector = AudioEventDetector::default();
+        let loud = [frames(SILENT, 12), frames(-20.0, 1)].concat();
+        assert_eq!(
+            scan(&detector, &loud),
+            [
+                AudioEvent::Silence { frames: 0..=12 },
+                AudioEvent::Transient { frame: 12 }
+            ]
+        );
+        // 5 dB over the threshold is not enough of a rise.
+        let quiet = [frames(SILENT, 12), frames(-45.0, 1)].concat();
+        assert_eq!(
+            scan(&detector, &quiet),
+            [AudioEvent::Silence { frames: 0..=12 }]
+        );
+    }
+
+    #[test]
+    fn transients_keep_apart() {
+        // Bursts at windows 20 and 45, after a full history of quiet windows each.
+        let mut levels = vec![-40.0; 60];
+        levels[20] = -10.0;
+        levels[45] = -10.0;
+        let detector = AudioEventDetector::default();
+        assert_eq!(
+            scan(&detector, &levels),
+            [
+                AudioEvent::Transient { frame: 2 },
+                AudioEvent::Transient { frame: 4 }
+            ]
+        );
+        let detector = AudioEventDetector {
+            min_transient_gap: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            scan(&detector, &levels),
+            [AudioEvent::Transient { frame: 2 }]
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Loudness measurement by ITU-R BS.1770-4 and EBU R128, and normalization to a target loudness.
+//!
+//! [`LoudnessMeter`] K-weights the audio and keeps the mean square of every 100 ms, from which momentary (400 ms),
+//! short-term (3 s), gated integrated loudness and the loudness range of EBU Tech 3342 are derived. True peak is
+//! measured on the audio oversampled 4 times.
+
+use super::{resample::Resampler, AudioBuf, ChannelLayout, Sample};
+use crate::{
+    filter::{editing::Editing, AudioBuffer},
+    AviUtlError, Result,
+};
+use std::{f64::consts::PI, ops::RangeInclusive};
+
+const SUB_BLOCKS_PER_SECOND: u64 = 10;
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+const TRUE_PEAK_OVERSAMPLING: u32 = 4;
+
+/// A second order IIR section in the transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The high shelf and the high pass of the K-weighting, designed for `sample_rate`.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+/// Weights of the channels in the sum, which excludes LFE and boosts the surrounds by 1.5 dB.
+fn channel_weights(layout: ChannelLayout) -> Vec<f64> {
+    const SURROUND: f64 = 1.41;
+    match layout {
+        ChannelLayout::Quad => vec![1.0, 1.0, SURROUND, SURROUND],
+        ChannelLayout::Surround51 => vec![1.0, 1.0, 1.0, 0.0, SURROUND, SURROUND],
+        ChannelLayout::Surround71 => {
+            vec![1.0, 1.0, 1.0, 0.0, SURROUND, SURROUND, SURROUND, SURROUND]
+        }
+        _ => vec![1.0; layout.channels()],
+    }
+}
+
+/// Loudness in LUFS of a weighted mean square, which is negative infinity for silence.
+fn to_lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.log10()
+}
+
+/// Results of [`LoudnessMeter::report`], in LUFS, LU, or dBFS and dBTP for the peaks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    pub integrated: f64,
+    pub loudness_range: f64,
+    pub max_momentary: f64,
+    pub max_short_term: f64,
+    pub sample_peak: f64,
+    pub true_peak: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    layout: ChannelLayout,
+    sample_rate: u32,
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    /// Weighted mean squares of the complete 100 ms sub-blocks.
+    sub_blocks: Vec<f64>,
+    /// Weighted sum of squares and samples of the sub-block in progress.
+    energy: f64,
+    samples: u64,
+    sample_peak: f64,
+    true_peak: f64,
+    oversampler: Resampler,
+}
+
+impl LoudnessMeter {
+    pub fn new(layout: ChannelLayout, sample_rate: u32) -> Result<Self> {
+        if sample_rate < SUB_BLOCKS_PER_SECOND as u32 || layout.channels() == 0 {
+            return Err(AviUtlError::Unsupported(format!(
+                "measuring loudness of {:?} at {} Hz",
+                layout, sample_rate
+            )));
+        }
+        Ok(Self {
+            layout,
+            sample_rate,
+            filters: vec![k_weighting(sample_rate); layout.channels()],
+            weights: channel_weights(layout),
+            sub_blocks: vec![],
+            energy: 0.0,
+            samples: 0,
+            sample_peak: 0.0,
+            true_peak: 0.0,
+            oversampler: Resampler::new(layout, sample_rate, sample_rate * TRUE_PEAK_OVERSAMPLING)?,
+        })
+    }
+
+    /// Measures the audio of `frames` after all filters, by the format of the editing file.
+    pub fn measure(editing: &Editing, frames: RangeInclusive<usize>) -> Result<Loudness> {
+        let info = editing.get_file_info()?;
+        let layout = ChannelLayout::from_channels(info.audio_channels as u16);
+        let mut meter = Self::new(layout, info.audio_rate as u32)?;
+        let mut buf = AudioBuf::new(layout, 0);
+        for frame in frames {
+            editing.get_filtered_audio(frame, &mut buf)?;
+            meter.push(&buf)?;
+        }
+        Ok(meter.report())
+    }
+
+    /// Measures the whole timeline by [`LoudnessMeter::measure`].
+    pub fn measure_timeline(editing: &Editing) -> Result<Loudness> {
+        match editing.total_frames() {
+            0 => Self::new(ChannelLayout::default(), 48000).map(|meter| meter.report()),
+            total => Self::measure(editing, 0..=total - 1),
+        }
+    }
+
+    /// Measures the selected frames by [`LoudnessMeter::measure`].
+    pub fn measure_selection(editing: &Editing) -> Result<Loudness> {
+        Self::measure(editing, editing.selected_frame_range()?)
+    }
+
+    /// Clears the measurement.
+    pub fn reset(&mut self) {
+        self.filters = vec![k_weighting(self.sample_rate); self.layout.channels()];
+        self.sub_blocks.clear();
+        self.energy = 0.0;
+        self.samples = 0;
+        self.sample_peak = 0.0;
+        self.true_peak = 0.0;
+        self.oversampler.reset();
+    }
+
+    pub fn push<S: Sample>(&mut self, audio: &AudioBuf<S>) -> Result<()> {
+        if audio.layout() != self.layout {
+            return Err(AviUtlError::Unsupported(format!(
+                "measuring {:?} by a meter for {:?}",
+                audio.layout(),
+                self.layout
+            )));
+        }
+        let rate = self.sample_rate as u64;
+        for frame in audio.interleaved().chunks_exact(self.layout.channels()) {
+            for ((sample, [shelf, high_pass]), weight) in
+                frame.iter().zip(&mut self.filters).zip(&self.weights)
+            {
+                let x = sample.to_f32() as f64;
+                self.sample_peak = self.sample_peak.max(x.abs());
+                let y = high_pass.process(shelf.process(x));
+                self.energy += weight * y * y;
+            }
+            self.samples += 1;
+            // Sub-blocks end at exact multiples of 100 ms, rounded down to samples.
+            let index = self.sub_blocks.len() as u64;
+            let length =
+                (index + 1) * rate / SUB_BLOCKS_PER_SECOND - index * rate / SUB_BLOCKS_PER_SECOND;
+            if self.samples == length {
+                self.sub_blocks.push(self.energy / length as f64);
+                self.energy = 0.0;
+                self.samples = 0;
+            }
+        }
+        let oversampled = self.oversampler.process(&audio.convert::<f32>())?;
+        self.update_true_peak(&oversampled);
+        Ok(())
+    }
+
+    fn update_true_peak(&mut self, oversampled: &AudioBuf<f32>) {
+        for sample in oversampled.interleaved() {
+            self.true_peak = self.true_peak.max(sample.abs() as f64);
+        }
+    }
+
+    /// Loudness of the last `len` sub-blocks, or negative infinity until enough audio.
+    fn window(&self, len: usize) -> f64 {
+        match self.sub_blocks.len().checked_sub(len) {
+            Some(start) => to_lufs(self.sub_blocks[start..].iter().sum::<f64>() / len as f64),
+            None => f64::NEG_INFINITY,
+        }
+    }
+
+    /// Loudness of every window of `len` sub-blocks, advancing by a sub-block.
+    fn windows(&self, len: usize) -> impl Iterator<Item = f64> + '_ {
+        self.sub_blocks
+            .windows(len)
+            .map(move |window| window.iter().sum::<f64>() / len as f64)
+    }
+
+    /// Loudness of the last 400 ms.
+    pub fn momentary(&self) -> f64 {
+        self.window(MOMENTARY_SUB_BLOCKS)
+    }
+
+    /// Loudness of the last 3 s.
+    pub fn short_term(&self) -> f64 {
+        self.window(SHORT_TERM_SUB_BLOCKS)
+    }
+
+    /// Gated loudness of the whole audio so far.
+    pub fn integrated(&self) -> f64 {
+        let absolute: Vec<f64> = self
+            .windows(MOMENTARY_SUB_BLOCKS)
+            .filter(|&block| to_lufs(block) > ABSOLUTE_GATE)
+            .collect();
+        if absolute.is_empty() {
+            return f64::NEG_INFINITY;
+        }
+        let threshold =
+            to_lufs(absolute.iter().sum::<f64>() / absolute.len() as f64) + RELATIVE_GATE;
+        let relative: Vec<f64> = absolute
+            .into_iter()
+            .filter(|&block| to_lufs(block) > threshold)
+            .collect();
+        if relative.is_empty() {
+            return f64::NEG_INFINITY;
+        }
+        to_lufs(relative.iter().sum::<f64>() / relative.len() as f64)
+    }
+
+    /// Spread between the 10th and 95th percentiles of the gated short-term loudness, in LU.
+    pub fn loudness_range(&self) -> f64 {
+        let absolute: Vec<f64> = self
+            .windows(SHORT_TERM_SUB_BLOCKS)
+            .filter(|&block| to_lufs(block) > ABSOLUTE_GATE)
+            .collect();
+        if absolute.is_empty() {
+            return 0.0;
+        }
+        let threshold =
+            to_lufs(absolute.iter().sum::<f64>() / absolute.len() as f64) + RANGE_RELATIVE_GATE;
+        let mut relative: Vec<f64> = absolute
+            .into_iter()
+            .map(to_lufs)
+            .filter(|&loudness| loudness > threshold)
+            .collect();
+        if relative.is_empty() {
+            return 0.0;
+        }
+        relative.sort_by(f64::total_cmp);
+        let percentile = |p: f64| relative[((relative.len() - 1) as f64 * p).round() as usize];
+        percentile(0.95) - percentile(0.10)
+    }
+
+    pub fn sample_peak(&self) -> f64 {
+        to_db(self.sample_peak)
+    }
+
+    /// Peak of the audio oversampled 4 times, including the samples pending in the filter.
+    pub fn true_peak(&self) -> f64 {
+        let mut oversampler = self.oversampler.clone();
+        let peak = oversampler.flush::<f32>().map_or(0.0, |tail| {
+            tail.interleaved()
+                .iter()
+                .fold(0.0f64, |peak, sample| peak.max(sample.abs() as f64))
+        });
+        to_db(self.true_peak.max(peak).max(self.sample_peak))
+    }
+
+    pub fn report(&self) -> Loudness {
+        Loudness {
+            integrated: self.integrated(),
+            loudness_range: self.loudness_range(),
+            max_momentary: self
+                .windows(MOMENTARY_SUB_BLOCKS)
+                .map(to_lufs)
+                .fold(f64::NEG_INFINITY, f64::max),
+            max_short_term: self
+                .windows(SHORT_TERM_SUB_BLOCKS)
+                .map(to_lufs)
+                .fold(f64::NEG_INFINITY, f64::max),
+            sample_peak: self.sample_peak(),
+            true_peak: self.true_peak(),
+        }
+    }
+}
+
+/// A constant gain reaching a target loudness, for an audio filter after measuring.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessNormalizer {
+    /// Gain in dB.
+    pub gain: f64,
+}
+
+impl LoudnessNormalizer {
+    /// The gain bringing `loudness` to `target` LUFS, reduced so that the true peak stays under `ceiling` dBTP if
+    /// given. Silence gets no gain.
+    pub fn new(loudness: &Loudness, target: f64, ceiling: Option<f64>) -> Self {
+        if !loudness.integrated.is_finite() {
+            return Self { gain: 0.0 };
+        }
+        let mut gain = target - loudness.integrated;
+        if let Some(ceiling) = ceiling.filter(|_| loudness.true_peak.is_finite()) {
+            gain = gain.min(ceiling - loudness.true_peak);
+        }
+        Self { gain }
+    }
+
+    fn factor(&self) -> f32 {
+        10f64.powf(self.gain / 20.0) as f32
+    }
+
+    /// Applies the gain to the samples of a filter, saturating them.
+    pub fn apply(&self, buffer: &mut AudioBuffer) {
+        let factor = self.factor();
+        for sample in buffer.interleaved_mut() {
+            *sample = i16::from_f32(sample.to_f32() * factor);
+        }
+    }
+
+    pub fn apply_buf<S: Sample>(&self, audio: &mut AudioBuf<S>) {
+        let factor = self.factor();
+        for sample in audio.interleaved_mut() {
+            *sample = S::from_f32(sample.to_f32() * factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn sine(
+        layout: ChannelLayout,
+        rate: u32,
+        frequency: f64,
+        dbfs: f64,
+        seconds: f64,
+    ) -> AudioBuf<f32> {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        let frames = (rate as f64 * seconds) as usize;
+        let samples = (0..frames)
+            .flat_map(|i| {
+                let value = amplitude * (2.0 * PI * frequency * i as f64 / rate as f64).sin();
+                std::iter::repeat_n(value as f32, layout.channels())
+            })
+            .collect();
+        AudioBuf::from_interleaved(layout, samples).unwrap()
+    }
+
+    #[test]
+    fn stereo_sine_at_1khz() {
+        // EBU Tech 3341 expects a stereo 1 kHz sine to measure as loud in LUFS as its peak level in dBFS, since the
+        // -0.691 dB of BS.1770 cancels the gain of the K-weighting at 1 kHz.
+        for rate in [44100, 48000] {
+            let mut meter = LoudnessMeter::new(ChannelLayout::Stereo, rate).unwrap();
+            meter
+                .push(&sine(ChannelLayout::Stereo, rate, 1000.0, -18.0, 4.0))
+                .unwrap();
+            let report = meter.report();
+            assert!((report.integrated + 18.0).abs() < 0.1, "{:?}", report);
+            assert!((report.max_momentary + 18.0).abs() < 0.1, "{:?}", report);
+            assert!(report.loudness_range < 0.1, "{:?}", report);
+            assert!((report.sample_peak + 18.0).abs() < 0.01, "{:?}", report);
+            assert!(report.true_peak >= report.sample_peak, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn mono_sine_is_3db_quieter() {
+        let mut meter = LoudnessMeter::new(ChannelLayout::Mono, 48000).unwrap();
+        meter
+            .push(&sine(ChannelLayout::Mono, 48000, 1000.0, -18.0, 2.0))
+            .unwrap();
+        assert!((meter.integrated() + 21.01).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence_is_gated() {
+        let mut meter = LoudnessMeter::new(ChannelLayout::Stereo, 48000).unwrap();
+        meter
+            .push(&AudioBuf::<i16>::new(ChannelLayout::Stereo, 48000 * 2))
+            .unwrap();
+        let report = meter.report();
+        for value in [
+            report.integrated,
+            report.loudness_range,
+            report.max_momentary,
+            report.max_short_term,
+            report.sample_peak,
+            report.true_peak,
+        ] {
+            assert!(!value.is_nan(), "{:?}", report);
+        }
+        assert_eq!(report.integrated, f64::NEG_INFINITY);
+        assert_eq!(report.loudness_range, 0.0);
+        assert_eq!(
+            LoudnessNormalizer::new(&report, -23.0, Some(-1.0)).gain,
+            0.0
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Mixing channels into another [`ChannelLayout`] by a matrix of gains.
+
+use super::{AudioBuf, ChannelLayout, Sample};
+use crate::{AviUtlError, Result};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Gains from each input channel to each output channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemixMatrix {
+    input: ChannelLayout,
+    output: ChannelLayout,
+    /// Rows of output channels, each of which has the gains of the input channels.
+    gains: Vec<f32>,
+}
+
+impl RemixMatrix {
+    /// `gains` has a row of the input channels for every output channel.
+    pub fn new(input: ChannelLayout, output: ChannelLayout, gains: Vec<f32>) -> Result<Self> {
+        if gains.len() != input.channels() * output.channels() {
+            return Err(AviUtlError::Unsupported(format!(
+                "{} gains for {} to {} channels",
+                gains.len(),
+                input.channels(),
+                output.channels()
+            )));
+        }
+        Ok(Self {
+            input,
+            output,
+            gains,
+        })
+    }
+
+    pub fn identity(layout: ChannelLayout) -> Self {
+        let channels = layout.channels();
+        Self {
+            input: layout,
+            output: layout,
+            gains: (0..channels * channels)
+                .map(|i| {
+                    if i / channels == i % channels {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// The conventional downmix or upmix between mono, stereo and 5.1 following ITU-R BS.775. The center and the
+    /// surrounds are mixed down at -3 dB and LFE is dropped, while upmixing only places the channels at the front.
+    pub fn standard(input: ChannelLayout, output: ChannelLayout) -> Result<Self> {
+        use ChannelLayout::*;
+        const H: f32 = FRAC_1_SQRT_2;
+        #[rustfmt::skip]
+        let gains = match (input, output) {
+            _ if input == output => return Ok(Self::identity(input)),
+            (Mono, Stereo) => vec![
+                1.0,
+                1.0,
+            ],
+            (Stereo, Mono) => vec![
+                0.5, 0.5,
+            ],
+            (Mono, Surround51) => vec![
+                0.0,
+                0.0,
+                1.0,
+                0.0,
+                0.0,
+                0.0,
+            ],
+            (Stereo, Surround51) => vec![
+                1.0, 0.0,
+                0.0, 1.0,
+                0.0, 0.0,
+                0.0, 0.0,
+                0.0, 0.0,
+                0.0, 0.0,
+            ],
+            (Surround51, Stereo) => vec![
+                1.0, 0.0, H, 0.0, H, 0.0,
+                0.0, 1.0, H, 0.0, 0.0, H,
+            ],
+            (Surround51, Mono) => vec![
+                0.5, 0.5, H, 0.0, 0.5 * H, 0.5 * H,
+            ],
+            _ => {
+                return Err(AviUtlError::Unsupported(format!(
+                    "remixing {:?} into {:?}",
+                    input, output
+                )))
+            }
+        };
+        Self::new(input, output, gains)
+    }
+
+    pub fn input(&self) -> ChannelLayout {
+        self.input
+    }
+
+    pub fn output(&self) -> ChannelLayout {
+        self.output
+    }
+
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        self.gains[output * self.input.channels() + input]
+    }
+
+    pub fn set_gain(&mut self, output: usize, input: usize, gain: f32) {
+        let channels = self.input.channels();
+        self.gains[output * channels + input] = gain;
+    }
+
+    /// Scales the rows whose gains sum over 1 in magnitude down to 1, so that the output never clips.
+    pub fn normalized(mut self) -> Self {
+        let channels = self.input.channels();
+        for row in self.gains.chunks_exact_mut(channels.max(1)) {
+            let sum: f32 = row.iter().map(|gain| gain.abs()).sum();
+            if sum > 1.0 {
+                row.iter_mut().for_each(|gain| *gain /= sum);
+            }
+        }
+        self
+    }
+
+    /// Mixes `input` of [`RemixMatrix::input`] into a buffer of [`RemixMatrix::output`].
+    pub fn apply<S: Sample>(&self, input: &AudioBuf<S>) -> Result<AudioBuf<S>> {
+        if input.layout() != self.input {
+            return Err(AviUtlError::Unsupported(format!(
+                "remixing {:?} by a matrix for {:?}",
+                input.layout(),
+                self.input
+            )));
+        }
+        let (in_channels, out_channels) = (self.input.channels(), self.output.channels());
+        let mut output = AudioBuf::new(self.output, input.frames());
+        if in_channels == 0 || out_channels == 0 {
+            return Ok(output);
+        }
+        for (src, dst) in input
+            .interleaved()
+            .chunks_exact(in_channels)
+            .zip(output.interleaved_mut().chunks_exact_mut(out_channels))
+        {
+            for (sample, row) in dst.iter_mut().zip(self.gains.chunks_exact(in_channels)) {
+                let mixed: f32 = src
+                    .iter()
+                    .zip(row)
+                    .map(|(sample, gain)| sample.to_f32() * gain)
+                    .sum();
+                *sample = S::from_f32(mixed);
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_to_mono() {
+        let matrix = RemixMatrix::standard(ChannelLayout::Stereo, ChannelLayout::Mono).unwrap();
+        assert_eq!((matrix.gain(0, 0), matrix.gain(0, 1)), (0.5, 0.5));
+        let input = AudioBuf::from_interleaved(ChannelLayout::Stereo, vec![0.5f32, -0.5, 1.0, 0.0]);
+        let output = matrix.apply(&input.unwrap()).unwrap();
+        assert_eq!(output.layout(), ChannelLayout::Mono);
+        assert_eq!(output.interleaved(), [0.0, 0.5]);
+    }
+
+    #[test]
+    fn surround51_to_stereo() {
+        let matrix =
+            RemixMatrix::standard(ChannelLayout::Surround51, ChannelLayout::Stereo).unwrap();
+        // Channels in the order of L, R, C, LFE, Ls and Rs.
+        let expected = [
+            [1.0, 0.0, FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2, 0.0],
+            [0.0, 1.0, FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2],
+        ];
+        for (output, row) in expected.iter().enumerate() {
+            for (input, &gain) in row.iter().enumerate() {
+                assert_eq!(
+                    matrix.gain(output, input),
+                    gain,
+                    "{} from {}",
+                    output,
+                    input
+                );
+            }
+        }
+        let normalized = matrix.normalized();
+        let sum: f32 = (0..6).map(|input| normalized.gain(0, input).abs()).sum();
+        assert!((sum - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn same_layout_is_identity() {
+        let matrix = RemixMatrix::standard(ChannelLayout::Quad, ChannelLayout::Quad).unwrap();
+        assert_eq!(matrix, RemixMatrix::identity(ChannelLayout::Quad));
+        assert!(RemixMatrix::standard(ChannelLayout::Quad, ChannelLayout::Stereo).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Band-limited sample-rate conversion by a rational ratio.
+//!
+//! [`Resampler`] interpolates by a polyphase filter of Kaiser-windowed sinc, with the cutoff at the lower Nyquist
+//! frequency of the two rates. It keeps the input it still needs between calls, so audio can be converted in chunks
+//! of any length, and the output is aligned to the input without delay.
+
+use super::{AudioBuf, ChannelLayout, Sample};
+use crate::{AviUtlError, Result};
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc on each side at the cutoff, which sets the steepness of the filter.
+const ZERO_CROSSINGS: usize = 16;
+/// Phases up to this many are tabulated, and the others are computed for every sample.
+const MAX_TABLE_PHASES: usize = 4096;
+const KAISER_BETA: f64 = 8.6;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Modified Bessel function of the first kind of order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let (mut sum, mut term) = (1.0, 1.0);
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    layout: ChannelLayout,
+    input_rate: u32,
+    output_rate: u32,
+    /// Output samples per `down` input samples.
+    up: u64,
+    down: u64,
+    /// Taps on each side of the center.
+    half: usize,
+    cutoff: f64,
+    /// Taps of each phase, if tabulated.
+    table: Option<Vec<f32>>,
+    /// Pending input, which starts `half` samples before the input not yet passed by the output.
+    history: Vec<f32>,
+    /// Position of the next output from the start of `history`, in `1 / up` samples.
+    position: u64,
+    /// Input samples per channel so far.
+    consumed: u64,
+    /// Output samples per channel so far.
+    produced: u64,
+}
+
+impl Resampler {
+    pub fn new(layout: ChannelLayout, input_rate: u32, output_rate: u32) -> Result<Self> {
+        if input_rate == 0 || output_rate == 0 || layout.channels() == 0 {
+            return Err(AviUtlError::Unsupported(format!(
+                "resampling {:?} of {} Hz into {} Hz",
+                layout, input_rate, output_rate
+            )));
+        }
+        let divisor = gcd(input_rate as u64, output_rate as u64);
+        let (up, down) = (output_rate as u64 / divisor, input_rate as u64 / divisor);
+        let cutoff = (up as f64 / down as f64).min(1.0);
+        let half = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let mut resampler = Self {
+            layout,
+            input_rate,
+            output_rate,
+            up,
+            down,
+            half,
+            cutoff,
+            table: None,
+            history: vec![],
+            position: 0,
+            consumed: 0,
+            produced: 0,
+        };
+        if up as usize <= MAX_TABLE_PHASES {
+            let mut table = vec![0.0; up as usize * 2 * half];
+            for (phase, taps) in table.chunks_exact_mut(2 * half).enumerate() {
+                resampler.compute_taps(phase as u64, taps);
+            }
+            resampler.table = Some(table);
+        }
+        resampler.reset();
+        Ok(resampler)
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Discards the pending input, as if nothing had been processed.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize(self.half * self.layout.channels(), 0.0);
+        self.position = self.half as u64 * self.up;
+        self.consumed = 0;
+        self.produced = 0;
+    }
+
+    /// Number of output samples per channel for `frames` input samples per channel.
+    pub fn output_frames(&self, frames: usize) -> usize {
+        (frames as u64 * self.up).div_ceil(self.down) as usize
+    }
+
+    /// Taps for the output at `phase / up` after an input sample, normalized to unity gain.
+    fn compute_taps(&self, phase: u64, taps: &mut [f32]) {
+        let offset = phase as f64 / self.up as f64;
+        let mut sum = 0.0;
+        for (j, tap) in taps.iter_mut().enumerate() {
+            // Distance from the output to the input sample of the tap.
+            let x = self.half as f64 - 1.0 - j as f64 + offset;
+            let t = x / self.half as f64;
+            let window = if t.abs() < 1.0 {
+                bessel_i0(KAISER_BETA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_BETA)
+            } else {
+                0.0
+            };
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * self.cutoff * x).sin() / (PI * self.cutoff * x)
+            };
+            *tap = (sinc * window) as f32;
+            sum += sinc * window;
+        }
+        for tap in taps {
+            *tap = (*tap as f64 / sum) as f32;
+        }
+    }
+
+    /// Converts the samples available so far, while keeping the input the next output needs.
+    fn drain(&mut self, output: &mut Vec<f32>, limit: u64) {
+        let channels = self.layout.channels();
+        let taps_len = 2 * self.half;
+        let mut scratch = vec![0.0; taps_len];
+        let available = self.history.len().checked_div(channels).unwrap_or(0);
+        while self.produced < limit {
+            let center = (self.position / self.up) as usize;
+            if center + self.half >= available {
+                break;
+            }
+            let phase = self.position % self.up;
+            let taps = match &self.table {
+                Some(table) => &table[phase as usize * taps_len..][..taps_len],
+                None => {
+                    self.compute_taps(phase, &mut scratch);
+                    &scratch
+                }
+            };
+            let start = (center + 1 - self.half) * channels;
+            for channel in 0..channels {
+                let sum: f32 = taps
+                    .iter()
+                    .zip(self.history[start + channel..].iter().step_by(channels))
+                    .map(|(tap, sample)| tap * sample)
+                    .sum();
+                output.push(sum);
+            }
+            self.position += self.down;
+            self.produced += 1;
+        }
+        let keep_from = ((self.position / self.up) as usize + 1)
+            .saturating_sub(self.half)
+            .min(available);
+        self.history.drain(..keep_from * channels);
+        self.position -= keep_from as u64 * self.up;
+    }
+
+    /// Converts `input` continuing from the previous call. The output may lag by the filter length until
+    /// [`Resampler::flush`].
+    pub fn process<S: Sample>(&mut self, input: &AudioBuf<S>) -> Result<AudioBuf<S>> {
+        if input.layout() != self.layout {
+            return Err(AviUtlError::Unsupported(format!(
+                "resampling {:?} by a resampler for {:?}",
+                input.layout(),
+                self.layout
+            )));
+        }
+        self.history
+            .extend(input.interleaved().iter().map(|sample| sample.to_f32()));
+        self.consumed += input.frames() as u64;
+        let mut output = vec![];
+        self.drain(&mut output, u64::MAX);
+        self.to_buf(output)
+    }
+
+    /// Returns the rest of the output for the input so far, and resets the state.
+    pub fn flush<S: Sample>(&mut self) -> Result<AudioBuf<S>> {
+        let limit = (self.consumed * self.up).div_ceil(self.down);
+        self.history.resize(
+            self.history.len() + (self.half + 1) * self.layout.channels(),
+            0.0,
+        );
+        let mut output = vec![];
+        self.drain(&mut output, limit);
+        self.reset();
+        self.to_buf(output)
+    }
+
+    fn to_buf<S: Sample>(&self, output: Vec<f32>) -> Result<AudioBuf<S>> {
+        AudioBuf::from_interleaved(self.layout, output.into_iter().map(S::from_f32).collect())
+    }
+}
+
+/// Converts the whole `input` from `input_rate` into `output_rate`.
+pub fn resample<S: Sample>(
+    input: &AudioBuf<S>,
+    input_rate: u32,
+    output_rate: u32,
+) -> Result<AudioBuf<S>> {
+    let mut resampler = Resampler::new(input.layout(), input_rate, output_rate)?;
+    let mut output = resampler.process(input)?.into_interleaved();
+    output.extend(resampler.flush::<S>()?.into_interleaved());
+    AudioBuf::from_interleaved(input.layout(), output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATES: [(u32, u32); 5] = [
+        (48000, 44100),
+        (44100, 48000),
+        (32000, 48000),
+        (48000, 8000),
+        (44100, 44100),
+    ];
+
+    #[test]
+    fn dc_gain_is_unity() {
+        for (input_rate, output_rate) in RATES {
+            let input = AudioBuf::from_interleaved(ChannelLayout::Stereo, vec![0.5f32; 2 * 4800]);
+            let mut resampler =
+                Resampler::new(ChannelLayout::Stereo, input_rate, output_rate).unwrap();
+            let mut output = resampler
+                .process(&input.unwrap())
+                .unwrap()
+                .into_interleaved();
+            output.extend(resampler.flush::<f32>().unwrap().into_interleaved());
+            // The edges ring against the silence outside the input, so only the middle is steady.
+            let frames = output.len() / 2;
+            for &sample in &output[frames / 4 * 2..frames * 3 / 4 * 2] {
+                assert!(
+                    (sample - 0.5).abs() < 1e-3,
+                    "{} from {} Hz into {} Hz",
+                    sample,
+                    input_rate,
+                    output_rate
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        for (input_rate, output_rate) in RATES {
+            for frames in [0, 1, 999, 4800] {
+                let input = AudioBuf::new(ChannelLayout::Mono, frames);
+                let output: AudioBuf<f32> = resample(&input, input_rate, output_rate).unwrap();
+                let expected = (frames as u64 * output_rate as u64).div_ceil(input_rate as u64);
+                assert_eq!(output.frames() as u64, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_match_whole() {
+        let samples: Vec<f32> = (0..3000).map(|i| (i as f32 * 0.05).sin()).collect();
+        let input = AudioBuf::from_interleaved(ChannelLayout::Mono, samples.clone()).unwrap();
+        let whole = resample(&input, 44100, 48000).unwrap();
+        let mut resampler = Resampler::new(ChannelLayout::Mono, 44100, 48000).unwrap();
+        let mut chunked = vec![];
+        for chunk in samples.chunks(777) {
+            let chunk = AudioBuf::from_interleaved(ChannelLayout::Mono, chunk.to_vec()).unwrap();
+            chunked.extend(resampler.process(&chunk).unwrap().into_interleaved());
+        }
+        chunked.extend(resampler.flush::<f32>().unwrap().into_interleaved());
+        assert_eq!(chunked, whole.interleaved());
+    }
+}
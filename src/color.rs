@@ -1,4 +1,7 @@
-use crate::{AviUtlError, MultiThreadFn, PixelFormat, Result, Size};
+use crate::{
+    executor::{execute_raw, Executor, Job},
+    AviUtlError, MultiThreadFn, PixelFormat, Result, Size,
+};
 use aviutl_plugin_sys::{color::ColorProcInfo, MultiThreadFunc, PixelYc};
 use std::{
     marker::PhantomData,
@@ -58,6 +61,12 @@ impl<'a> ProcInfo<'a> {
     }
 }
 
+impl Executor for ProcInfo<'_> {
+    fn execute(&self, job: &Job) -> Result<()> {
+        execute_raw(self.exec_multi_thread_func, job)
+    }
+}
+
 pub mod prelude {
     pub use super::{ColorPlugin, ProcInfo};
     pub use crate::{export_color_plugin, AviUtlError, PixelYc, Result};
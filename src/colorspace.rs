@@ -0,0 +1,310 @@
+//! Host-independent conversions between [`PixelYc`] (YC48) and RGB.
+//!
+//! AviUtl converts RGB into YC48 by ITU-R BT.601 in full range, where RGB 0-255 spans luma 0-4096 and chroma
+//! -2048-2048. [`ColorSpace::AVIUTL`] follows it and agrees with [`Api::rgb_to_yc`] and [`Api::yc_to_rgb`] within
+//! ±1 of rounding, so this module can be used in color, input and output plugins where no [`Api`] is available.
+//!
+//! [`Api`]: crate::filter::api::Api
+//! [`Api::rgb_to_yc`]: crate::filter::api::Api::rgb_to_yc
+//! [`Api::yc_to_rgb`]: crate::filter::api::Api::yc_to_rgb
+
+use crate::{filter::Frame, PixelRgb, PixelYc};
+
+/// A matrix of luma coefficients.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Matrix {
+    /// ITU-R BT.601, used by AviUtl.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709.
+    Bt709,
+    /// ITU-R BT.2020 with non-constant luminance.
+    Bt2020,
+}
+
+impl Matrix {
+    /// Returns the coefficients `(Kr, Kb)`.
+    pub const fn coefficients(self) -> (f32, f32) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+            Matrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// A quantization range of 8-bit RGB and YCbCr.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Range {
+    /// 0-255 for all components.
+    #[default]
+    Full,
+    /// 16-235 for RGB and luma, 16-240 for chroma.
+    Limited,
+}
+
+impl Range {
+    fn encode_luma(self, value: f32) -> u8 {
+        let value = match self {
+            Range::Full => value * 255.0,
+            Range::Limited => 16.0 + value * 219.0,
+        };
+        value.round().clamp(0.0, 255.0) as u8
+    }
+
+    fn decode_luma(self, value: u8) -> f32 {
+        match self {
+            Range::Full => value as f32 / 255.0,
+            Range::Limited => (value as f32 - 16.0) / 219.0,
+        }
+    }
+
+    fn encode_chroma(self, value: f32) -> u8 {
+        let value = match self {
+            Range::Full => 128.0 + value * 255.0,
+            Range::Limited => 128.0 + value * 224.0,
+        };
+        value.round().clamp(0.0, 255.0) as u8
+    }
+
+    fn decode_chroma(self, value: u8) -> f32 {
+        match self {
+            Range::Full => (value as f32 - 128.0) / 255.0,
+            Range::Limited => (value as f32 - 128.0) / 224.0,
+        }
+    }
+}
+
+/// 8-bit YCbCr, as packed in YUY2 or similar formats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ycbcr8 {
+    pub y: u8,
+    pub cb: u8,
+    pub cr: u8,
+}
+
+/// Normalizes YC48 into luma `0.0..=1.0` and chroma `-0.5..=0.5`, without clamping.
+pub fn normalize(yc: PixelYc) -> [f32; 3] {
+    let scale = PixelYc::Y_MAX as f32;
+    [
+        yc.y as f32 / scale,
+        yc.cb as f32 / scale,
+        yc.cr as f32 / scale,
+    ]
+}
+
+/// Inverse of [`normalize`], clamping the components into the documented ranges.
+pub fn denormalize([y, cb, cr]: [f32; 3]) -> PixelYc {
+    let scale = PixelYc::Y_MAX as f32;
+    let quantize = |value: f32, min: i16, max: i16| {
+        (value * scale).round().clamp(min as f32, max as f32) as i16
+    };
+    PixelYc {
+        y: quantize(y, PixelYc::Y_MIN, PixelYc::Y_MAX),
+        cb: quantize(cb, PixelYc::C_MIN, PixelYc::C_MAX),
+        cr: quantize(cr, PixelYc::C_MIN, PixelYc::C_MAX),
+    }
+}
+
+/// Neutral gray of luma `level` between 0.0 and 1.0, clamped.
+pub fn gray(level: f32) -> PixelYc {
+    PixelYc {
+        y: (level.clamp(0.0, 1.0) * PixelYc::Y_MAX as f32).round() as i16,
+        cb: 0,
+        cr: 0,
+    }
+}
+
+/// A pair of matrix and range for conversions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColorSpace {
+    pub matrix: Matrix,
+    pub range: Range,
+}
+
+impl ColorSpace {
+    /// The conversion of AviUtl, BT.601 in full range.
+    pub const AVIUTL: Self = Self {
+        matrix: Matrix::Bt601,
+        range: Range::Full,
+    };
+
+    pub const fn new(matrix: Matrix, range: Range) -> Self {
+        Self { matrix, range }
+    }
+
+    /// Converts normalized YCbCr as [`normalize`] into normalized RGB `[r, g, b]`, without clamping.
+    pub fn ycbcr_f32_to_rgb_f32(&self, [y, cb, cr]: [f32; 3]) -> [f32; 3] {
+        let (kr, kb) = self.matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+        let r = y + 2.0 * (1.0 - kr) * cr;
+        let b = y + 2.0 * (1.0 - kb) * cb;
+        let g = (y - kr * r - kb * b) / kg;
+        [r, g, b]
+    }
+
+    /// Converts normalized RGB `[r, g, b]` into normalized YCbCr as [`normalize`], without clamping.
+    pub fn rgb_f32_to_ycbcr_f32(&self, [r, g, b]: [f32; 3]) -> [f32; 3] {
+        let (kr, kb) = self.matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+        let y = kr * r + kg * g + kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - kb));
+        let cr = (r - y) / (2.0 * (1.0 - kr));
+        [y, cb, cr]
+    }
+
+    /// Converts YC48 into normalized RGB `[r, g, b]`, without clamping.
+    pub fn yc_to_rgb_f32(&self, yc: PixelYc) -> [f32; 3] {
+        self.ycbcr_f32_to_rgb_f32(normalize(yc))
+    }
+
+    /// Converts normalized RGB `[r, g, b]` into YC48.
+    pub fn rgb_f32_to_yc(&self, rgb: [f32; 3]) -> PixelYc {
+        denormalize(self.rgb_f32_to_ycbcr_f32(rgb))
+    }
+
+    pub fn yc_to_rgb(&self, yc: PixelYc) -> PixelRgb {
+        let [r, g, b] = self.yc_to_rgb_f32(yc);
+        PixelRgb {
+            r: self.range.encode_luma(r),
+            g: self.range.encode_luma(g),
+            b: self.range.encode_luma(b),
+        }
+    }
+
+    pub fn rgb_to_yc(&self, rgb: PixelRgb) -> PixelYc {
+        self.rgb_f32_to_yc([
+            self.range.decode_luma(rgb.r),
+            self.range.decode_luma(rgb.g),
+            self.range.decode_luma(rgb.b),
+        ])
+    }
+
+    /// Quantizes YC48 into 8-bit YCbCr of this range. The matrix is not involved.
+    pub fn yc_to_ycbcr8(&self, yc: PixelYc) -> Ycbcr8 {
+        let [y, cb, cr] = normalize(yc);
+        Ycbcr8 {
+            y: self.range.encode_luma(y),
+            cb: self.range.encode_chroma(cb),
+            cr: self.range.encode_chroma(cr),
+        }
+    }
+
+    /// Expands 8-bit YCbCr of this range into YC48. The matrix is not involved.
+    pub fn ycbcr8_to_yc(&self, ycbcr: Ycbcr8) -> PixelYc {
+        denormalize([
+            self.range.decode_luma(ycbcr.y),
+            self.range.decode_chroma(ycbcr.cb),
+            self.range.decode_chroma(ycbcr.cr),
+        ])
+    }
+
+    pub fn yc_slice_to_rgb(&self, rgb: &mut [PixelRgb], yc: &[PixelYc]) {
+        assert!(yc.len() <= rgb.len());
+        for (dst, &src) in rgb.iter_mut().zip(yc) {
+            *dst = self.yc_to_rgb(src);
+        }
+    }
+
+    pub fn rgb_slice_to_yc(&self, yc: &mut [PixelYc], rgb: &[PixelRgb]) {
+        assert!(rgb.len() <= yc.len());
+        for (dst, &src) in yc.iter_mut().zip(rgb) {
+            *dst = self.rgb_to_yc(src);
+        }
+    }
+
+    /// Converts `frame` into RGB pixels in raster order.
+    pub fn frame_to_rgb(&self, frame: &impl Frame) -> Vec<PixelRgb> {
+        frame
+            .lines()
+            .flatten()
+            .map(|&yc| self.yc_to_rgb(yc))
+            .collect()
+    }
+
+    /// Overwrites `frame` by RGB pixels in raster order.
+    pub fn rgb_to_frame(&self, frame: &mut impl Frame, rgb: &[PixelRgb]) {
+        assert!(frame.frame_size().area() <= rgb.len());
+        for (dst, &src) in frame.lines_mut().flatten().zip(rgb) {
+            *dst = self.rgb_to_yc(src);
+        }
+    }
+
+    /// Converts another color space of YC48 into this in place, through normalized RGB.
+    pub fn convert_from(&self, from: &ColorSpace, frame: &mut impl Frame) {
+        if from.matrix == self.matrix {
+            return;
+        }
+        for pixel in frame.lines_mut().flatten() {
+            *pixel = self.rgb_f32_to_yc(from.yc_to_rgb_f32(*pixel));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The integer conversion of AviUtl from 8-bit RGB into YC48.
+    fn reference(rgb: PixelRgb) -> PixelYc {
+        let (r, g, b) = (rgb.r as i32, rgb.g as i32, rgb.b as i32);
+        let scale = |value: i32| (value as f32 / 1024.0).round() as i16;
+        PixelYc {
+            y: scale(4918 * r + 9655 * g + 1875 * b),
+            cb: scale(-2775 * r - 5449 * g + 8224 * b),
+            cr: scale(8224 * r - 6887 * g - 1337 * b),
+        }
+    }
+
+    fn assert_near(actual: PixelYc, expected: PixelYc) {
+        assert!(
+            (actual.y - expected.y).abs() <= 1
+                && (actual.cb - expected.cb).abs() <= 1
+                && (actual.cr - expected.cr).abs() <= 1,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn rgb_to_yc_matches_reference() {
+        let cases = [
+            ((0, 0, 0), (0, 0, 0)),
+            ((255, 255, 255), (4096, 0, 0)),
+            ((128, 128, 128), (2056, 0, 0)),
+            ((255, 0, 0), (1225, -691, 2048)),
+            ((0, 255, 0), (2404, -1357, -1715)),
+            ((0, 0, 255), (467, 2048, -333)),
+        ];
+        for ((r, g, b), (y, cb, cr)) in cases {
+            let rgb = PixelRgb { r, g, b };
+            let expected = PixelYc { y, cb, cr };
+            assert_near(reference(rgb), expected);
+            assert_near(ColorSpace::AVIUTL.rgb_to_yc(rgb), expected);
+        }
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        let space = ColorSpace::AVIUTL;
+        for r in (0..=255).step_by(5) {
+            for g in (0..=255).step_by(5) {
+                for b in (0..=255).step_by(5) {
+                    let rgb = PixelRgb { r, g, b };
+                    let yc = space.rgb_to_yc(rgb);
+                    assert_near(yc, reference(rgb));
+                    let back = space.yc_to_rgb(yc);
+                    assert!(
+                        back.r.abs_diff(r) <= 1
+                            && back.g.abs_diff(g) <= 1
+                            && back.b.abs_diff(b) <= 1,
+                        "{:?} became {:?}",
+                        rgb,
+                        back
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Row-parallel execution over AviUtl's thread pool.
+//!
+//! [`Executor`] abstracts `exec_multi_thread_func` of the filter and color plugin APIs, so that frame processing can
+//! run on AviUtl's threads inside a host, or on [`SingleThread`] without it.
+
+use crate::{filter::Frame, AviUtlError, PixelYc, Result};
+use std::os::raw::{c_int, c_void};
+
+/// A job called with `(thread_id, thread_num)` on every thread.
+pub type Job<'a> = dyn Fn(usize, usize) + Sync + 'a;
+
+pub trait Executor {
+    /// Calls `job` on every thread, and returns after all of them finished.
+    fn execute(&self, job: &Job) -> Result<()>;
+}
+
+/// Runs a job on the current thread only.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SingleThread;
+
+impl Executor for SingleThread {
+    fn execute(&self, job: &Job) -> Result<()> {
+        job(0, 1);
+        Ok(())
+    }
+}
+
+impl<E: Executor + ?Sized> Executor for &E {
+    fn execute(&self, job: &Job) -> Result<()> {
+        (**self).execute(job)
+    }
+}
+
+pub(crate) unsafe extern "system" fn job_trampoline(
+    id: c_int,
+    num: c_int,
+    job: *mut c_void,
+    _: *mut c_void,
+) {
+    let job = &*(job as *const &Job);
+    job(id as usize, num as usize);
+}
+
+/// Calls `exec_multi_thread_func` of AviUtl with `job`.
+pub(crate) fn execute_raw(
+    exec_multi_thread_func: unsafe extern "system" fn(
+        aviutl_plugin_sys::MultiThreadFunc,
+        *mut c_void,
+        *mut c_void,
+    ) -> c_int,
+    job: &Job,
+) -> Result<()> {
+    let job_ref: &Job = job;
+    if unsafe {
+        exec_multi_thread_func(
+            job_trampoline,
+            &job_ref as *const &Job as *mut c_void,
+            std::ptr::null_mut(),
+        )
+    } == 0
+    {
+        Err(AviUtlError::ThreadExecutionFailure)
+    } else {
+        Ok(())
+    }
+}
+
+/// Rows of `0..rows` assigned to `thread_id` of `thread_num`.
+pub fn band(rows: usize, thread_id: usize, thread_num: usize) -> std::ops::Range<usize> {
+    rows * thread_id / thread_num..rows * (thread_id + 1) / thread_num
+}
+
+struct SharedMut<T>(*mut T);
+
+unsafe impl<T: Send> Send for SharedMut<T> {}
+unsafe impl<T: Send> Sync for SharedMut<T> {}
+
+/// Calls `func` with every row of `data`, split into `rows` rows of `stride` and visible `width`, in parallel.
+pub fn for_each_row_mut<T: Send>(
+    executor: &impl Executor,
+    data: &mut [T],
+    rows: usize,
+    width: usize,
+    stride: usize,
+    func: impl Fn(usize, &mut [T]) + Sync,
+) -> Result<()> {
+    assert!(width <= stride);
+    assert!(rows == 0 || (rows - 1) * stride + width <= data.len());
+    let ptr = SharedMut(data.as_mut_ptr());
+    executor.execute(&|id, num| {
+        let ptr = &ptr;
+        for y in band(rows, id, num) {
+            // Safety: Every row is visited by only one thread, and the rows never overlap.
+            let row = unsafe { std::slice::from_raw_parts_mut(ptr.0.add(y * stride), width) };
+            func(y, row);
+        }
+    })
+}
+
+/// Calls `func` with every line of `frame`, in parallel.
+pub fn for_each_line_mut(
+    executor: &impl Executor,
+    frame: &mut impl Frame,
+    func: impl Fn(usize, &mut [PixelYc]) + Sync,
+) -> Result<()> {
+    let size = frame.frame_size();
+    let stride = frame.stride();
+    for_each_row_mut(
+        executor,
+        frame.image_mut(),
+        size.height as usize,
+        size.width as usize,
+        stride,
+        func,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Runs a job as `threads` threads one after another.
+    struct Sequential(usize);
+
+    impl Executor for Sequential {
+        fn execute(&self, job: &Job) -> Result<()> {
+            for id in 0..self.0 {
+                job(id, self.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bands_cover_rows_once() {
+        for rows in [0, 1, 7, 64, 1081] {
+            for threads in 1..=9 {
+                let mut next = 0;
+                for id in 0..threads {
+                    let band = band(rows, id, threads);
+                    assert_eq!(band.start, next);
+                    next = band.end;
+                }
+                assert_eq!(next, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn single_thread_visits_every_row_once() {
+        let (rows, width, stride) = (37, 5, 8);
+        let mut data = vec![0u32; rows * stride];
+        for_each_row_mut(&SingleThread, &mut data, rows, width, stride, |y, row| {
+            row.iter_mut().for_each(|value| *value += y as u32 + 1);
+        })
+        .unwrap();
+        for (y, line) in data.chunks(stride).enumerate() {
+            assert!(line[..width].iter().all(|&value| value == y as u32 + 1));
+            assert!(line[width..].iter().all(|&value| value == 0));
+        }
+    }
+
+    #[test]
+    fn threads_visit_every_row_once() {
+        let rows = 100;
+        let visits: Vec<AtomicUsize> = (0..rows).map(|_| AtomicUsize::new(0)).collect();
+        let mut data = vec![0u8; rows];
+        for_each_row_mut(&Sequential(7), &mut data, rows, 1, 1, |y, _| {
+            visits[y].fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+        assert!(visits
+            .iter()
+            .all(|count| count.load(Ordering::Relaxed) == 1));
+    }
+}
@@ -1,5 +1,13 @@
-use self::{api::Api, editing::Editing, window_message::WindowMessage};
-use crate::{PixelYc, Result, Size};
+use self::{
+    api::Api,
+    editing::Editing,
+    planar::{Plane, PlaneView, PlaneViewMut},
+    window_message::WindowMessage,
+};
+use crate::{
+    audio::{AudioBuf, Channel, ChannelLayout, ChannelMut},
+    AviUtlError, PixelYc, Result, Size,
+};
 use aviutl_plugin_sys::filter::{FilterProcInfo, FilterUpdateStatus};
 use std::ops::RangeInclusive;
 use windows::Win32::Foundation::{HINSTANCE, HWND};
@@ -9,11 +17,28 @@ pub use aviutl_plugin_sys::filter::FilterProcInfoFlag as ProcInfoFlag;
 pub use aviutl_plugin_sys::filter::{EditFlag, FrameInterlace};
 
 pub mod api;
+pub mod audio_stream;
 pub mod avi_file;
+pub mod composite;
+pub mod convolution;
+pub mod decimate;
+pub mod deinterlace;
+pub mod draw;
 pub mod editing;
 pub mod file_info;
 pub mod frame_status;
+pub mod grade;
+pub mod lut;
+pub mod planar;
+pub mod resample;
+pub mod scene;
+pub mod scope;
 pub mod sys_info;
+pub mod telecine;
+pub mod temporal;
+pub mod text;
+pub mod timeline;
+pub mod transform;
 pub mod window_message;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -71,7 +96,28 @@ impl<'a> AudioBuffer<'a> {
     }
 
     pub fn samples_per_channel(&self) -> usize {
-        self.total_samples / self.channels
+        self.total_samples.checked_div(self.channels).unwrap_or(0)
+    }
+
+    /// Samples of all channels, interleaved.
+    pub fn interleaved(&self) -> &[i16] {
+        self.data
+    }
+
+    pub fn interleaved_mut(&mut self) -> &mut [i16] {
+        self.data
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        ChannelLayout::from_channels(self.channels as u16)
+    }
+
+    pub fn channel(&self, channel: usize) -> Channel<'_, i16> {
+        Channel::new(self.data, self.channels, channel)
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> ChannelMut<'_, i16> {
+        ChannelMut::new(self.data, self.channels, channel)
     }
 
     pub fn samples_by_channel(&mut self, channel: usize) -> impl Iterator<Item = &mut i16> {
@@ -82,27 +128,126 @@ impl<'a> AudioBuffer<'a> {
             .filter(move |&(i, _)| i % channels == channel)
             .map(|(_, sample)| sample)
     }
+
+    pub fn to_buf(&self) -> AudioBuf<i16> {
+        let mut buf = AudioBuf::new(self.layout(), self.samples_per_channel());
+        let len = buf.interleaved().len();
+        buf.interleaved_mut().copy_from_slice(&self.data[..len]);
+        buf
+    }
+
+    /// Copies `src` of the same number of channels, truncating or padding with silence.
+    pub fn copy_from_buf(&mut self, src: &AudioBuf<i16>) -> Result<()> {
+        if src.channels() != self.channels {
+            return Err(AviUtlError::Unsupported(format!(
+                "copying {} channels into {} channels",
+                src.channels(),
+                self.channels
+            )));
+        }
+        let len = self.data.len().min(src.interleaved().len());
+        self.data[..len].copy_from_slice(&src.interleaved()[..len]);
+        self.data[len..].fill(0);
+        Ok(())
+    }
 }
 
+/// An image of [`PixelYc`] whose lines may be padded.
+///
+/// A line starts at every [`Frame::stride`] pixels of [`Frame::image`], and only the first `width` pixels of it are
+/// visible. AviUtl gives frames whose stride is the max width of the system.
 pub trait Frame {
     fn image(&self) -> &[PixelYc];
     fn image_mut(&mut self) -> &mut [PixelYc];
     fn frame_size(&self) -> Size;
 
-    fn lines(&self) -> std::slice::ChunksExact<PixelYc> {
-        let Size { width, .. } = self.frame_size();
-        self.image().chunks_exact(width as usize)
+    /// The number of pixels from the start of a line to the next.
+    fn stride(&self) -> usize {
+        self.frame_size().width as usize
+    }
+
+    /// The largest size which the image buffer can hold.
+    fn max_size(&self) -> Size {
+        let stride = self.stride();
+        Size {
+            width: stride as u32,
+            height: self.image().len().checked_div(stride).unwrap_or(0) as u32,
+        }
+    }
+
+    /// Changes the visible size of the frame without moving pixels. It fails if `size` exceeds [`Frame::max_size`].
+    fn set_frame_size(&mut self, size: Size) -> Result<()> {
+        if size == self.frame_size() {
+            Ok(())
+        } else {
+            Err(AviUtlError::Unsupported("resizing frame".into()))
+        }
+    }
+
+    fn line(&self, y: usize) -> &[PixelYc] {
+        assert!(y < self.frame_size().height as usize);
+        let start = y * self.stride();
+        &self.image()[start..start + self.frame_size().width as usize]
     }
 
-    fn lines_mut(&mut self) -> std::slice::ChunksExactMut<PixelYc> {
-        let Size { width, .. } = self.frame_size();
-        self.image_mut().chunks_exact_mut(width as usize)
+    fn line_mut(&mut self, y: usize) -> &mut [PixelYc] {
+        assert!(y < self.frame_size().height as usize);
+        let start = y * self.stride();
+        let width = self.frame_size().width as usize;
+        &mut self.image_mut()[start..start + width]
     }
 
-    fn split_at_y(&mut self, y: usize) -> (BorrowedMutFrame, BorrowedMutFrame) {
+    fn pixel(&self, x: usize, y: usize) -> PixelYc {
+        self.line(y)[x]
+    }
+
+    fn pixel_mut(&mut self, x: usize, y: usize) -> &mut PixelYc {
+        &mut self.line_mut(y)[x]
+    }
+
+    fn lines(&self) -> Lines<'_> {
+        Lines {
+            rest: self.image(),
+            width: self.frame_size().width as usize,
+            stride: self.stride(),
+            remaining: self.frame_size().height as usize,
+        }
+    }
+
+    fn lines_mut(&mut self) -> LinesMut<'_> {
+        let width = self.frame_size().width as usize;
+        let stride = self.stride();
+        let remaining = self.frame_size().height as usize;
+        LinesMut {
+            rest: self.image_mut(),
+            width,
+            stride,
+            remaining,
+        }
+    }
+
+    fn plane(&self, plane: Plane) -> PlaneView<'_> {
+        PlaneView::new(self.image(), self.frame_size(), self.stride(), plane)
+    }
+
+    fn plane_mut(&mut self, plane: Plane) -> PlaneViewMut<'_> {
+        let size = self.frame_size();
+        let stride = self.stride();
+        PlaneViewMut::new(self.image_mut(), size, stride, plane)
+    }
+
+    /// Returns the writable planes in order of Y, Cb and Cr.
+    fn planes_mut(&mut self) -> [PlaneViewMut<'_>; 3] {
+        let size = self.frame_size();
+        let stride = self.stride();
+        PlaneViewMut::split(self.image_mut(), size, stride)
+    }
+
+    fn split_at_y(&mut self, y: usize) -> (BorrowedMutFrame<'_>, BorrowedMutFrame<'_>) {
         let Size { width, height } = self.frame_size();
         assert!((0..(height as usize)).contains(&y));
-        let pos = y * width as usize;
+        let stride = self.stride();
+        let pos = y * stride;
         let (left, right) = self.image_mut().split_at_mut(pos);
         let left_height = y as u32;
         let right_height = height - left_height;
@@ -113,6 +258,7 @@ pub trait Frame {
                     width,
                     height: left_height,
                 },
+                stride,
             },
             BorrowedMutFrame {
                 image: right,
@@ -120,22 +266,112 @@ pub trait Frame {
                     width,
                     height: right_height,
                 },
+                stride,
             },
         )
     }
 }
 
+/// An iterator over the visible lines of a [`Frame`].
+#[derive(Debug, Clone)]
+pub struct Lines<'a> {
+    rest: &'a [PixelYc],
+    width: usize,
+    stride: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [PixelYc];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let line = &self.rest[..self.width];
+        self.rest = if self.remaining == 0 {
+            &[]
+        } else {
+            &self.rest[self.stride..]
+        };
+        Some(line)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Lines<'_> {}
+
+/// A mutable iterator over the visible lines of a [`Frame`].
+#[derive(Debug)]
+pub struct LinesMut<'a> {
+    rest: &'a mut [PixelYc],
+    width: usize,
+    stride: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for LinesMut<'a> {
+    type Item = &'a mut [PixelYc];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let rest = std::mem::take(&mut self.rest);
+        let (line, rest) = if self.remaining == 0 {
+            (rest, Default::default())
+        } else {
+            rest.split_at_mut(self.stride)
+        };
+        self.rest = rest;
+        Some(&mut line[..self.width])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for LinesMut<'_> {}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct OwnedFrame {
     image: Vec<PixelYc>,
     size: Size,
+    stride: usize,
 }
 
 impl OwnedFrame {
     pub fn new(size: Size) -> Self {
+        Self::with_max_size(size, size)
+    }
+
+    /// Creates a frame which can be resized up to `max_size` by [`Frame::set_frame_size`].
+    pub fn with_max_size(size: Size, max_size: Size) -> Self {
+        assert!(size.width <= max_size.width && size.height <= max_size.height);
+        Self {
+            image: vec![PixelYc::default(); max_size.area()],
+            size,
+            stride: max_size.width as usize,
+        }
+    }
+
+    /// Copies the visible pixels of `frame`.
+    pub fn from_frame(frame: &impl Frame) -> Self {
+        let size = frame.frame_size();
+        let mut image = Vec::with_capacity(size.area());
+        for line in frame.lines() {
+            image.extend_from_slice(line);
+        }
         Self {
-            image: vec![PixelYc::default(); size.area()],
+            image,
             size,
+            stride: size.width as usize,
         }
     }
 
@@ -143,6 +379,7 @@ impl OwnedFrame {
         BorrowedMutFrame {
             image: &mut self.image,
             size: self.size,
+            stride: self.stride,
         }
     }
 }
@@ -159,19 +396,38 @@ impl Frame for OwnedFrame {
     fn frame_size(&self) -> Size {
         self.size
     }
+
+    fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn set_frame_size(&mut self, size: Size) -> Result<()> {
+        let max_size = self.max_size();
+        if max_size.width < size.width || max_size.height < size.height {
+            return Err(AviUtlError::BufferLimitExceed);
+        }
+        self.size = size;
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct BorrowedMutFrame<'a> {
     image: &'a mut [PixelYc],
     size: Size,
+    stride: usize,
 }
 
 impl<'a> BorrowedMutFrame<'a> {
-    pub(crate) unsafe fn from_raw(image: *mut PixelYc, size: Size) -> Self {
+    pub(crate) unsafe fn from_raw_with_max_size(
+        image: *mut PixelYc,
+        size: Size,
+        max_size: Size,
+    ) -> Self {
         Self {
-            image: std::slice::from_raw_parts_mut(image, size.area()),
+            image: std::slice::from_raw_parts_mut(image, max_size.area()),
             size,
+            stride: max_size.width as usize,
         }
     }
 }
@@ -188,6 +444,19 @@ impl Frame for BorrowedMutFrame<'_> {
     fn frame_size(&self) -> Size {
         self.size
     }
+
+    fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn set_frame_size(&mut self, size: Size) -> Result<()> {
+        let max_size = self.max_size();
+        if max_size.width < size.width || max_size.height < size.height {
+            return Err(AviUtlError::BufferLimitExceed);
+        }
+        self.size = size;
+        Ok(())
+    }
 }
 
 pub struct ProcInfo<'a> {
@@ -214,15 +483,16 @@ impl<'a> ProcInfo<'a> {
             width: raw.w as u32,
             height: raw.h as u32,
         };
+        let max_size = Size {
+            width: raw.max_w as u32,
+            height: raw.max_h as u32,
+        };
         Self {
             flags: raw.flag,
-            yc_p_edit: BorrowedMutFrame::from_raw(raw.yc_p_edit, size),
-            yc_p_temp: BorrowedMutFrame::from_raw(raw.yc_p_temp, size),
+            yc_p_edit: BorrowedMutFrame::from_raw_with_max_size(raw.yc_p_edit, size, max_size),
+            yc_p_temp: BorrowedMutFrame::from_raw_with_max_size(raw.yc_p_temp, size, max_size),
             size,
-            max_size: Size {
-                width: raw.max_w as u32,
-                height: raw.max_h as u32,
-            },
+            max_size,
             current_frame: raw.frame as usize,
             total_frames: raw.frame_n as usize,
             original_size: Size {
@@ -234,7 +504,7 @@ impl<'a> ProcInfo<'a> {
                     raw.audio_p,
                     (raw.audio_n * raw.audio_ch) as usize,
                 ),
-                total_samples: raw.audio_n as usize,
+                total_samples: (raw.audio_n * raw.audio_ch) as usize,
                 channels: raw.audio_ch as usize,
             },
             editing: Editing::from_raw(raw.edit_p, api),
@@ -620,3 +890,87 @@ macro_rules! export_filter_plugin {
         }
     };
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Pixels after the visible part of each line in [`padded_frame`].
+    const PADDING: u32 = 40;
+
+    /// A frame of `size` with `luma` at each pixel, whose lines are padded by white pixels as in the caches of
+    /// AviUtl, so that reading past the visible width shows.
+    pub(crate) fn padded_frame(size: Size, luma: impl Fn(usize, usize) -> i16) -> OwnedFrame {
+        let max_size = Size {
+            width: size.width + PADDING,
+            height: size.height,
+        };
+        let mut frame = OwnedFrame::with_max_size(size, max_size);
+        frame.image_mut().fill(PixelYc {
+            y: PixelYc::Y_MAX,
+            cb: 0,
+            cr: 0,
+        });
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                pixel.y = luma(x, y);
+            }
+        }
+        frame
+    }
+
+    const SIZE: Size = Size {
+        width: 3,
+        height: 2,
+    };
+
+    fn luma(x: usize, y: usize) -> i16 {
+        (y * 10 + x) as i16
+    }
+
+    #[test]
+    fn lines_skip_padding() {
+        let frame = padded_frame(SIZE, luma);
+        assert_eq!(frame.stride(), 43);
+        assert_eq!(
+            frame.max_size(),
+            Size {
+                width: 43,
+                height: 2
+            }
+        );
+        let lumas: Vec<Vec<i16>> = frame
+            .lines()
+            .map(|line| line.iter().map(|pixel| pixel.y).collect())
+            .collect();
+        assert_eq!(lumas, [[0, 1, 2], [10, 11, 12]]);
+        assert_eq!(frame.lines().len(), 2);
+        assert_eq!(frame.line(1)[0].y, 10);
+        assert_eq!(frame.pixel(2, 1).y, 12);
+        // The padding after the visible pixels is untouched.
+        assert_eq!(frame.image()[3].y, PixelYc::Y_MAX);
+        assert_eq!(frame.image()[43].y, 10);
+    }
+
+    #[test]
+    fn copies_drop_padding() {
+        let frame = OwnedFrame::from_frame(&padded_frame(SIZE, luma));
+        assert_eq!(frame.stride(), 3);
+        assert_eq!(
+            frame
+                .image()
+                .iter()
+                .map(|pixel| pixel.y)
+                .collect::<Vec<_>>(),
+            [0, 1, 2, 10, 11, 12]
+        );
+    }
+
+    #[test]
+    fn split_keeps_stride() {
+        let mut frame = padded_frame(SIZE, luma);
+        let (top, bottom) = frame.split_at_y(1);
+        assert_eq!(top.frame_size().height, 1);
+        assert_eq!(bottom.line(0)[2].y, 12);
+    }
+}
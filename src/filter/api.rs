@@ -1,5 +1,6 @@
 use super::avi_file::AviFile;
 use crate::{
+    executor::{execute_raw, Executor, Job},
     into_win_str, AviUtlError, FileFilters, MultiThreadFn, PixelRgb, PixelYc, Result, Size,
 };
 use aviutl_plugin_sys::filter::{Exports, FilterPlugin as Table};
@@ -208,7 +209,7 @@ impl<'a> Api<'a> {
         unsafe { (self.exports.get_filter_p)(id as _).cast() }
     }
 
-    pub fn set_yc_cache_size(&mut self, size: Size, frames: usize) -> Result<()> {
+    pub fn set_yc_cache_size(&self, size: Size, frames: usize) -> Result<()> {
         if unsafe {
             (self.exports.set_yc_p_filtering_cache_size)(
                 self.filter,
@@ -301,6 +302,12 @@ impl<'a> Api<'a> {
     }
 }
 
+impl Executor for Api<'_> {
+    fn execute(&self, job: &Job) -> Result<()> {
+        execute_raw(self.exports.exec_multi_thread_func, job)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ShortcutKey {
     pub key_code: u8,
@@ -0,0 +1,272 @@
+//! Streaming audio through stateful effects across `process` calls.
+//!
+//! AviUtl passes the samples of one frame per call, in any order of frames. [`AudioStream`] follows
+//! `current_frame` and resets the [`AudioEffect`] when the frames are not consecutive, warming it up with the audio
+//! of earlier frames. Input ahead of the current frame is fed to compensate [`AudioEffect::latency`], so the output
+//! stays aligned with the video.
+
+use super::ProcInfo;
+use crate::{audio::AudioBuf, Result};
+use std::{collections::VecDeque, mem};
+
+/// An effect with memory, which consumes and produces samples one by one.
+pub trait AudioEffect {
+    /// Delay of the output in samples per channel.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Clears the state, as if no samples had been processed.
+    fn reset(&mut self);
+
+    /// Processes interleaved samples of `channels` channels in place.
+    fn process(&mut self, samples: &mut [i16], channels: usize);
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AudioStream {
+    /// Number of frames before the current one to process after a discontinuity.
+    pub pre_roll: usize,
+    last_frame: Option<usize>,
+    /// The first frame whose input has not been fed yet.
+    next_frame: usize,
+    channels: usize,
+    /// Number of output samples to drop, of latency and pre-roll.
+    discard: usize,
+    output: VecDeque<i16>,
+    discontinuity: bool,
+    buf: AudioBuf<i16>,
+}
+
+impl AudioStream {
+    pub fn new(pre_roll: usize) -> Self {
+        Self {
+            pre_roll,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the last [`AudioStream::process`] did not continue from the frame before.
+    pub fn discontinuity(&self) -> bool {
+        self.discontinuity
+    }
+
+    /// Makes the next [`AudioStream::process`] reset the effect.
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Processes the audio of the current frame in `proc_info` by `effect`.
+    pub fn process(
+        &mut self,
+        proc_info: &mut ProcInfo,
+        effect: &mut impl AudioEffect,
+    ) -> Result<()> {
+        let ProcInfo {
+            current_frame,
+            total_frames,
+            audio_buffer,
+            editing,
+            ..
+        } = proc_info;
+        let channels = audio_buffer.channels();
+        let mut buf = mem::take(&mut self.buf);
+        let result = self.process_with(
+            *current_frame,
+            *total_frames,
+            audio_buffer.interleaved_mut(),
+            channels,
+            effect,
+            |frame| {
+                editing.get_filtering_audio(frame, &mut buf)?;
+                Ok(buf.interleaved().to_vec())
+            },
+        );
+        self.buf = buf;
+        result
+    }
+
+    /// Processes interleaved `samples` of frame `current` out of `total`, where `fetch` returns the interleaved input
+    /// of other frames.
+    fn process_with(
+        &mut self,
+        current: usize,
+        total: usize,
+        samples: &mut [i16],
+        channels: usize,
+        effect: &mut impl AudioEffect,
+        mut fetch: impl FnMut(usize) -> Result<Vec<i16>>,
+    ) -> Result<()> {
+        if channels == 0 {
+            return Ok(());
+        }
+
+        self.discontinuity = channels != self.channels
+            || self.last_frame.is_none_or(|last| last + 1 != current)
+            || self.next_frame < current;
+        if self.discontinuity {
+            effect.reset();
+            self.channels = channels;
+            self.output.clear();
+            self.discard = effect.latency() * channels;
+            self.next_frame = current.saturating_sub(self.pre_roll);
+            while self.next_frame < current {
+                let mut input = fetch(self.next_frame)?;
+                self.discard += input.len();
+                self.feed(effect, &mut input);
+                self.next_frame += 1;
+            }
+        }
+        self.last_frame = Some(current);
+
+        if self.next_frame == current {
+            let mut input = samples.to_vec();
+            self.feed(effect, &mut input);
+            self.next_frame += 1;
+        }
+        while self.output.len() < samples.len() {
+            let mut input = if self.next_frame < total {
+                fetch(self.next_frame)?
+            } else {
+                // Flushes the effect with silence after the last frame.
+                vec![0; samples.len() - self.output.len() + self.discard]
+            };
+            self.feed(effect, &mut input);
+            self.next_frame += 1;
+        }
+        let len = samples.len();
+        for (sample, output) in samples.iter_mut().zip(self.output.drain(..len)) {
+            *sample = output;
+        }
+        Ok(())
+    }
+
+    fn feed(&mut self, effect: &mut impl AudioEffect, input: &mut [i16]) {
+        effect.process(input, self.channels);
+        let skipped = self.discard.min(input.len());
+        self.discard -= skipped;
+        self.output.extend(&input[skipped..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNELS: usize = 2;
+    /// Interleaved samples per frame.
+    const LEN: usize = 4 * CHANNELS;
+    const TOTAL: usize = 6;
+
+    /// Distinct interleaved input of `frame`.
+    fn source(frame: usize) -> Vec<i16> {
+        (0..LEN).map(|i| (frame * 100 + i + 1) as i16).collect()
+    }
+
+    /// Delays the input by `latency` samples per channel.
+    #[derive(Default)]
+    struct Delay {
+        latency: usize,
+        queue: VecDeque<i16>,
+        resets: usize,
+    }
+
+    impl AudioEffect for Delay {
+        fn latency(&self) -> usize {
+            self.latency
+        }
+
+        fn reset(&mut self) {
+            self.queue.clear();
+            self.resets += 1;
+        }
+
+        fn process(&mut self, samples: &mut [i16], channels: usize) {
+            if self.queue.is_empty() {
+                self.queue.resize(self.latency * channels, 0);
+            }
+            for sample in samples {
+                self.queue.push_back(*sample);
+                *sample = self.queue.pop_front().unwrap();
+            }
+        }
+    }
+
+    /// Adds the input of one frame before.
+    #[derive(Default)]
+    struct Echo {
+        history: VecDeque<i16>,
+    }
+
+    impl AudioEffect for Echo {
+        fn reset(&mut self) {
+            self.history.clear();
+        }
+
+        fn process(&mut self, samples: &mut [i16], _: usize) {
+            for sample in samples {
+                self.history.push_back(*sample);
+                if self.history.len() > LEN {
+                    *sample += self.history.pop_front().unwrap();
+                }
+            }
+        }
+    }
+
+    fn process(stream: &mut AudioStream, effect: &mut impl AudioEffect, frame: usize) -> Vec<i16> {
+        let mut samples = source(frame);
+        stream
+            .process_with(frame, TOTAL, &mut samples, CHANNELS, effect, |frame| {
+                assert!(frame < TOTAL);
+                Ok(source(frame))
+            })
+            .unwrap();
+        samples
+    }
+
+    #[test]
+    fn latency_is_compensated() {
+        for latency in [0, 3, 9] {
+            let mut stream = AudioStream::new(0);
+            let mut effect = Delay {
+                latency,
+                ..Delay::default()
+            };
+            for frame in [2, 3, 4, 5] {
+                assert_eq!(process(&mut stream, &mut effect, frame), source(frame));
+            }
+            assert_eq!(effect.resets, 1);
+        }
+    }
+
+    #[test]
+    fn discontinuities_reset_with_pre_roll() {
+        let echoed = |frame: usize| -> Vec<i16> {
+            let previous = frame.checked_sub(1).map_or(vec![0; LEN], source);
+            source(frame)
+                .into_iter()
+                .zip(previous)
+                .map(|(sample, previous)| sample + previous)
+                .collect()
+        };
+        let mut stream = AudioStream::new(1);
+        let mut effect = Echo::default();
+        for (frame, discontinuity) in [(3, true), (4, true), (5, false), (0, true), (1, false)] {
+            if frame == 4 {
+                stream.invalidate();
+            }
+            assert_eq!(process(&mut stream, &mut effect, frame), echoed(frame));
+            assert_eq!(stream.discontinuity(), discontinuity, "at {}", frame);
+        }
+
+        let mut stream = AudioStream::new(0);
+        assert_eq!(process(&mut stream, &mut effect, 3), source(3));
+        let mut mono = source(4);
+        stream
+            .process_with(4, TOTAL, &mut mono, 1, &mut effect, |frame| {
+                Ok(source(frame))
+            })
+            .unwrap();
+        assert!(stream.discontinuity());
+    }
+}
@@ -1,5 +1,8 @@
 use super::{api::Api, file_info::FileInfo, Frame};
-use crate::{AviUtlError, Result};
+use crate::{
+    audio::{AudioBuf, ChannelLayout},
+    AviUtlError, Result,
+};
 use aviutl_plugin_sys::filter::{AviFileHandle, FileOpenFlag};
 use std::mem::MaybeUninit;
 
@@ -63,14 +66,30 @@ impl<'a> AviFile<'a> {
         }
     }
 
-    pub fn read_audio(&mut self, target: &mut [u8], frame: usize) -> usize {
-        unsafe {
+    /// Reads the audio of `frame` into `target` in the layout of the file, resizing it to the samples read.
+    pub fn read_audio(&mut self, target: &mut AudioBuf<i16>, frame: usize) -> usize {
+        let FileInfo {
+            frame_rate,
+            audio_rate,
+            audio_channels,
+            ..
+        } = self.file_info;
+        target.set_layout(ChannelLayout::from_channels(audio_channels as u16));
+        // Frames differ in samples by rounding, so one more sample is room enough.
+        let capacity = (audio_rate as u64 * frame_rate.scale as u64)
+            .div_ceil(frame_rate.rate.max(1) as u64) as usize
+            + 1;
+        target.resize(capacity);
+        let read = unsafe {
             (self.api.exports.avi_file_read_audio)(
                 self.handle,
-                target.as_mut_ptr().cast(),
+                target.interleaved_mut().as_mut_ptr().cast(),
                 frame as _,
-            ) as usize
+            )
         }
+        .max(0) as usize;
+        target.resize(read.min(capacity));
+        target.frames()
     }
 
     pub fn get_video_dib(&mut self, frame: usize, bytes_per_pixel: usize) -> Result<&[u8]> {
@@ -91,24 +110,36 @@ impl<'a> AviFile<'a> {
         }
     }
 
-    pub fn read_audio_sample(&mut self, frame: usize, buf: &mut [u8]) -> usize {
-        unsafe {
+    /// Reads samples from `start` into `target`, as many as [`AudioBuf::frames`] of it. `target` is truncated to
+    /// the samples read at the end of the file.
+    pub fn read_audio_sample(&mut self, start: usize, target: &mut AudioBuf<i16>) -> usize {
+        let channels = self.file_info.audio_channels as u16;
+        target.set_layout(ChannelLayout::from_channels(channels));
+        let frames = target.frames();
+        let read = unsafe {
             (self.api.exports.avi_file_read_audio_sample)(
                 self.handle,
-                frame as _,
-                buf.len() as _,
-                buf.as_mut_ptr().cast(),
-            ) as usize
+                start as _,
+                frames as _,
+                target.interleaved_mut().as_mut_ptr().cast(),
+            )
         }
+        .max(0) as usize;
+        target.resize(read.min(frames));
+        target.frames()
     }
 
+    /// Converts the audio read afterward to `sample_rate`, returning the total samples at the rate.
     pub fn set_audio_sample_rate(&mut self, sample_rate: u32) -> u32 {
-        unsafe {
+        let samples = unsafe {
             (self.api.exports.avi_file_set_audio_sample_rate)(
                 self.handle,
                 sample_rate as _,
                 self.file_info.audio_channels as _,
             ) as u32
-        }
+        };
+        self.file_info.audio_rate = sample_rate as usize;
+        self.file_info.audio_samples = samples as usize;
+        samples
     }
 }
@@ -0,0 +1,328 @@
+//! Host-independent compositing of [`Frame`]s with alpha masks and blend modes.
+//!
+//! Unlike [`Editing::copy_from`], [`Compositor`] accepts a per-pixel [`AlphaPlane`], places the source at any offset
+//! including negative ones, and clips it by the destination bounds.
+//!
+//! [`Editing::copy_from`]: super::editing::Editing::copy_from
+
+use super::Frame;
+use crate::{colorspace::ColorSpace, pixel::FloatPixelYc, PixelYc, Point, Rect, Size};
+
+/// The opacity which means fully opaque, as AviUtl uses.
+pub const OPAQUE: u16 = 4096;
+
+/// A plane of opacity between 0 and [`OPAQUE`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AlphaPlane {
+    data: Vec<u16>,
+    size: Size,
+}
+
+impl AlphaPlane {
+    pub fn new(size: Size, alpha: u16) -> Self {
+        Self {
+            data: vec![alpha.min(OPAQUE); size.area()],
+            size,
+        }
+    }
+
+    pub fn from_fn(size: Size, mut f: impl FnMut(usize, usize) -> u16) -> Self {
+        let width = size.width as usize;
+        Self {
+            data: (0..size.area())
+                .map(|i| f(i % width, i / width).min(OPAQUE))
+                .collect(),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u16 {
+        assert!(x < self.size.width as usize);
+        self.data[y * self.size.width as usize + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, alpha: u16) {
+        assert!(x < self.size.width as usize);
+        self.data[y * self.size.width as usize + x] = alpha.min(OPAQUE);
+    }
+
+    pub fn line(&self, y: usize) -> &[u16] {
+        let width = self.size.width as usize;
+        &self.data[y * width..(y + 1) * width]
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.data
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Add,
+    Subtract,
+    Multiply,
+    Screen,
+    Overlay,
+}
+
+impl BlendMode {
+    /// Blends normalized components of backdrop `dst` and `src`.
+    pub fn blend(self, dst: f32, src: f32) -> f32 {
+        match self {
+            BlendMode::Normal => src,
+            BlendMode::Add => (dst + src).min(1.0),
+            BlendMode::Subtract => (dst - src).max(0.0),
+            BlendMode::Multiply => dst * src,
+            BlendMode::Screen => 1.0 - (1.0 - dst) * (1.0 - src),
+            BlendMode::Overlay => {
+                if dst < 0.5 {
+                    2.0 * dst * src
+                } else {
+                    1.0 - 2.0 * (1.0 - dst) * (1.0 - src)
+                }
+            }
+        }
+    }
+}
+
+/// The space where blending is computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendSpace {
+    /// Converts into RGB of the color space, blends and converts back. This is correct for all modes.
+    Rgb(ColorSpace),
+    /// Blends YC48 directly. `Normal`, `Add` and `Subtract` are applied to all components, which equals to RGB
+    /// except for clipping. `Multiply`, `Screen` and `Overlay` are approximated by blending luma only, with chroma
+    /// mixed as `Normal`.
+    Yc,
+}
+
+impl Default for BlendSpace {
+    fn default() -> Self {
+        BlendSpace::Rgb(ColorSpace::AVIUTL)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Compositor {
+    pub mode: BlendMode,
+    pub space: BlendSpace,
+    /// Opacity of the whole source between 0 and [`OPAQUE`], multiplied with the mask.
+    pub opacity: u16,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self {
+            mode: BlendMode::Normal,
+            space: BlendSpace::default(),
+            opacity: OPAQUE,
+        }
+    }
+}
+
+impl Compositor {
+    pub fn new(mode: BlendMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Blends a pair of pixels by `alpha` between 0.0 and 1.0.
+    pub fn blend_pixel(&self, dst: PixelYc, src: PixelYc, alpha: f32) -> PixelYc {
+        match self.space {
+            BlendSpace::Rgb(color_space) => {
+                let backdrop = color_space.yc_to_rgb_f32(dst);
+                let source = color_space.yc_to_rgb_f32(src);
+                let mixed = std::array::from_fn(|i| {
+                    let d = backdrop[i].clamp(0.0, 1.0);
+                    let s = source[i].clamp(0.0, 1.0);
+                    d + (self.mode.blend(d, s) - d) * alpha
+                });
+                color_space.rgb_f32_to_yc(mixed)
+            }
+            BlendSpace::Yc => {
+                let linear = |d: i16, s: i16| match self.mode {
+                    BlendMode::Add => d as f32 + s as f32,
+                    BlendMode::Subtract => d as f32 - s as f32,
+                    _ => s as f32,
+                };
+                let scale = PixelYc::Y_MAX as f32;
+                let luma = match self.mode {
+                    BlendMode::Multiply | BlendMode::Screen | BlendMode::Overlay => {
+                        self.mode.blend(dst.y as f32 / scale, src.y as f32 / scale) * scale
+                    }
+                    _ => linear(dst.y, src.y),
+                };
+                let blended = FloatPixelYc {
+                    y: luma,
+                    cb: linear(dst.cb, src.cb),
+                    cr: linear(dst.cr, src.cr),
+                };
+                let dst = FloatPixelYc::from(dst);
+                (dst + (blended - dst) * alpha).to_pixel()
+            }
+        }
+    }
+
+    /// Composites `source` onto `frame` with its top-left at `position`, and returns the affected region of
+    /// `frame`. `mask` must be the same size as `source`.
+    pub fn composite(
+        &self,
+        frame: &mut impl Frame,
+        position: Point,
+        source: &impl Frame,
+        mask: Option<&AlphaPlane>,
+    ) -> Option<Rect> {
+        let source_size = source.frame_size();
+        if let Some(mask) = mask {
+            assert_eq!(
+                mask.size(),
+                source_size,
+                "mask must be the same size as source"
+            );
+        }
+        let placed = Rect {
+            size: source_size,
+            point: position,
+        };
+        let region = placed.intersection(Rect {
+            size: frame.frame_size(),
+            point: Point::new(),
+        })?;
+        let opacity = self.opacity.min(OPAQUE) as f32 / OPAQUE as f32;
+        for y in region.top()..region.bottom() {
+            let source_y = (y - position.y) as usize;
+            let source_line = source.line(source_y);
+            let line = frame.line_mut(y as usize);
+            for x in region.left()..region.right() {
+                let source_x = (x - position.x) as usize;
+                let alpha = mask.map_or(1.0, |mask| {
+                    mask.get(source_x, source_y) as f32 / OPAQUE as f32
+                }) * opacity;
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let dst = &mut line[x as usize];
+                *dst = self.blend_pixel(*dst, source_line[source_x], alpha);
+            }
+        }
+        Some(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::OwnedFrame;
+
+    fn flat(size: Size, y: i16) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(size);
+        frame.image_mut().fill(PixelYc { y, cb: 0, cr: 0 });
+        frame
+    }
+
+    fn lumas(frame: &impl Frame) -> Vec<Vec<i16>> {
+        frame
+            .lines()
+            .map(|line| line.iter().map(|pixel| pixel.y).collect())
+            .collect()
+    }
+
+    const YC: Compositor = Compositor {
+        mode: BlendMode::Normal,
+        space: BlendSpace::Yc,
+        opacity: OPAQUE,
+    };
+
+    #[test]
+    fn blend_modes() {
+        assert_eq!(BlendMode::Normal.blend(0.2, 0.6), 0.6);
+        assert_eq!(BlendMode::Add.blend(0.7, 0.6), 1.0);
+        assert_eq!(BlendMode::Subtract.blend(0.2, 0.6), 0.0);
+        assert_eq!(BlendMode::Multiply.blend(0.5, 0.5), 0.25);
+        assert_eq!(BlendMode::Screen.blend(0.5, 0.5), 0.75);
+        assert_eq!(BlendMode::Overlay.blend(0.25, 0.5), 0.25);
+        assert_eq!(BlendMode::Overlay.blend(0.75, 0.5), 0.75);
+    }
+
+    #[test]
+    fn clips_source_at_negative_position() {
+        let mut frame = flat(
+            Size {
+                width: 4,
+                height: 3,
+            },
+            0,
+        );
+        let source = flat(
+            Size {
+                width: 3,
+                height: 3,
+            },
+            1000,
+        );
+        let region = YC.composite(&mut frame, Point { x: -1, y: 1 }, &source, None);
+        assert_eq!(
+            region,
+            Some(Rect {
+                size: Size {
+                    width: 2,
+                    height: 2
+                },
+                point: Point { x: 0, y: 1 },
+            })
+        );
+        assert_eq!(
+            lumas(&frame),
+            [[0, 0, 0, 0], [1000, 1000, 0, 0], [1000, 1000, 0, 0]]
+        );
+        assert_eq!(
+            YC.composite(&mut frame, Point { x: 4, y: 0 }, &source, None),
+            None
+        );
+    }
+
+    #[test]
+    fn mask_and_opacity_multiply() {
+        let size = Size {
+            width: 2,
+            height: 1,
+        };
+        let mask = AlphaPlane::from_fn(size, |x, _| if x == 0 { OPAQUE / 2 } else { 0 });
+        let mut frame = flat(size, 0);
+        YC.composite(&mut frame, Point::new(), &flat(size, 1000), Some(&mask));
+        assert_eq!(lumas(&frame), [[500, 0]]);
+        let half = Compositor {
+            opacity: OPAQUE / 2,
+            ..YC
+        };
+        let mut frame = flat(size, 0);
+        half.composite(&mut frame, Point::new(), &flat(size, 1000), Some(&mask));
+        assert_eq!(lumas(&frame), [[250, 0]]);
+    }
+
+    #[test]
+    fn rgb_space_keeps_gray() {
+        let compositor = Compositor::new(BlendMode::Multiply);
+        let white = PixelYc {
+            y: PixelYc::Y_MAX,
+            cb: 0,
+            cr: 0,
+        };
+        let gray = PixelYc {
+            y: 2048,
+            cb: 0,
+            cr: 0,
+        };
+        let blended = compositor.blend_pixel(white, gray, 1.0);
+        assert!((blended.y - 2048).abs() <= 1 && blended.cb.abs() <= 1 && blended.cr.abs() <= 1);
+        assert_eq!(compositor.blend_pixel(white, gray, 0.0), white);
+    }
+}
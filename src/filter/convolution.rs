@@ -0,0 +1,496 @@
+//! Host-independent convolution and spatial filters on [`Frame`]s.
+//!
+//! [`Convolution`] holds how to treat the frame edges and which planes to filter, and runs every filter on the
+//! threads of an [`Executor`].
+
+use super::{planar::Plane, resample::quantize, Frame, OwnedFrame};
+use crate::{
+    executor::{for_each_line_mut, for_each_row_mut, Executor},
+    AviUtlError, Result, Size,
+};
+
+/// How to sample outside of a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BorderMode {
+    /// Repeats the edge pixel.
+    #[default]
+    Clamp,
+    /// Reflects at the edge without repeating it, as `2 1 | 0 1 2 | 1 0`.
+    Mirror,
+    /// Tiles the frame.
+    Wrap,
+}
+
+impl BorderMode {
+    /// Maps the index `i` into `0..len`.
+    pub fn resolve(self, i: isize, len: usize) -> usize {
+        let len = len as isize;
+        if (0..len).contains(&i) {
+            return i as usize;
+        }
+        match self {
+            BorderMode::Clamp => i.clamp(0, len - 1) as usize,
+            BorderMode::Mirror => {
+                if len == 1 {
+                    return 0;
+                }
+                let period = 2 * (len - 1);
+                let i = i.rem_euclid(period);
+                (if i < len { i } else { period - i }) as usize
+            }
+            BorderMode::Wrap => i.rem_euclid(len) as usize,
+        }
+    }
+}
+
+/// Planes to be filtered. The others are left as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaneSelection {
+    pub luma: bool,
+    pub chroma: bool,
+}
+
+impl PlaneSelection {
+    pub const ALL: Self = Self {
+        luma: true,
+        chroma: true,
+    };
+    pub const LUMA: Self = Self {
+        luma: true,
+        chroma: false,
+    };
+    pub const CHROMA: Self = Self {
+        luma: false,
+        chroma: true,
+    };
+
+    pub fn contains(self, plane: Plane) -> bool {
+        if plane.is_chroma() {
+            self.chroma
+        } else {
+            self.luma
+        }
+    }
+}
+
+impl Default for PlaneSelection {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// A 2D kernel of odd width and height, centered at the middle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel2d {
+    weights: Vec<f32>,
+    size: Size,
+}
+
+impl Kernel2d {
+    pub fn new(size: Size, weights: Vec<f32>) -> Result<Self> {
+        if size.width.is_multiple_of(2)
+            || size.height.is_multiple_of(2)
+            || weights.len() != size.area()
+        {
+            return Err(AviUtlError::Unsupported(format!(
+                "kernel of size {}x{} with {} weights",
+                size.width,
+                size.height,
+                weights.len()
+            )));
+        }
+        Ok(Self { weights, size })
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Laplacian of 4 neighbors, for edge detection.
+    pub fn laplacian() -> Self {
+        Self {
+            weights: vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0],
+            size: Size {
+                width: 3,
+                height: 3,
+            },
+        }
+    }
+
+    /// Sharpens by subtracting 4 neighbors.
+    pub fn sharpen() -> Self {
+        Self {
+            weights: vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],
+            size: Size {
+                width: 3,
+                height: 3,
+            },
+        }
+    }
+}
+
+/// Normalized taps of a box filter of `2 * radius + 1` width.
+pub fn box_taps(radius: usize) -> Vec<f32> {
+    let len = 2 * radius + 1;
+    vec![1.0 / len as f32; len]
+}
+
+/// Normalized taps of a Gaussian filter, truncated at 3 sigma.
+pub fn gaussian_taps(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil().max(0.0) as isize;
+    if radius == 0 {
+        return vec![1.0];
+    }
+    let taps: Vec<_> = (-radius..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = taps.iter().sum();
+    taps.into_iter().map(|tap| tap / sum).collect()
+}
+
+/// Applies spatial filters with the border mode to the selected planes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Convolution {
+    pub border: BorderMode,
+    pub planes: PlaneSelection,
+}
+
+impl Convolution {
+    pub const fn new(border: BorderMode, planes: PlaneSelection) -> Self {
+        Self { border, planes }
+    }
+
+    /// Convolves `frame` by `horizontal` taps and then by `vertical` taps, both of odd length.
+    pub fn separable(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        horizontal: &[f32],
+        vertical: &[f32],
+    ) -> Result<()> {
+        if horizontal.len().is_multiple_of(2) || vertical.len().is_multiple_of(2) {
+            return Err(AviUtlError::Unsupported(
+                "separable kernel of even taps".into(),
+            ));
+        }
+        let source = OwnedFrame::from_frame(frame);
+        let Size { width, height } = source.frame_size();
+        let (width, height) = (width as usize, height as usize);
+        let (h_radius, v_radius) = (horizontal.len() as isize / 2, vertical.len() as isize / 2);
+
+        let mut intermediate = vec![[0.0f32; 3]; width * height];
+        for_each_row_mut(
+            executor,
+            &mut intermediate,
+            height,
+            width,
+            width,
+            |y, row| {
+                let line = source.line(y);
+                for (x, out) in row.iter_mut().enumerate() {
+                    for plane in Plane::ALL {
+                        out[plane as usize] = horizontal
+                            .iter()
+                            .enumerate()
+                            .map(|(i, tap)| {
+                                let sx = self
+                                    .border
+                                    .resolve(x as isize + i as isize - h_radius, width);
+                                tap * plane.get(&line[sx]) as f32
+                            })
+                            .sum();
+                    }
+                }
+            },
+        )?;
+
+        for_each_line_mut(executor, frame, |y, line| {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                for plane in Plane::ALL.into_iter().filter(|&p| self.planes.contains(p)) {
+                    let sum: f32 = vertical
+                        .iter()
+                        .enumerate()
+                        .map(|(i, tap)| {
+                            let sy = self
+                                .border
+                                .resolve(y as isize + i as isize - v_radius, height);
+                            tap * intermediate[sy * width + x][plane as usize]
+                        })
+                        .sum();
+                    *plane.get_mut(pixel) = quantize(sum, plane);
+                }
+            }
+        })
+    }
+
+    pub fn convolve_2d(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        kernel: &Kernel2d,
+    ) -> Result<()> {
+        let source = OwnedFrame::from_frame(frame);
+        let Size { width, height } = source.frame_size();
+        let (width, height) = (width as usize, height as usize);
+        let k_width = kernel.size.width as usize;
+        let (h_radius, v_radius) = (
+            kernel.size.width as isize / 2,
+            kernel.size.height as isize / 2,
+        );
+        for_each_line_mut(executor, frame, |y, line| {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                for plane in Plane::ALL.into_iter().filter(|&p| self.planes.contains(p)) {
+                    let sum: f32 = kernel
+                        .weights
+                        .iter()
+                        .enumerate()
+                        .map(|(i, weight)| {
+                            let sx = self
+                                .border
+                                .resolve(x as isize + (i % k_width) as isize - h_radius, width);
+                            let sy = self
+                                .border
+                                .resolve(y as isize + (i / k_width) as isize - v_radius, height);
+                            weight * plane.get(&source.pixel(sx, sy)) as f32
+                        })
+                        .sum();
+                    *plane.get_mut(pixel) = quantize(sum, plane);
+                }
+            }
+        })
+    }
+
+    pub fn box_blur(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        radius: usize,
+    ) -> Result<()> {
+        let taps = box_taps(radius);
+        self.separable(executor, frame, &taps, &taps)
+    }
+
+    pub fn gaussian_blur(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        sigma: f32,
+    ) -> Result<()> {
+        let taps = gaussian_taps(sigma);
+        self.separable(executor, frame, &taps, &taps)
+    }
+
+    /// Sharpens by adding the difference from the Gaussian blurred by `amount`, where the absolute difference
+    /// exceeds `threshold`.
+    pub fn unsharp_mask(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        sigma: f32,
+        amount: f32,
+        threshold: i16,
+    ) -> Result<()> {
+        let mut blurred = OwnedFrame::from_frame(frame);
+        self.gaussian_blur(executor, &mut blurred, sigma)?;
+        for_each_line_mut(executor, frame, |y, line| {
+            let blurred_line = blurred.line(y);
+            for (pixel, blurred) in line.iter_mut().zip(blurred_line) {
+                for plane in Plane::ALL.into_iter().filter(|&p| self.planes.contains(p)) {
+                    let original = plane.get(pixel);
+                    let diff = original as f32 - plane.get(blurred) as f32;
+                    if diff.abs() > threshold as f32 {
+                        *plane.get_mut(pixel) = quantize(original as f32 + diff * amount, plane);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Replaces each component with the median in the square of `2 * radius + 1`.
+    pub fn median(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        radius: usize,
+    ) -> Result<()> {
+        let source = OwnedFrame::from_frame(frame);
+        let Size { width, height } = source.frame_size();
+        let (width, height) = (width as usize, height as usize);
+        let radius = radius as isize;
+        for_each_line_mut(executor, frame, |y, line| {
+            let mut window = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+            for (x, pixel) in line.iter_mut().enumerate() {
+                for plane in Plane::ALL.into_iter().filter(|&p| self.planes.contains(p)) {
+                    window.clear();
+                    for dy in -radius..=radius {
+                        let sy = self.border.resolve(y as isize + dy, height);
+                        let source_line = source.line(sy);
+                        for dx in -radius..=radius {
+                            let sx = self.border.resolve(x as isize + dx, width);
+                            window.push(plane.get(&source_line[sx]));
+                        }
+                    }
+                    let middle = window.len() / 2;
+                    *plane.get_mut(pixel) = *window.select_nth_unstable(middle).1;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::SingleThread, filter::tests::padded_frame};
+
+    const SIZE: Size = Size {
+        width: 5,
+        height: 5,
+    };
+
+    fn lumas(frame: &impl Frame) -> Vec<Vec<i16>> {
+        frame
+            .lines()
+            .map(|line| line.iter().map(|pixel| pixel.y).collect())
+            .collect()
+    }
+
+    #[test]
+    fn borders_resolve() {
+        let resolve =
+            |border: BorderMode| -> Vec<_> { (-3..7).map(|i| border.resolve(i, 4)).collect() };
+        assert_eq!(resolve(BorderMode::Clamp), [0, 0, 0, 0, 1, 2, 3, 3, 3, 3]);
+        assert_eq!(resolve(BorderMode::Mirror), [3, 2, 1, 0, 1, 2, 3, 2, 1, 0]);
+        assert_eq!(resolve(BorderMode::Wrap), [1, 2, 3, 0, 1, 2, 3, 0, 1, 2]);
+        assert_eq!(BorderMode::Mirror.resolve(-2, 1), 0);
+    }
+
+    #[test]
+    fn kernels_need_odd_sizes() {
+        let size = |width, height| Size { width, height };
+        assert!(Kernel2d::new(size(3, 1), vec![1.0; 3]).is_ok());
+        assert!(Kernel2d::new(size(2, 1), vec![1.0; 2]).is_err());
+        assert!(Kernel2d::new(size(3, 3), vec![1.0; 8]).is_err());
+        let mut frame = padded_frame(SIZE, |_, _| 0);
+        assert!(Convolution::default()
+            .separable(&SingleThread, &mut frame, &[0.5, 0.5], &[1.0])
+            .is_err());
+    }
+
+    #[test]
+    fn taps_are_normalized() {
+        for taps in [
+            box_taps(0),
+            box_taps(3),
+            gaussian_taps(0.0),
+            gaussian_taps(1.5),
+        ] {
+            let sum: f32 = taps.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-5, "{:?} sums to {}", taps, sum);
+            assert_eq!(taps.len() % 2, 1);
+        }
+        assert_eq!(gaussian_taps(1.0).len(), 7);
+    }
+
+    #[test]
+    fn blur_spreads_impulse() {
+        let mut frame = padded_frame(SIZE, |x, y| if (x, y) == (2, 2) { 900 } else { 0 });
+        Convolution::default()
+            .box_blur(&SingleThread, &mut frame, 1)
+            .unwrap();
+        for (y, line) in lumas(&frame).into_iter().enumerate() {
+            for (x, luma) in line.into_iter().enumerate() {
+                let near = x.abs_diff(2) <= 1 && y.abs_diff(2) <= 1;
+                assert_eq!(luma, if near { 100 } else { 0 }, "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_frames_are_kept() {
+        let convolution = Convolution::new(BorderMode::Mirror, PlaneSelection::ALL);
+        let mut frame = padded_frame(SIZE, |_, _| 1000);
+        convolution
+            .gaussian_blur(&SingleThread, &mut frame, 2.0)
+            .unwrap();
+        convolution
+            .convolve_2d(&SingleThread, &mut frame, &Kernel2d::sharpen())
+            .unwrap();
+        assert!(lumas(&frame).into_iter().flatten().all(|luma| luma == 1000));
+
+        convolution
+            .convolve_2d(&SingleThread, &mut frame, &Kernel2d::laplacian())
+            .unwrap();
+        assert!(lumas(&frame).into_iter().flatten().all(|luma| luma == 0));
+    }
+
+    #[test]
+    fn unsharp_mask_steepens_edges() {
+        let size = Size {
+            width: 8,
+            height: 3,
+        };
+        let step = |x: usize, _| if x < 4 { 1000 } else { 3000 };
+        let mut frame = padded_frame(size, step);
+        Convolution::default()
+            .unsharp_mask(&SingleThread, &mut frame, 1.0, 1.0, 0)
+            .unwrap();
+        let lumas = lumas(&frame);
+        assert!(lumas[1][3] < 1000);
+        assert!(lumas[1][4] > 3000);
+        assert_eq!(lumas[1][0], 1000);
+        assert_eq!(lumas[1][7], 3000);
+
+        let mut frame = padded_frame(size, step);
+        Convolution::default()
+            .unsharp_mask(&SingleThread, &mut frame, 1.0, 1.0, 2000)
+            .unwrap();
+        assert_eq!(self::lumas(&frame), self::lumas(&padded_frame(size, step)));
+    }
+
+    #[test]
+    fn median_removes_outlier() {
+        let mut frame = padded_frame(SIZE, |x, y| if (x, y) == (1, 3) { 4000 } else { 1000 });
+        Convolution::new(BorderMode::Clamp, PlaneSelection::ALL)
+            .median(&SingleThread, &mut frame, 1)
+            .unwrap();
+        assert!(lumas(&frame).into_iter().flatten().all(|luma| luma == 1000));
+    }
+
+    #[test]
+    fn selected_planes_only_are_filtered() {
+        let mut frame = padded_frame(SIZE, |x, _| (x * 1000) as i16);
+        for (x, pixel) in frame
+            .lines_mut()
+            .flat_map(|line| line.iter_mut().enumerate())
+        {
+            pixel.cb = x as i16 * 500 - 1000;
+        }
+        let chroma: Vec<_> = frame.lines().flatten().map(|pixel| pixel.cb).collect();
+        Convolution::new(BorderMode::Clamp, PlaneSelection::LUMA)
+            .box_blur(&SingleThread, &mut frame, 1)
+            .unwrap();
+        assert_eq!(
+            frame
+                .lines()
+                .flatten()
+                .map(|pixel| pixel.cb)
+                .collect::<Vec<_>>(),
+            chroma
+        );
+        assert_eq!(lumas(&frame)[0], [333, 1000, 2000, 3000, 3667]);
+
+        let mut frame = padded_frame(SIZE, |x, _| (x * 1000) as i16);
+        Convolution::new(BorderMode::Clamp, PlaneSelection::CHROMA)
+            .box_blur(&SingleThread, &mut frame, 1)
+            .unwrap();
+        assert_eq!(
+            lumas(&frame),
+            lumas(&padded_frame(SIZE, |x, _| (x * 1000) as i16))
+        );
+    }
+}
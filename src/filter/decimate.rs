@@ -0,0 +1,350 @@
+//! Detection of duplicate frames and decimation of the timeline.
+//!
+//! Frames are compared with the previous frame block by block, ignoring luma differences within the noise tolerance,
+//! and a frame is a duplicate when even its most changed block stays under the threshold. Frames can be dropped
+//! either for being duplicates or by a fixed pattern such as 1 in 5, where the most similar frames of each cycle are
+//! dropped. Dropped frames get [`EditFlag::DEL_FRAME`] so that AviUtl leaves them out of the output, or a plugin can
+//! answer [`FilterPlugin::is_save_frame`](super::FilterPlugin::is_save_frame) by [`DecimationReport::is_save_frame`].
+
+use super::{
+    editing::{Editing, FrameSource},
+    EditFlag, Frame, OwnedFrame,
+};
+use crate::{AviUtlError, PixelYc, Result};
+use std::{fmt, ops::ControlFlow, ops::RangeInclusive};
+
+/// Differences of luma from the previous frame, between 0.0 and 1.0.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameDifference {
+    /// Average over the whole frame.
+    pub mean: f32,
+    /// Average in the most changed block.
+    pub max_block: f32,
+}
+
+impl FrameDifference {
+    /// Compares `frame` with `previous` by blocks of `block_size` pixels square, subtracting `noise` from the
+    /// difference of each pixel.
+    pub fn new(previous: &impl Frame, frame: &impl Frame, block_size: usize, noise: i16) -> Self {
+        let size = frame.frame_size();
+        if previous.frame_size() != size {
+            return Self {
+                mean: 1.0,
+                max_block: 1.0,
+            };
+        }
+        let block_size = block_size.max(1);
+        let columns = (size.width as usize).div_ceil(block_size);
+        let mut sums = vec![0u64; columns * (size.height as usize).div_ceil(block_size)];
+        let mut counts = vec![0u32; sums.len()];
+        for (y, (before, after)) in previous.lines().zip(frame.lines()).enumerate() {
+            let row = y / block_size * columns;
+            for (x, (a, b)) in before.iter().zip(after).enumerate() {
+                let difference = (a.y as i32 - b.y as i32).unsigned_abs();
+                sums[row + x / block_size] += difference.saturating_sub(noise.max(0) as u32) as u64;
+                counts[row + x / block_size] += 1;
+            }
+        }
+        let scale = PixelYc::Y_MAX as f64;
+        let total = counts.iter().map(|&count| count as u64).sum::<u64>().max(1);
+        let max_block = sums
+            .iter()
+            .zip(&counts)
+            .map(|(&sum, &count)| sum as f64 / count.max(1) as f64)
+            .fold(0.0, f64::max);
+        Self {
+            mean: (sums.iter().sum::<u64>() as f64 / total as f64 / scale).min(1.0) as f32,
+            max_block: (max_block / scale).min(1.0) as f32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecimationMode {
+    /// Drops nothing, only detecting duplicates.
+    #[default]
+    None,
+    /// Drops every duplicate.
+    Duplicates,
+    /// Drops the `drop` most similar frames in every `cycle` frames, such as 1 in 5 for telecined sources.
+    Cycle { cycle: usize, drop: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decimator {
+    /// Side of the compared blocks in pixels.
+    pub block_size: usize,
+    /// Luma difference of a pixel regarded as noise.
+    pub noise: i16,
+    /// Highest [`FrameDifference::max_block`] of a duplicate.
+    pub threshold: f32,
+    pub mode: DecimationMode,
+    pub source: FrameSource,
+}
+
+impl Default for Decimator {
+    fn default() -> Self {
+        Self {
+            block_size: 32,
+            noise: PixelYc::Y_MAX / 256,
+            threshold: 0.005,
+            mode: DecimationMode::default(),
+            source: FrameSource::default(),
+        }
+    }
+}
+
+/// What was decided for a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameDecision {
+    pub frame: usize,
+    /// Difference from the previous frame, or `None` for the first frame.
+    pub difference: Option<FrameDifference>,
+    pub duplicate: bool,
+    pub dropped: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecimationReport {
+    pub frames: Vec<FrameDecision>,
+}
+
+impl DecimationReport {
+    pub fn duplicates(&self) -> impl Iterator<Item = usize> + '_ {
+        self.frames.iter().filter(|d| d.duplicate).map(|d| d.frame)
+    }
+
+    pub fn dropped(&self) -> impl Iterator<Item = usize> + '_ {
+        self.frames.iter().filter(|d| d.dropped).map(|d| d.frame)
+    }
+
+    /// Whether `frame` is kept, which is true for frames outside the report.
+    pub fn is_save_frame(&self, frame: usize) -> bool {
+        self.frames
+            .binary_search_by_key(&frame, |d| d.frame)
+            .map_or(true, |i| !self.frames[i].dropped)
+    }
+
+    /// Puts [`EditFlag::MARK_FRAME`] on the duplicates, after saving the undo state.
+    pub fn mark(&self, editing: &Editing) -> Result<()> {
+        editing.insert_edit_flag(self.duplicates(), EditFlag::MARK_FRAME)?;
+        Ok(())
+    }
+
+    /// Sets [`EditFlag::DEL_FRAME`] on the dropped frames and clears it on the kept frames of the report, after saving
+    /// the undo state.
+    pub fn apply(&self, editing: &Editing) -> Result<()> {
+        let (Some(first), Some(last)) = (self.frames.first(), self.frames.last()) else {
+            return Ok(());
+        };
+        editing.update_frame_status(first.frame..=last.frame, |frame, status| {
+            if let Ok(i) = self.frames.binary_search_by_key(&frame, |d| d.frame) {
+                status
+                    .edit_flag
+                    .set(EditFlag::DEL_FRAME, self.frames[i].dropped);
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecimationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frame\tmean\tmax_block\tduplicate\tdecision")?;
+        for decision in &self.frames {
+            match decision.difference {
+                Some(difference) => write!(
+                    f,
+                    "{}\t{:.5}\t{:.5}",
+                    decision.frame, difference.mean, difference.max_block
+                )?,
+                None => write!(f, "{}\t-\t-", decision.frame)?,
+            }
+            writeln!(
+                f,
+                "\t{}\t{}",
+                decision.duplicate,
+                if decision.dropped { "drop" } else { "keep" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// State of a detection over consecutive frames.
+#[derive(Debug)]
+pub struct DecimationScan<'d> {
+    decimator: &'d Decimator,
+    previous: Option<OwnedFrame>,
+    /// Decisions of the current cycle, not yet dropped by the pattern.
+    pending: Vec<FrameDecision>,
+    report: DecimationReport,
+}
+
+impl Decimator {
+    pub fn start(&self) -> DecimationScan<'_> {
+        DecimationScan {
+            decimator: self,
+            previous: None,
+            pending: vec![],
+            report: DecimationReport::default(),
+        }
+    }
+
+    /// Detects duplicates in `frames` and decides the frames to drop, calling `progress` with the frames done and all
+    /// before each frame. It fails with [`AviUtlError::Cancelled`] if `progress` breaks.
+    pub fn analyze(
+        &self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+        mut progress: impl FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> Result<DecimationReport> {
+        if let DecimationMode::Cycle { cycle, drop } = self.mode {
+            if drop >= cycle {
+                return Err(AviUtlError::Unsupported(format!(
+                    "dropping {} in {} frames",
+                    drop, cycle
+                )));
+            }
+        }
+        let total = frames.clone().count();
+        let mut scan = self.start();
+        for (done, index) in frames.enumerate() {
+            if progress(done, total).is_break() {
+                return Err(AviUtlError::Cancelled);
+            }
+            scan.push(index, &editing.get_frame_from(self.source, index)?);
+        }
+        let _ = progress(total, total);
+        Ok(scan.finish())
+    }
+}
+
+impl DecimationScan<'_> {
+    /// Compares `frame` at `index` with the frame pushed before.
+    pub fn push(&mut self, index: usize, frame: &impl Frame) {
+        let decimator = self.decimator;
+        let difference = self.previous.as_ref().map(|previous| {
+            FrameDifference::new(previous, frame, decimator.block_size, decimator.noise)
+        });
+        self.previous = Some(OwnedFrame::from_frame(frame));
+        let duplicate = difference.is_some_and(|d| d.max_block <= decimator.threshold);
+        let decision = FrameDecision {
+            frame: index,
+            difference,
+            duplicate,
+            dropped: duplicate && decimator.mode == DecimationMode::Duplicates,
+        };
+        match decimator.mode {
+            DecimationMode::Cycle { cycle, .. } => {
+                self.pending.push(decision);
+                if self.pending.len() >= cycle.max(1) {
+                    self.close_cycle();
+                }
+            }
+            _ => self.report.frames.push(decision),
+        }
+    }
+
+    /// Drops the most similar frames of the current cycle, never the first frame.
+    fn close_cycle(&mut self) {
+        if let DecimationMode::Cycle { cycle, drop } = self.decimator.mode {
+            // A partial cycle at the end drops in proportion, rounding down.
+            let drop = (drop * self.pending.len() / cycle.max(1)).min(self.pending.len());
+            let mut order: Vec<usize> = (0..self.pending.len())
+                .filter(|&i| self.pending[i].difference.is_some())
+                .collect();
+            order.sort_by(|&a, &b| {
+                let key = |i: usize| self.pending[i].difference.map_or(1.0, |d| d.max_block);
+                key(a).total_cmp(&key(b))
+            });
+            for &i in order.iter().take(drop) {
+                self.pending[i].dropped = true;
+            }
+        }
+        self.report.frames.append(&mut self.pending);
+    }
+
+    /// Ends the detection, deciding the last partial cycle.
+    pub fn finish(mut self) -> DecimationReport {
+        self.close_cycle();
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::tests::padded_frame, Size};
+
+    const SIZE: Size = Size {
+        width: 48,
+        height: 36,
+    };
+
+    /// The film frame `content`, plus `delta` at each pixel.
+    fn frame(content: usize, delta: impl Fn(usize, usize) -> i16) -> OwnedFrame {
+        padded_frame(SIZE, |x, y| {
+            ((x * 40 + y * 30 + content * 500) % 4096) as i16 + delta(x, y)
+        })
+    }
+
+    /// Decisions on 12 frames repeating every fifth frame, as left by 3:2 pulldown after field matching.
+    fn report(mode: DecimationMode) -> DecimationReport {
+        let decimator = Decimator {
+            mode,
+            ..Default::default()
+        };
+        let mut scan = decimator.start();
+        for index in 0..12 {
+            let content = index - (index + 2) / 5;
+            scan.push(index, &frame(content, |_, _| 0));
+        }
+        scan.finish()
+    }
+
+    #[test]
+    fn drops_duplicates() {
+        let report = report(DecimationMode::Duplicates);
+        assert_eq!(report.duplicates().collect::<Vec<_>>(), [3, 8]);
+        assert_eq!(report.dropped().collect::<Vec<_>>(), [3, 8]);
+        assert!(!report.is_save_frame(3) && report.is_save_frame(4) && report.is_save_frame(100));
+    }
+
+    #[test]
+    fn drops_one_in_cycle() {
+        let cycle = DecimationMode::Cycle { cycle: 5, drop: 1 };
+        let report = report(cycle);
+        // The partial cycle of the last two frames drops nothing.
+        assert_eq!(report.dropped().collect::<Vec<_>>(), [3, 8]);
+        assert_eq!(report.frames.len(), 12);
+    }
+
+    #[test]
+    fn noise_is_tolerated() {
+        let decimator = Decimator::default();
+        let jitter = frame(0, |x, y| if (x + y) % 2 == 0 { 10 } else { -10 });
+        let difference = FrameDifference::new(&frame(0, |_, _| 0), &jitter, 32, decimator.noise);
+        assert_eq!(difference.max_block, 0.0);
+        let difference = FrameDifference::new(&frame(0, |_, _| 0), &jitter, 32, 0);
+        assert!((difference.max_block - 10.0 / PixelYc::Y_MAX as f32).abs() < 1e-6);
+    }
+
+    #[test]
+    fn small_change_is_found_by_block() {
+        let decimator = Decimator {
+            block_size: 8,
+            ..Default::default()
+        };
+        let changed = frame(0, |x, y| if x < 4 && y < 4 { 1000 } else { 0 });
+        let mut scan = decimator.start();
+        scan.push(0, &frame(0, |_, _| 0));
+        scan.push(1, &changed);
+        let decision = scan.finish().frames[1];
+        let difference = decision.difference.unwrap();
+        // The change is lost in the mean over the frame, but not in its block.
+        assert!(difference.mean < decimator.threshold);
+        assert!(difference.max_block > decimator.threshold);
+        assert!(!decision.duplicate);
+    }
+}
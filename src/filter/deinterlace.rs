@@ -0,0 +1,459 @@
+//! Host-independent deinterlacing of [`Frame`]s.
+//!
+//! Line 0 belongs to the top field. [`Deinterlacer`] keeps the lines of one field and fills the others by the
+//! chosen [`Method`]. Temporal methods also look at the previous and next frames, which may be `cur` itself at the
+//! ends of the clip.
+
+use super::{planar::Plane, Frame, OwnedFrame, ProcInfoFlag};
+use crate::{
+    executor::{for_each_line_mut, Executor, SingleThread},
+    AviUtlError, PixelYc, Result, Size,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    #[default]
+    Top,
+    Bottom,
+}
+
+impl Field {
+    pub const fn opposite(self) -> Self {
+        match self {
+            Field::Top => Field::Bottom,
+            Field::Bottom => Field::Top,
+        }
+    }
+
+    pub const fn contains(self, y: usize) -> bool {
+        y.is_multiple_of(2) == matches!(self, Field::Top)
+    }
+
+    /// The field to keep by `flags`, which is the first field unless [`ProcInfoFlag::INVERT_INTERLACE`].
+    pub fn kept(flags: ProcInfoFlag) -> Self {
+        let first = FieldOrder::from_flags(flags).first();
+        if flags.contains(ProcInfoFlag::INVERT_INTERLACE) {
+            first.opposite()
+        } else {
+            first
+        }
+    }
+}
+
+/// Temporal order of the fields in a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldOrder {
+    #[default]
+    TopFirst,
+    BottomFirst,
+}
+
+impl FieldOrder {
+    /// Top field first, unless [`ProcInfoFlag::INVERT_FIELD_ORDER`].
+    pub fn from_flags(flags: ProcInfoFlag) -> Self {
+        if flags.contains(ProcInfoFlag::INVERT_FIELD_ORDER) {
+            FieldOrder::BottomFirst
+        } else {
+            FieldOrder::TopFirst
+        }
+    }
+
+    pub const fn first(self) -> Field {
+        match self {
+            FieldOrder::TopFirst => Field::Top,
+            FieldOrder::BottomFirst => Field::Bottom,
+        }
+    }
+}
+
+/// Splits `frame` into the top field and the bottom field, of half height each.
+pub fn split_fields(frame: &impl Frame) -> (OwnedFrame, OwnedFrame) {
+    let Size { width, height } = frame.frame_size();
+    let mut top = OwnedFrame::new(Size {
+        width,
+        height: height.div_ceil(2),
+    });
+    let mut bottom = OwnedFrame::new(Size {
+        width,
+        height: height / 2,
+    });
+    for (y, line) in top.lines_mut().enumerate() {
+        line.copy_from_slice(frame.line(2 * y));
+    }
+    for (y, line) in bottom.lines_mut().enumerate() {
+        line.copy_from_slice(frame.line(2 * y + 1));
+    }
+    (top, bottom)
+}
+
+/// Interleaves the lines of `top` and `bottom` into `frame`. The top field must have as many lines as the bottom
+/// field or one more.
+pub fn weave(frame: &mut impl Frame, top: &impl Frame, bottom: &impl Frame) -> Result<()> {
+    let (top_size, bottom_size) = (top.frame_size(), bottom.frame_size());
+    if top_size.width != bottom_size.width
+        || !(bottom_size.height..=bottom_size.height + 1).contains(&top_size.height)
+    {
+        return Err(AviUtlError::Unsupported(format!(
+            "weaving fields of {:?} and {:?}",
+            top_size, bottom_size
+        )));
+    }
+    frame.set_frame_size(Size {
+        width: top_size.width,
+        height: top_size.height + bottom_size.height,
+    })?;
+    for (y, line) in frame.lines_mut().enumerate() {
+        let field: &dyn Frame = if y.is_multiple_of(2) { top } else { bottom };
+        line.copy_from_slice(field.line(y / 2));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    /// Interpolates the missing lines linearly from the lines above and below.
+    Bob,
+    /// Filters all lines vertically by `[1, 2, 1] / 4`, mixing both fields.
+    Blend,
+    /// Edge-directed interpolation limited by the temporal difference, in the style of YADIF. The spatial check
+    /// additionally protects details against the lines two above and below.
+    Yadif { spatial_check: bool },
+}
+
+impl Default for Method {
+    fn default() -> Self {
+        Method::Yadif {
+            spatial_check: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Deinterlacer {
+    pub method: Method,
+    pub order: FieldOrder,
+    /// The field whose lines are kept.
+    pub field: Field,
+}
+
+impl Deinterlacer {
+    /// Creates a deinterlacer with the field order and the kept field from `flags` of [`super::ProcInfo`].
+    pub fn from_flags(method: Method, flags: ProcInfoFlag) -> Self {
+        Self {
+            method,
+            order: FieldOrder::from_flags(flags),
+            field: Field::kept(flags),
+        }
+    }
+
+    /// Deinterlaces `cur` into `frame`, referring to `prev` and `next` for temporal methods. All of them must be the
+    /// same size.
+    pub fn deinterlace(
+        &self,
+        frame: &mut impl Frame,
+        prev: &(impl Frame + Sync),
+        cur: &(impl Frame + Sync),
+        next: &(impl Frame + Sync),
+    ) -> Result<()> {
+        self.deinterlace_with(&SingleThread, frame, prev, cur, next)
+    }
+
+    /// [`Deinterlacer::deinterlace`] on the threads of `executor`.
+    pub fn deinterlace_with(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        prev: &(impl Frame + Sync),
+        cur: &(impl Frame + Sync),
+        next: &(impl Frame + Sync),
+    ) -> Result<()> {
+        let size = cur.frame_size();
+        if prev.frame_size() != size || next.frame_size() != size {
+            return Err(AviUtlError::Unsupported(
+                "deinterlacing frames of different sizes".into(),
+            ));
+        }
+        frame.set_frame_size(size)?;
+        for_each_line_mut(executor, frame, |y, line| match self.method {
+            _ if self.field.contains(y) && self.method != Method::Blend => {
+                line.copy_from_slice(cur.line(y));
+            }
+            Method::Blend => {
+                let (above, center, below) = (
+                    line_at(cur, y as isize - 1),
+                    cur.line(y),
+                    line_at(cur, y as isize + 1),
+                );
+                for (x, pixel) in line.iter_mut().enumerate() {
+                    for plane in Plane::ALL {
+                        let sum = plane.get(&above[x]) as i32
+                            + 2 * plane.get(&center[x]) as i32
+                            + plane.get(&below[x]) as i32;
+                        *plane.get_mut(pixel) = ((sum + 2) >> 2) as i16;
+                    }
+                }
+            }
+            Method::Bob => {
+                let (above, below) = (line_at(cur, y as isize - 1), line_at(cur, y as isize + 1));
+                for (x, pixel) in line.iter_mut().enumerate() {
+                    for plane in Plane::ALL {
+                        let sum = plane.get(&above[x]) as i32 + plane.get(&below[x]) as i32;
+                        *plane.get_mut(pixel) = ((sum + 1) >> 1) as i16;
+                    }
+                }
+            }
+            Method::Yadif { spatial_check } => {
+                // The missing field of the frame which is temporally symmetric around the kept field.
+                let (prev2, next2): (&(dyn Frame + Sync), &(dyn Frame + Sync)) =
+                    if self.field == self.order.first() {
+                        (prev, cur)
+                    } else {
+                        (cur, next)
+                    };
+                let lines = YadifLines {
+                    cur_above: line_at(cur, y as isize - 1),
+                    cur_below: line_at(cur, y as isize + 1),
+                    prev_above: line_at(prev, y as isize - 1),
+                    prev_below: line_at(prev, y as isize + 1),
+                    next_above: line_at(next, y as isize - 1),
+                    next_below: line_at(next, y as isize + 1),
+                    prev2: prev2.line(y),
+                    next2: next2.line(y),
+                    prev2_above2: line_at(prev2, y as isize - 2),
+                    prev2_below2: line_at(prev2, y as isize + 2),
+                    next2_above2: line_at(next2, y as isize - 2),
+                    next2_below2: line_at(next2, y as isize + 2),
+                };
+                for (x, pixel) in line.iter_mut().enumerate() {
+                    for plane in Plane::ALL {
+                        *plane.get_mut(pixel) = lines.interpolate(plane, x, spatial_check);
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Line `y` of `frame`, reflected by two lines at the edges to keep the field.
+fn line_at(frame: &(dyn Frame + Sync), y: isize) -> &[PixelYc] {
+    let height = frame.frame_size().height as isize;
+    let y = if y < 0 {
+        y + 2
+    } else if y >= height {
+        y - 2
+    } else {
+        y
+    };
+    frame.line(y.clamp(0, height - 1) as usize)
+}
+
+/// Lines around a missing line for YADIF.
+struct YadifLines<'a> {
+    cur_above: &'a [PixelYc],
+    cur_below: &'a [PixelYc],
+    prev_above: &'a [PixelYc],
+    prev_below: &'a [PixelYc],
+    next_above: &'a [PixelYc],
+    next_below: &'a [PixelYc],
+    prev2: &'a [PixelYc],
+    next2: &'a [PixelYc],
+    prev2_above2: &'a [PixelYc],
+    prev2_below2: &'a [PixelYc],
+    next2_above2: &'a [PixelYc],
+    next2_below2: &'a [PixelYc],
+}
+
+impl YadifLines<'_> {
+    fn interpolate(&self, plane: Plane, x: usize, spatial_check: bool) -> i16 {
+        let width = self.cur_above.len() as isize;
+        let at =
+            |line: &[PixelYc], x: isize| plane.get(&line[x.clamp(0, width - 1) as usize]) as i32;
+        let x = x as isize;
+        let (c, e) = (at(self.cur_above, x), at(self.cur_below, x));
+        let d = (at(self.prev2, x) + at(self.next2, x)) >> 1;
+        let temporal_diff0 = (at(self.prev2, x) - at(self.next2, x)).abs();
+        let temporal_diff1 =
+            ((at(self.prev_above, x) - c).abs() + (at(self.prev_below, x) - e).abs()) >> 1;
+        let temporal_diff2 =
+            ((at(self.next_above, x) - c).abs() + (at(self.next_below, x) - e).abs()) >> 1;
+        let mut diff = (temporal_diff0 >> 1)
+            .max(temporal_diff1)
+            .max(temporal_diff2);
+
+        let score = |j: isize| {
+            (at(self.cur_above, x - 1 + j) - at(self.cur_below, x - 1 - j)).abs()
+                + (at(self.cur_above, x + j) - at(self.cur_below, x - j)).abs()
+                + (at(self.cur_above, x + 1 + j) - at(self.cur_below, x + 1 - j)).abs()
+        };
+        let mut spatial_pred = (c + e) >> 1;
+        let mut spatial_score = score(0) - 1;
+        // Follows the edge direction further only while the score improves.
+        for direction in [-1, 1] {
+            for j in [direction, 2 * direction] {
+                let candidate = score(j);
+                if candidate >= spatial_score {
+                    break;
+                }
+                spatial_score = candidate;
+                spatial_pred = (at(self.cur_above, x + j) + at(self.cur_below, x - j)) >> 1;
+            }
+        }
+
+        if spatial_check {
+            let b = (at(self.prev2_above2, x) + at(self.next2_above2, x)) >> 1;
+            let f = (at(self.prev2_below2, x) + at(self.next2_below2, x)) >> 1;
+            let max = (d - e).max(d - c).max((b - c).min(f - e));
+            let min = (d - e).min(d - c).min((b - c).max(f - e));
+            diff = diff.max(min).max(-max);
+        }
+        let range = plane.range();
+        spatial_pred
+            .clamp(d - diff, d + diff)
+            .clamp(*range.start() as i32, *range.end() as i32) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::padded_frame;
+
+    const SIZE: Size = Size {
+        width: 3,
+        height: 6,
+    };
+
+    fn lumas(frame: &impl Frame) -> Vec<i16> {
+        frame.lines().map(|line| line[0].y).collect()
+    }
+
+    fn deinterlace(
+        deinterlacer: Deinterlacer,
+        prev: &OwnedFrame,
+        cur: &OwnedFrame,
+        next: &OwnedFrame,
+    ) -> Vec<i16> {
+        let mut frame = OwnedFrame::new(SIZE);
+        deinterlacer
+            .deinterlace(&mut frame, prev, cur, next)
+            .unwrap();
+        assert!(frame
+            .lines()
+            .all(|line| line.iter().all(|pixel| pixel == &line[0])));
+        lumas(&frame)
+    }
+
+    #[test]
+    fn flags_choose_fields() {
+        let invert_order = ProcInfoFlag::INVERT_FIELD_ORDER;
+        let invert_field = ProcInfoFlag::INVERT_INTERLACE;
+        for (flags, order, field) in [
+            (ProcInfoFlag::empty(), FieldOrder::TopFirst, Field::Top),
+            (invert_order, FieldOrder::BottomFirst, Field::Bottom),
+            (invert_field, FieldOrder::TopFirst, Field::Bottom),
+            (
+                invert_order | invert_field,
+                FieldOrder::BottomFirst,
+                Field::Top,
+            ),
+        ] {
+            let deinterlacer = Deinterlacer::from_flags(Method::Bob, flags);
+            assert_eq!(
+                (deinterlacer.order, deinterlacer.field),
+                (order, field),
+                "{:?}",
+                flags
+            );
+        }
+        assert!(Field::Top.contains(0) && Field::Bottom.contains(1));
+    }
+
+    #[test]
+    fn fields_split_and_weave() {
+        let size = Size {
+            width: 3,
+            height: 5,
+        };
+        let frame = padded_frame(size, |x, y| (y * 100 + x) as i16);
+        let (top, bottom) = split_fields(&frame);
+        assert_eq!(lumas(&top), [0, 200, 400]);
+        assert_eq!(lumas(&bottom), [100, 300]);
+
+        let mut woven = OwnedFrame::new(SIZE);
+        weave(&mut woven, &top, &bottom).unwrap();
+        assert_eq!(woven.frame_size(), size);
+        for y in 0..5 {
+            assert_eq!(woven.line(y), frame.line(y));
+        }
+        assert!(weave(&mut woven, &bottom, &top).is_err());
+    }
+
+    #[test]
+    fn bob_interpolates_missing_lines() {
+        let ramp = padded_frame(SIZE, |_, y| (y * 100) as i16);
+        let bob = Deinterlacer {
+            method: Method::Bob,
+            ..Deinterlacer::default()
+        };
+        // The last line is reflected onto the kept line above it.
+        assert_eq!(
+            deinterlace(bob, &ramp, &ramp, &ramp),
+            [0, 100, 200, 300, 400, 400]
+        );
+        let bob = Deinterlacer {
+            field: Field::Bottom,
+            ..bob
+        };
+        assert_eq!(
+            deinterlace(bob, &ramp, &ramp, &ramp),
+            [100, 100, 200, 300, 400, 500]
+        );
+    }
+
+    #[test]
+    fn blend_removes_combing() {
+        let comb = padded_frame(SIZE, |_, y| if y % 2 == 0 { 0 } else { 4000 });
+        let blend = Deinterlacer {
+            method: Method::Blend,
+            ..Deinterlacer::default()
+        };
+        assert_eq!(deinterlace(blend, &comb, &comb, &comb), [2000; 6]);
+    }
+
+    #[test]
+    fn yadif_weaves_static_and_interpolates_motion() {
+        let ramp = padded_frame(SIZE, |_, y| (y * 100) as i16);
+        let detail = padded_frame(SIZE, |_, y| [0, 900, 200, 50, 400, 1000][y]);
+        for spatial_check in [false, true] {
+            let yadif = Deinterlacer {
+                method: Method::Yadif { spatial_check },
+                ..Deinterlacer::default()
+            };
+            // The last line has no kept line below it, where the spatial check sees combing.
+            assert_eq!(
+                deinterlace(yadif, &ramp, &ramp, &ramp)[..5],
+                lumas(&ramp)[..5]
+            );
+
+            // Only the kept field of the current frame shows an object, which is absent from the neighbors.
+            let background = padded_frame(SIZE, |_, _| 3000);
+            let moving = padded_frame(SIZE, |_, y| if y % 2 == 0 { 1000 } else { 3000 });
+            assert_eq!(
+                deinterlace(yadif, &background, &moving, &background),
+                [1000; 6]
+            );
+
+            // A static spike out of both neighbors is kept as is, unless the spatial check takes it for combing.
+            let spike = deinterlace(yadif, &detail, &detail, &detail)[1];
+            assert_eq!(spike, if spatial_check { 200 } else { 900 });
+        }
+        let mut frame = OwnedFrame::new(SIZE);
+        let small = OwnedFrame::new(Size {
+            width: 3,
+            height: 4,
+        });
+        assert!(Deinterlacer::default()
+            .deinterlace(&mut frame, &small, &ramp, &ramp)
+            .is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! Host-independent anti-aliased drawing on [`Frame`]s.
+//!
+//! Unlike [`Editing::draw_text`], which goes through GDI in AviUtl, [`Painter`] rasterizes shapes in pure Rust. Every
+//! shape is converted into a [`Path`] of polygons, and the coverage of each pixel is computed by sub-scanlines with
+//! exact horizontal spans.
+//!
+//! Coordinates are in pixels, where the center of the top-left pixel is at `(0.5, 0.5)`.
+//!
+//! [`Editing::draw_text`]: super::editing::Editing::draw_text
+
+use super::{composite::OPAQUE, Frame};
+use crate::{colorspace::ColorSpace, pixel::lerp, PixelRgb, Point, Rect};
+use std::f32::consts::TAU;
+
+/// Sub-scanlines per pixel row.
+const SUBSAMPLES: usize = 5;
+
+/// A point in sub-pixel precision.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PointF {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl PointF {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Center of the pixel at `point`.
+    pub fn pixel_center(point: Point) -> Self {
+        Self {
+            x: point.x as f32 + 0.5,
+            y: point.y as f32 + 0.5,
+        }
+    }
+}
+
+impl From<Point> for PointF {
+    fn from(point: Point) -> Self {
+        Self {
+            x: point.x as f32,
+            y: point.y as f32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// A set of closed polygons.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Path {
+    contours: Vec<Vec<PointF>>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a polygon, which is closed implicitly.
+    pub fn add_polygon(&mut self, points: &[PointF]) {
+        if points.len() >= 3 {
+            self.contours.push(points.to_vec());
+        }
+    }
+
+    /// Adds a circle approximated by a polygon fine enough for anti-aliasing. It is clockwise unless `reversed`.
+    pub fn add_circle(&mut self, center: PointF, radius: f32, reversed: bool) {
+        if radius <= 0.0 {
+            return;
+        }
+        let segments = ((TAU * radius).ceil() as usize).clamp(16, 1024);
+        let direction = if reversed { -1.0 } else { 1.0 };
+        self.contours.push(
+            (0..segments)
+                .map(|i| {
+                    let angle = direction * TAU * i as f32 / segments as f32;
+                    PointF::new(
+                        center.x + radius * angle.cos(),
+                        center.y + radius * angle.sin(),
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    pub fn contours(&self) -> &[Vec<PointF>] {
+        &self.contours
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (PointF, PointF)> + '_ {
+        self.contours.iter().flat_map(|contour| {
+            contour
+                .iter()
+                .zip(contour.iter().cycle().skip(1))
+                .map(|(&a, &b)| (a, b))
+        })
+    }
+}
+
+/// Signed area of a polygon, which is positive if clockwise on the screen.
+fn signed_area(points: &[PointF]) -> f32 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        / 2.0
+}
+
+/// Draws shapes by a solid color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Painter {
+    pub color: PixelRgb,
+    /// Opacity between 0 and [`OPAQUE`].
+    pub opacity: u16,
+    /// Width of strokes in pixels.
+    pub stroke_width: f32,
+    pub fill_rule: FillRule,
+    /// The color space to convert `color` into YC.
+    pub color_space: ColorSpace,
+}
+
+impl Default for Painter {
+    fn default() -> Self {
+        Self {
+            color: PixelRgb::default(),
+            opacity: OPAQUE,
+            stroke_width: 1.0,
+            fill_rule: FillRule::default(),
+            color_space: ColorSpace::AVIUTL,
+        }
+    }
+}
+
+impl Painter {
+    pub fn new(color: PixelRgb) -> Self {
+        Self {
+            color,
+            ..Self::default()
+        }
+    }
+
+    /// Fills `path` by [`Painter::fill_rule`].
+    pub fn fill_path(&self, frame: &mut impl Frame, path: &Path) {
+        self.rasterize(frame, path, self.fill_rule);
+    }
+
+    fn rasterize(&self, frame: &mut impl Frame, path: &Path, fill_rule: FillRule) {
+        let size = frame.frame_size();
+        let points = || path.contours.iter().flatten();
+        let top = points().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let bottom = points().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let left = points().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let right = points().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        if !(top < bottom && left < right) {
+            return;
+        }
+        let rows =
+            (top.floor().max(0.0) as usize)..(bottom.ceil().min(size.height as f32) as usize);
+        let columns =
+            (left.floor().max(0.0) as usize)..(right.ceil().min(size.width as f32) as usize);
+        if rows.is_empty() || columns.is_empty() {
+            return;
+        }
+        let color = self.color_space.rgb_to_yc(self.color);
+        let opacity = self.opacity.min(OPAQUE) as f32 / OPAQUE as f32;
+        let mut coverage = vec![0.0f32; columns.len()];
+        let mut crossings = vec![];
+        for y in rows {
+            coverage.fill(0.0);
+            for sub in 0..SUBSAMPLES {
+                let scan_y = y as f32 + (sub as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for (a, b) in path.edges() {
+                    if (a.y <= scan_y) != (b.y <= scan_y) {
+                        let x = a.x + (scan_y - a.y) * (b.x - a.x) / (b.y - a.y);
+                        crossings.push((x, if a.y < b.y { 1 } else { -1 }));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if fill_rule.is_inside(winding) {
+                        add_span(&mut coverage, columns.start, pair[0].0, pair[1].0);
+                    }
+                }
+            }
+            let line = &mut frame.line_mut(y)[columns.clone()];
+            for (pixel, &covered) in line.iter_mut().zip(&coverage) {
+                let alpha = (covered / SUBSAMPLES as f32).min(1.0) * opacity;
+                if alpha > 0.0 {
+                    *pixel = lerp(*pixel, color, alpha);
+                }
+            }
+        }
+    }
+
+    pub fn fill_polygon(&self, frame: &mut impl Frame, points: &[PointF]) {
+        let mut path = Path::new();
+        path.add_polygon(points);
+        self.fill_path(frame, &path);
+    }
+
+    /// Strokes connected segments with butt caps and round joins. The end is connected to the start if `closed`.
+    pub fn stroke_polyline(&self, frame: &mut impl Frame, points: &[PointF], closed: bool) {
+        let half = self.stroke_width / 2.0;
+        if half <= 0.0 || points.len() < 2 {
+            return;
+        }
+        let mut path = Path::new();
+        let segments = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+        for i in 0..segments {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let (dx, dy) = (b.x - a.x, b.y - a.y);
+            let length = dx.hypot(dy);
+            if length == 0.0 {
+                continue;
+            }
+            let (nx, ny) = (-dy / length * half, dx / length * half);
+            let mut quad = [
+                PointF::new(a.x + nx, a.y + ny),
+                PointF::new(b.x + nx, b.y + ny),
+                PointF::new(b.x - nx, b.y - ny),
+                PointF::new(a.x - nx, a.y - ny),
+            ];
+            // Every part is clockwise, so overlaps are drawn once by the non-zero rule.
+            if signed_area(&quad) < 0.0 {
+                quad.reverse();
+            }
+            path.add_polygon(&quad);
+        }
+        let joins = if closed {
+            points
+        } else {
+            &points[1..points.len() - 1]
+        };
+        for &point in joins {
+            path.add_circle(point, half, false);
+        }
+        self.rasterize(frame, &path, FillRule::NonZero);
+    }
+
+    pub fn line(&self, frame: &mut impl Frame, from: PointF, to: PointF) {
+        self.stroke_polyline(frame, &[from, to], false);
+    }
+
+    pub fn stroke_polygon(&self, frame: &mut impl Frame, points: &[PointF]) {
+        self.stroke_polyline(frame, points, true);
+    }
+
+    pub fn fill_rect(&self, frame: &mut impl Frame, rect: Rect) {
+        let (left, top) = (rect.left() as f32, rect.top() as f32);
+        let (right, bottom) = (rect.right() as f32, rect.bottom() as f32);
+        self.fill_polygon(
+            frame,
+            &[
+                PointF::new(left, top),
+                PointF::new(right, top),
+                PointF::new(right, bottom),
+                PointF::new(left, bottom),
+            ],
+        );
+    }
+
+    /// Strokes the outline through the centers of the edge pixels of `rect` with mitered corners, so that a stroke
+    /// of odd width covers whole pixels.
+    pub fn stroke_rect(&self, frame: &mut impl Frame, rect: Rect) {
+        let half = self.stroke_width / 2.0;
+        if half <= 0.0 || rect.size.width == 0 || rect.size.height == 0 {
+            return;
+        }
+        let (left, top) = (rect.left() as f32 + 0.5, rect.top() as f32 + 0.5);
+        let (right, bottom) = (rect.right() as f32 - 0.5, rect.bottom() as f32 - 0.5);
+        let mut path = Path::new();
+        path.add_polygon(&[
+            PointF::new(left - half, top - half),
+            PointF::new(right + half, top - half),
+            PointF::new(right + half, bottom + half),
+            PointF::new(left - half, bottom + half),
+        ]);
+        if left + half < right - half && top + half < bottom - half {
+            path.add_polygon(&[
+                PointF::new(left + half, top + half),
+                PointF::new(left + half, bottom - half),
+                PointF::new(right - half, bottom - half),
+                PointF::new(right - half, top + half),
+            ]);
+        }
+        self.rasterize(frame, &path, FillRule::NonZero);
+    }
+
+    pub fn fill_circle(&self, frame: &mut impl Frame, center: PointF, radius: f32) {
+        let mut path = Path::new();
+        path.add_circle(center, radius, false);
+        self.rasterize(frame, &path, FillRule::NonZero);
+    }
+
+    /// Strokes the circle of `radius`, centered on the stroke.
+    pub fn stroke_circle(&self, frame: &mut impl Frame, center: PointF, radius: f32) {
+        let half = self.stroke_width / 2.0;
+        let mut path = Path::new();
+        path.add_circle(center, radius + half, false);
+        path.add_circle(center, radius - half, true);
+        self.rasterize(frame, &path, FillRule::NonZero);
+    }
+}
+
+/// Adds the coverage of the span `from..to` into pixels starting at `offset`.
+fn add_span(coverage: &mut [f32], offset: usize, from: f32, to: f32) {
+    let from = (from - offset as f32).max(0.0);
+    let to = (to - offset as f32).min(coverage.len() as f32);
+    if from >= to {
+        return;
+    }
+    for (x, covered) in coverage
+        .iter_mut()
+        .enumerate()
+        .take(to.ceil() as usize)
+        .skip(from.floor() as usize)
+    {
+        *covered += to.min(x as f32 + 1.0) - from.max(x as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::tests::padded_frame, PixelYc, Size};
+
+    const SIZE: Size = Size {
+        width: 8,
+        height: 8,
+    };
+
+    /// A white frame to be painted in black, whose padding stays white.
+    fn canvas() -> impl Frame {
+        padded_frame(SIZE, |_, _| PixelYc::Y_MAX)
+    }
+
+    /// How much each pixel is painted, between 0 and 1.
+    fn coverage(frame: &impl Frame) -> Vec<Vec<f32>> {
+        frame
+            .lines()
+            .map(|line| {
+                line.iter()
+                    .map(|pixel| 1.0 - pixel.y as f32 / PixelYc::Y_MAX as f32)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn painted(frame: &impl Frame) -> Vec<(usize, usize)> {
+        let mut painted = vec![];
+        for (y, line) in coverage(frame).into_iter().enumerate() {
+            for (x, covered) in line.into_iter().enumerate() {
+                if covered > 0.5 {
+                    painted.push((x, y));
+                }
+            }
+        }
+        painted
+    }
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            size: Size { width, height },
+            point: Point { x, y },
+        }
+    }
+
+    #[test]
+    fn spans_cover_fractions() {
+        let mut coverage = vec![0.0; 4];
+        add_span(&mut coverage, 10, 10.25, 12.5);
+        assert_eq!(coverage, [0.75, 1.0, 0.5, 0.0]);
+        add_span(&mut coverage, 10, 5.0, 20.0);
+        assert_eq!(coverage, [1.75, 2.0, 1.5, 1.0]);
+    }
+
+    #[test]
+    fn rects_fill_whole_pixels() {
+        let mut frame = canvas();
+        Painter::default().fill_rect(&mut frame, rect(2, 1, 3, 2));
+        let expected: Vec<_> = (1..3).flat_map(|y| (2..5).map(move |x| (x, y))).collect();
+        assert_eq!(painted(&frame), expected);
+        assert!(coverage(&frame)
+            .into_iter()
+            .flatten()
+            .all(|covered| covered == 0.0 || covered == 1.0));
+        assert!(frame
+            .image()
+            .iter()
+            .skip(SIZE.width as usize)
+            .take(40)
+            .all(|p| p.y == PixelYc::Y_MAX));
+    }
+
+    #[test]
+    fn edges_are_anti_aliased() {
+        let mut frame = canvas();
+        Painter::default().fill_polygon(
+            &mut frame,
+            &[
+                PointF::new(1.5, 0.0),
+                PointF::new(4.0, 0.0),
+                PointF::new(4.0, 8.0),
+                PointF::new(1.5, 8.0),
+            ],
+        );
+        for line in coverage(&frame) {
+            assert_eq!(line[0], 0.0);
+            assert!((line[1] - 0.5).abs() < 1e-3, "{}", line[1]);
+            assert_eq!(&line[2..4], [1.0, 1.0]);
+            assert_eq!(line[4], 0.0);
+        }
+    }
+
+    #[test]
+    fn opacity_blends() {
+        let mut frame = canvas();
+        let painter = Painter {
+            opacity: OPAQUE / 4,
+            ..Painter::default()
+        };
+        painter.fill_rect(&mut frame, rect(0, 0, 8, 8));
+        assert!(coverage(&frame)
+            .into_iter()
+            .flatten()
+            .all(|covered| (covered - 0.25).abs() < 1e-3));
+    }
+
+    #[test]
+    fn shapes_are_clipped() {
+        let mut frame = canvas();
+        Painter::default().fill_rect(&mut frame, rect(-3, 6, 5, 10));
+        assert_eq!(painted(&frame), [(0, 6), (1, 6), (0, 7), (1, 7)]);
+        Painter::default().fill_circle(&mut frame, PointF::new(-10.0, -10.0), 3.0);
+        assert_eq!(painted(&frame).len(), 4);
+    }
+
+    #[test]
+    fn fill_rules_differ_for_nested_contours() {
+        let square = |from: f32, to: f32| {
+            [
+                PointF::new(from, from),
+                PointF::new(to, from),
+                PointF::new(to, to),
+                PointF::new(from, to),
+            ]
+        };
+        let mut path = Path::new();
+        path.add_polygon(&square(0.0, 8.0));
+        path.add_polygon(&square(2.0, 6.0));
+        for (fill_rule, hole) in [(FillRule::NonZero, false), (FillRule::EvenOdd, true)] {
+            let mut frame = canvas();
+            Painter {
+                fill_rule,
+                ..Painter::default()
+            }
+            .fill_path(&mut frame, &path);
+            assert_eq!(painted(&frame).len(), if hole { 64 - 16 } else { 64 });
+            assert!(painted(&frame).contains(&(1, 1)));
+            assert_eq!(painted(&frame).contains(&(3, 3)), !hole, "{:?}", fill_rule);
+        }
+    }
+
+    #[test]
+    fn circles_cover_their_area() {
+        let mut frame = canvas();
+        Painter::default().fill_circle(&mut frame, PointF::new(4.0, 4.0), 3.0);
+        let area: f32 = coverage(&frame).into_iter().flatten().sum();
+        assert!((area - 9.0 * std::f32::consts::PI).abs() < 0.5, "{}", area);
+
+        let mut frame = canvas();
+        Painter::default().stroke_circle(&mut frame, PointF::new(4.0, 4.0), 3.0);
+        let area: f32 = coverage(&frame).into_iter().flatten().sum();
+        assert!((area - TAU * 3.0).abs() < 0.5, "{}", area);
+        assert_eq!(coverage(&frame)[4][4], 0.0);
+    }
+
+    #[test]
+    fn strokes_cover_pixel_centers() {
+        let mut frame = canvas();
+        Painter::default().line(
+            &mut frame,
+            PointF::pixel_center(Point { x: 1, y: 2 }),
+            PointF::pixel_center(Point { x: 5, y: 2 }),
+        );
+        // Butt caps end at the centers, covering half of the end pixels.
+        let row = &coverage(&frame)[2];
+        assert_eq!(&row[2..5], [1.0, 1.0, 1.0]);
+        assert!((row[1] - 0.5).abs() < 1e-3 && (row[5] - 0.5).abs() < 1e-3);
+        assert_eq!(painted(&frame), [(2, 2), (3, 2), (4, 2)]);
+
+        let mut frame = canvas();
+        Painter::default().stroke_rect(&mut frame, rect(1, 1, 4, 3));
+        let mut expected = vec![];
+        for y in 1..4 {
+            for x in 1..5 {
+                if x == 1 || x == 4 || y == 1 || y == 3 {
+                    expected.push((x, y));
+                }
+            }
+        }
+        assert_eq!(painted(&frame), expected);
+        assert!(coverage(&frame)
+            .into_iter()
+            .flatten()
+            .all(|covered| covered == 0.0 || covered == 1.0));
+    }
+}
@@ -1,8 +1,12 @@
 use super::{
-    api::Api, file_info::FileInfo, frame_status::FrameStatus, sys_info::SysInfo, BorrowedMutFrame,
-    FileId, Frame, VideoId,
+    api::Api,
+    file_info::FileInfo,
+    frame_status::{FrameStatus, FrameStatusTable, StatusTableValue},
+    sys_info::SysInfo,
+    BorrowedMutFrame, EditFlag, FileId, Frame, VideoId,
 };
 use crate::{
+    audio::{AudioBuf, ChannelLayout},
     from_nullable_lpstr, into_win_str, AviUtlError, PixelFormat, PixelRgb, Point, Rect, Result,
     Size,
 };
@@ -18,11 +22,49 @@ pub use aviutl_plugin_sys::filter::EditOutputFlag as OutputFlag;
 pub use aviutl_plugin_sys::filter::FrameStatusType;
 use windows::Win32::Graphics::Gdi::HFONT;
 
+/// Which frames to read from the timeline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameSource {
+    /// Frames of the source files by [`Editing::get_source_frame`].
+    #[default]
+    Source,
+    /// Frames before the calling filter by [`Editing::get_yc_filtering`].
+    Filtering,
+}
+
 pub struct Editing<'a> {
     handle: *mut c_void,
     api: &'a Api<'a>,
 }
 
+/// Reads the audio of `frame` by `read` into `buf` in `layout`, where `read` returns the number of samples per channel
+/// and only counts them for null. It fails if nothing is read from a frame having audio.
+fn read_audio_with<N: TryInto<usize>>(
+    buf: &mut AudioBuf<i16>,
+    layout: ChannelLayout,
+    frame: usize,
+    read: impl Fn(*mut c_void) -> N,
+) -> Result<()> {
+    buf.set_layout(layout);
+    let expected = read(null_mut()).try_into().unwrap_or(0);
+    buf.resize(expected);
+    if expected == 0 {
+        return Ok(());
+    }
+    let read = read(buf.interleaved_mut().as_mut_ptr().cast())
+        .try_into()
+        .unwrap_or(0);
+    buf.resize(read.min(expected));
+    if read == 0 {
+        Err(AviUtlError::Unsupported(format!(
+            "reading audio of frame {}",
+            frame
+        )))
+    } else {
+        Ok(())
+    }
+}
+
 impl<'a> Drop for Editing<'a> {
     fn drop(&mut self) {
         todo!()
@@ -105,10 +147,33 @@ impl<'a> Editing<'a> {
         if ptr.is_null() {
             Err(AviUtlError::FrameIndexOutOfRange(frame))
         } else {
-            Ok(unsafe { BorrowedMutFrame::from_raw(ptr.cast(), self.frame_size()?) })
+            Ok(unsafe {
+                BorrowedMutFrame::from_raw_with_max_size(
+                    ptr.cast(),
+                    self.frame_size()?,
+                    self.cache_max_size()?,
+                )
+            })
         }
     }
 
+    /// Reads `frame` by [`Editing::get_source_frame`] or [`Editing::get_yc_filtering`] as `source`.
+    pub fn get_frame_from(
+        &self,
+        source: FrameSource,
+        frame: usize,
+    ) -> Result<BorrowedMutFrame<'_>> {
+        match source {
+            FrameSource::Source => self.get_source_frame(frame),
+            FrameSource::Filtering => self.get_yc_filtering(frame),
+        }
+    }
+
+    /// Size of the frame caches of AviUtl, whose lines are as long as the maximum width.
+    fn cache_max_size(&self) -> Result<Size> {
+        Ok(self.get_sys_info()?.max_size)
+    }
+
     pub fn get_source_frame(&self, frame: usize) -> Result<BorrowedMutFrame> {
         self.get_source_frame_from_avi(frame, 0)
     }
@@ -127,17 +192,21 @@ impl<'a> Editing<'a> {
         }
     }
 
-    pub fn get_audio(&self, frame: usize, buf: &mut [u8]) -> Result<usize> {
-        let expected_len =
-            unsafe { (self.api.exports.get_audio)(self.handle, frame as _, null_mut()) as usize };
-        if buf.len() < expected_len {
-            return Err(AviUtlError::BufferLimitExceed);
-        }
-        Ok(unsafe {
-            (self.api.exports.get_audio)(self.handle, frame as _, buf.as_mut_ptr().cast()) as usize
+    /// Reads the source audio of `frame` into `buf` in the layout of the editing file, resizing it to the samples of
+    /// the frame.
+    pub fn get_audio(&self, frame: usize, buf: &mut AudioBuf<i16>) -> Result<()> {
+        read_audio_with(buf, self.audio_layout()?, frame, |ptr| unsafe {
+            (self.api.exports.get_audio)(self.handle, frame as _, ptr)
         })
     }
 
+    /// Channels of the audio written by the host, which the buffers must be sized for.
+    fn audio_layout(&self) -> Result<ChannelLayout> {
+        Ok(ChannelLayout::from_channels(
+            self.get_file_info()?.audio_channels as u16,
+        ))
+    }
+
     pub fn is_editing(&self) -> bool {
         unsafe { (self.api.exports.is_editing)(self.handle) != 0 }
     }
@@ -292,21 +361,12 @@ impl<'a> Editing<'a> {
         Ok(size)
     }
 
-    pub fn get_filtered_audio(&self, frame: usize, wav_buf: &mut [u8]) -> Result<usize> {
-        let expected_len = unsafe {
-            (self.api.exports.get_audio_filtered)(self.handle, frame as _, null_mut()) as usize
-        };
-        if wav_buf.len() < expected_len {
-            Err(AviUtlError::BufferLimitExceed)
-        } else {
-            Ok(unsafe {
-                (self.api.exports.get_audio_filtered)(
-                    self.handle,
-                    frame as _,
-                    wav_buf.as_mut_ptr().cast(),
-                )
-            } as usize)
-        }
+    /// Reads the audio of `frame` after all filters into `buf` in the layout of the editing file, resizing it to the
+    /// samples of the frame.
+    pub fn get_filtered_audio(&self, frame: usize, buf: &mut AudioBuf<i16>) -> Result<()> {
+        read_audio_with(buf, self.audio_layout()?, frame, |ptr| unsafe {
+            (self.api.exports.get_audio_filtered)(self.handle, frame as _, ptr)
+        })
     }
 
     pub fn selected_frame_range(&self) -> Result<RangeInclusive<usize>> {
@@ -372,27 +432,12 @@ impl<'a> Editing<'a> {
         }
     }
 
-    pub fn get_filtering_audio(&self, frame: usize, buf: &mut [u8]) -> Result<usize> {
-        let expected_len = unsafe {
-            (self.api.exports.get_audio_filtering)(
-                self.api.filter,
-                self.handle,
-                frame as _,
-                null_mut(),
-            )
-        } as usize;
-        if buf.len() < expected_len {
-            Err(AviUtlError::BufferLimitExceed)
-        } else {
-            Ok(unsafe {
-                (self.api.exports.get_audio_filtering)(
-                    self.api.filter,
-                    self.handle,
-                    frame as _,
-                    buf.as_mut_ptr().cast(),
-                )
-            } as usize)
-        }
+    /// Reads the audio of `frame` before this filter into `buf` in the layout of the editing file, resizing it to the
+    /// samples of the frame.
+    pub fn get_filtering_audio(&self, frame: usize, buf: &mut AudioBuf<i16>) -> Result<()> {
+        read_audio_with(buf, self.audio_layout()?, frame, |ptr| unsafe {
+            (self.api.exports.get_audio_filtering)(self.api.filter, self.handle, frame as _, ptr)
+        })
     }
 
     pub fn get_displaying(&self, format: PixelFormat) -> Result<&[u8]> {
@@ -472,19 +517,114 @@ impl<'a> Editing<'a> {
             Err(AviUtlError::FrameIndexOutOfRange(frame))
         } else {
             Ok(unsafe {
-                BorrowedMutFrame::from_raw(
+                BorrowedMutFrame::from_raw_with_max_size(
                     ptr,
                     Size {
                         width: width as u32,
                         height: height as u32,
                     },
+                    self.cache_max_size()?,
                 )
             })
         }
     }
 
-    pub fn get_frame_status_table(&self, status: FrameStatusType) -> *const u8 {
-        unsafe { (self.api.exports.get_frame_status_table)(self.handle, status) }
+    /// Copies the table of `T` for all frames, such as `get_frame_status_table::<EditFlag>()`.
+    pub fn get_frame_status_table<T: StatusTableValue>(&self) -> Result<FrameStatusTable<T>> {
+        let len = self.total_frames();
+        let ptr = unsafe { (self.api.exports.get_frame_status_table)(self.handle, T::TYPE) };
+        if len == 0 {
+            Ok(FrameStatusTable::new(&[]))
+        } else if ptr.is_null() {
+            Err(AviUtlError::Unsupported(format!(
+                "getting frame status table of {:?}",
+                T::TYPE
+            )))
+        } else {
+            Ok(FrameStatusTable::new(unsafe {
+                std::slice::from_raw_parts(ptr, len)
+            }))
+        }
+    }
+
+    /// Updates the status of the frames in `range` by `f`, setting only the changed ones after saving the undo state
+    /// once if any. It returns the number of the changed frames.
+    pub fn update_frame_status<R: RangeBounds<usize>>(
+        &self,
+        range: R,
+        f: impl FnMut(usize, &mut FrameStatus),
+    ) -> Result<usize> {
+        let len = self.total_frames();
+        let start = match range.start_bound() {
+            Bound::Included(&idx) => Some(idx),
+            Bound::Excluded(&idx) => idx.checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&idx) => idx.checked_add(1),
+            Bound::Excluded(&idx) => Some(idx),
+            Bound::Unbounded => Some(len),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if end <= len => self.update_frames(start..end, f),
+            (Some(start), _) if start >= len => Err(AviUtlError::FrameIndexOutOfRange(start)),
+            _ => Err(AviUtlError::FrameIndexOutOfRange(len)),
+        }
+    }
+
+    /// Updates the status of each of `frames` by `f`, then saves the undo state and sets the changed ones if any.
+    fn update_frames(
+        &self,
+        frames: impl IntoIterator<Item = usize>,
+        mut f: impl FnMut(usize, &mut FrameStatus),
+    ) -> Result<usize> {
+        let mut changes = vec![];
+        for frame in frames {
+            let before = self.get_frame_status(frame)?;
+            let mut status = before.clone();
+            f(frame, &mut status);
+            if status != before {
+                changes.push((frame, status));
+            }
+        }
+        if !changes.is_empty() {
+            self.set_undo()?;
+        }
+        for (frame, status) in &changes {
+            self.set_frame_status(*frame, status.clone())?;
+        }
+        Ok(changes.len())
+    }
+
+    /// Sets `value` of `T` on the frames in `range`, with one undo state.
+    pub fn set_frame_status_value<T: StatusTableValue, R: RangeBounds<usize>>(
+        &self,
+        range: R,
+        value: T,
+    ) -> Result<usize> {
+        self.update_frame_status(range, |_, status| T::set(status, value))
+    }
+
+    /// Inserts or removes `flag` on the frames in `range`, with one undo state.
+    pub fn set_edit_flag<R: RangeBounds<usize>>(
+        &self,
+        range: R,
+        flag: EditFlag,
+        value: bool,
+    ) -> Result<usize> {
+        self.update_frame_status(range, |_, status| status.edit_flag.set(flag, value))
+    }
+
+    /// Inserts `flag` on each of `frames` in any order, with one undo state.
+    pub fn insert_edit_flag(
+        &self,
+        frames: impl IntoIterator<Item = usize>,
+        flag: EditFlag,
+    ) -> Result<usize> {
+        let mut frames: Vec<usize> = frames.into_iter().collect();
+        frames.sort_unstable();
+        frames.dedup();
+        self.update_frames(frames, |_, status| status.edit_flag.insert(flag))
     }
 
     pub fn set_undo(&self) -> Result<()> {
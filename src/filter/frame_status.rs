@@ -1,4 +1,5 @@
-use aviutl_plugin_sys::filter::FrameStatus as RawFrameStatus;
+use aviutl_plugin_sys::filter::{FrameStatus as RawFrameStatus, FrameStatusType};
+use std::{marker::PhantomData, ops::RangeInclusive};
 
 pub use aviutl_plugin_sys::filter::EditFlag;
 pub use aviutl_plugin_sys::filter::FrameInterlace;
@@ -9,6 +10,8 @@ pub struct FrameStatus {
     pub video_id: usize,
     pub audio_id: usize,
     pub interlace_mode: FrameInterlace,
+    /// Position in the 3:2 pulldown cycle of five frames, on converting into 24fps.
+    pub index24fps: usize,
     pub config_id: usize,
     pub vcm_id: usize,
     pub edit_flag: EditFlag,
@@ -20,6 +23,7 @@ impl FrameStatus {
             video_id: raw.video as usize,
             audio_id: raw.audio as usize,
             interlace_mode: raw.inter,
+            index24fps: raw.index24fps as usize,
             config_id: raw.config as usize,
             vcm_id: raw.vcm as usize,
             edit_flag: raw.edit_flag,
@@ -31,7 +35,7 @@ impl FrameStatus {
             video: self.video_id as _,
             audio: self.audio_id as _,
             inter: self.interlace_mode,
-            index24fps: 0,
+            index24fps: self.index24fps as _,
             config: self.config_id as _,
             vcm: self.vcm_id as _,
             edit_flag: self.edit_flag,
@@ -39,3 +43,182 @@ impl FrameStatus {
         }
     }
 }
+
+/// A value of [`FrameStatus`] kept in a table by the host, one byte for each frame.
+pub trait StatusTableValue: Copy + Eq {
+    const TYPE: FrameStatusType;
+
+    fn from_byte(byte: u8) -> Self;
+    fn get(status: &FrameStatus) -> Self;
+    fn set(status: &mut FrameStatus, value: Self);
+}
+
+impl StatusTableValue for EditFlag {
+    const TYPE: FrameStatusType = FrameStatusType::EditFlag;
+
+    fn from_byte(byte: u8) -> Self {
+        EditFlag::from_bits_truncate(byte as _)
+    }
+
+    fn get(status: &FrameStatus) -> Self {
+        status.edit_flag
+    }
+
+    fn set(status: &mut FrameStatus, value: Self) {
+        status.edit_flag = value;
+    }
+}
+
+impl StatusTableValue for FrameInterlace {
+    const TYPE: FrameStatusType = FrameStatusType::Interlace;
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            1 => FrameInterlace::Reverse,
+            2 => FrameInterlace::Odd,
+            3 => FrameInterlace::Even,
+            4 => FrameInterlace::Mix,
+            5 => FrameInterlace::Auto,
+            _ => FrameInterlace::Normal,
+        }
+    }
+
+    fn get(status: &FrameStatus) -> Self {
+        status.interlace_mode
+    }
+
+    fn set(status: &mut FrameStatus, value: Self) {
+        status.interlace_mode = value;
+    }
+}
+
+/// A copy of a value of all frames, by [`Editing::get_frame_status_table`](super::editing::Editing::get_frame_status_table).
+/// It is not updated by later changes of the frames.
+#[derive(Debug, Clone)]
+pub struct FrameStatusTable<T> {
+    bytes: Vec<u8>,
+    _value: PhantomData<T>,
+}
+
+impl<T: StatusTableValue> FrameStatusTable<T> {
+    pub(crate) fn new(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            _value: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, frame: usize) -> Option<T> {
+        self.bytes.get(frame).map(|&byte| T::from_byte(byte))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.bytes.iter().map(|&byte| T::from_byte(byte))
+    }
+
+    /// Runs of consecutive frames with the same value.
+    pub fn runs(&self) -> Runs<'_, T> {
+        Runs {
+            bytes: &self.bytes,
+            start: 0,
+            _value: PhantomData,
+        }
+    }
+}
+
+/// Iterator over runs of a [`FrameStatusTable`].
+#[derive(Debug, Clone)]
+pub struct Runs<'a, T> {
+    bytes: &'a [u8],
+    start: usize,
+    _value: PhantomData<T>,
+}
+
+impl<T: StatusTableValue> Iterator for Runs<'_, T> {
+    type Item = (RangeInclusive<usize>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = T::from_byte(*self.bytes.get(self.start)?);
+        let len = self.bytes[self.start..]
+            .iter()
+            .take_while(|&&byte| T::from_byte(byte) == value)
+            .count();
+        let run = self.start..=self.start + len - 1;
+        self.start += len;
+        Some((run, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs<T: StatusTableValue>(bytes: &[u8]) -> Vec<(RangeInclusive<usize>, T)> {
+        FrameStatusTable::<T>::new(bytes).runs().collect()
+    }
+
+    #[test]
+    fn runs_of_empty_table() {
+        assert!(runs::<EditFlag>(&[]).is_empty());
+    }
+
+    #[test]
+    fn runs_of_single_value() {
+        assert_eq!(runs(&[4; 6]), [(0..=5, EditFlag::DEL_FRAME)]);
+    }
+
+    #[test]
+    fn runs_of_alternating_values() {
+        assert_eq!(
+            runs(&[0, 2, 0, 2]),
+            [
+                (0..=0, FrameInterlace::Normal),
+                (1..=1, FrameInterlace::Odd),
+                (2..=2, FrameInterlace::Normal),
+                (3..=3, FrameInterlace::Odd),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_compare_decoded_values() {
+        // Unknown bytes decode to the same value and join a run.
+        assert_eq!(
+            runs(&[0, 9, 1]),
+            [
+                (0..=1, FrameInterlace::Normal),
+                (2..=2, FrameInterlace::Reverse)
+            ]
+        );
+        let table = FrameStatusTable::<EditFlag>::new(&[1, 2 | 4]);
+        assert_eq!(
+            table.get(1),
+            Some(EditFlag::MARK_FRAME | EditFlag::DEL_FRAME)
+        );
+        assert_eq!(table.get(2), None);
+    }
+
+    #[test]
+    fn status_round_trips_raw() {
+        let status = FrameStatus {
+            video_id: 12,
+            audio_id: 34,
+            interlace_mode: FrameInterlace::Even,
+            index24fps: 3,
+            config_id: 1,
+            vcm_id: 2,
+            edit_flag: EditFlag::KEYFRAME | EditFlag::DEL_FRAME,
+        };
+        let raw = status.clone().into_raw();
+        assert_eq!(raw.index24fps, 3);
+        assert_eq!(FrameStatus::from_raw(raw), status);
+    }
+}
@@ -0,0 +1,440 @@
+//! Parametric color grading of [`Frame`]s.
+//!
+//! A [`Grade`] is a sequence of [`GradeOp`]s evaluated on normalized RGB, where the operations in YC space convert
+//! through the [`ColorSpace`]. Applying it to a frame bakes the whole sequence into a [`CubeLut`] first, so every
+//! pixel costs one lookup regardless of the number of operations.
+//!
+//! [`Grade::to_bytes`] and [`Grade::from_bytes`] serialize the operations into a compact little-endian format, which
+//! fits into the project data of `save_project` and `load_project`.
+
+use super::{
+    lut::{CubeLut, Interpolation},
+    Frame,
+};
+use crate::{
+    colorspace::ColorSpace,
+    executor::{Executor, SingleThread},
+    AviUtlError, Result,
+};
+use std::io::{self, Read};
+
+/// Size of the LUT which [`Grade::apply`] bakes.
+pub const DEFAULT_LUT_SIZE: usize = 33;
+
+/// Version of the serialized format.
+const FORMAT_VERSION: u8 = 1;
+
+/// A tone curve through control points, interpolated by monotone cubic splines (Fritsch-Carlson), and constant
+/// outside the points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneCurve {
+    points: Vec<(f32, f32)>,
+    tangents: Vec<f32>,
+}
+
+impl ToneCurve {
+    /// Creates a curve through `points` of `(input, output)`, sorted by input. At least two points of distinct inputs
+    /// are required.
+    pub fn new(mut points: Vec<(f32, f32)>) -> Result<Self> {
+        if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+            return Err(AviUtlError::Unsupported(
+                "tone curve of non-finite point".into(),
+            ));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+        if points.len() < 2 {
+            return Err(AviUtlError::Unsupported(
+                "tone curve of fewer than 2 points".into(),
+            ));
+        }
+        let tangents = monotone_tangents(&points);
+        Ok(Self { points, tangents })
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+            tangents: vec![1.0, 1.0],
+        }
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+        let k = self.points.partition_point(|&(px, _)| px <= x) - 1;
+        let ((x0, y0), (x1, y1)) = (self.points[k], self.points[k + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[k]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.tangents[k + 1]
+    }
+}
+
+impl Default for ToneCurve {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+fn monotone_tangents(points: &[(f32, f32)]) -> Vec<f32> {
+    let secants: Vec<f32> = points
+        .windows(2)
+        .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+        .collect();
+    let n = points.len();
+    let mut tangents: Vec<f32> = (0..n)
+        .map(|k| {
+            if k == 0 {
+                secants[0]
+            } else if k == n - 1 {
+                secants[n - 2]
+            } else if secants[k - 1] * secants[k] <= 0.0 {
+                0.0
+            } else {
+                (secants[k - 1] + secants[k]) / 2.0
+            }
+        })
+        .collect();
+    for (k, &secant) in secants.iter().enumerate() {
+        if secant == 0.0 {
+            tangents[k] = 0.0;
+            tangents[k + 1] = 0.0;
+            continue;
+        }
+        let (a, b) = (tangents[k] / secant, tangents[k + 1] / secant);
+        let norm = a * a + b * b;
+        if norm > 9.0 {
+            let tau = 3.0 / norm.sqrt();
+            tangents[k] = tau * a * secant;
+            tangents[k + 1] = tau * b * secant;
+        }
+    }
+    tangents
+}
+
+/// A grading operation on normalized RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GradeOp {
+    /// Curves for each of red, green and blue.
+    RgbCurves([ToneCurve; 3]),
+    /// A curve on luma, keeping chroma.
+    LumaCurve(ToneCurve),
+    /// `(x * gain + lift * (1 - x)) ^ (1 / gamma)` for each of red, green and blue.
+    LiftGammaGain {
+        lift: [f32; 3],
+        gamma: [f32; 3],
+        gain: [f32; 3],
+    },
+    /// Multiplies chroma, where 1.0 keeps it and 0.0 makes it gray.
+    Saturation(f32),
+    /// Rotates chroma by degrees, counterclockwise on the Cb-Cr plane.
+    HueRotation(f32),
+    /// Shifts towards yellow by positive `temperature` and towards magenta by positive `tint`, both between -1.0 and
+    /// 1.0, keeping luma.
+    WhiteBalance { temperature: f32, tint: f32 },
+}
+
+impl GradeOp {
+    pub fn map_rgb(&self, rgb: [f32; 3], color_space: &ColorSpace) -> [f32; 3] {
+        match self {
+            GradeOp::RgbCurves(curves) => std::array::from_fn(|i| curves[i].evaluate(rgb[i])),
+            GradeOp::LumaCurve(curve) => {
+                let [y, cb, cr] = color_space.rgb_f32_to_ycbcr_f32(rgb);
+                color_space.ycbcr_f32_to_rgb_f32([curve.evaluate(y), cb, cr])
+            }
+            GradeOp::LiftGammaGain { lift, gamma, gain } => std::array::from_fn(|i| {
+                let x = rgb[i] * gain[i] + lift[i] * (1.0 - rgb[i]);
+                x.max(0.0).powf(1.0 / gamma[i].max(f32::EPSILON))
+            }),
+            GradeOp::Saturation(saturation) => {
+                let [y, cb, cr] = color_space.rgb_f32_to_ycbcr_f32(rgb);
+                color_space.ycbcr_f32_to_rgb_f32([y, cb * saturation, cr * saturation])
+            }
+            GradeOp::HueRotation(degrees) => {
+                let [y, cb, cr] = color_space.rgb_f32_to_ycbcr_f32(rgb);
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                color_space.ycbcr_f32_to_rgb_f32([y, cb * cos - cr * sin, cb * sin + cr * cos])
+            }
+            GradeOp::WhiteBalance { temperature, tint } => {
+                let gains = [
+                    1.0 + 0.3 * temperature + 0.15 * tint,
+                    1.0 - 0.3 * tint,
+                    1.0 - 0.3 * temperature + 0.15 * tint,
+                ];
+                let [y, _, _] = color_space.rgb_f32_to_ycbcr_f32(rgb);
+                let balanced: [f32; 3] = std::array::from_fn(|i| rgb[i] * gains[i]);
+                let [balanced_y, _, _] = color_space.rgb_f32_to_ycbcr_f32(balanced);
+                if balanced_y <= 0.0 {
+                    return balanced;
+                }
+                balanced.map(|value| value * y / balanced_y)
+            }
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            GradeOp::RgbCurves(_) => 0,
+            GradeOp::LumaCurve(_) => 1,
+            GradeOp::LiftGammaGain { .. } => 2,
+            GradeOp::Saturation(_) => 3,
+            GradeOp::HueRotation(_) => 4,
+            GradeOp::WhiteBalance { .. } => 5,
+        }
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(self.tag());
+        match self {
+            GradeOp::RgbCurves(curves) => curves.iter().for_each(|curve| write_curve(out, curve)),
+            GradeOp::LumaCurve(curve) => write_curve(out, curve),
+            GradeOp::LiftGammaGain { lift, gamma, gain } => {
+                for value in lift.iter().chain(gamma).chain(gain) {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            GradeOp::Saturation(value) | GradeOp::HueRotation(value) => {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            GradeOp::WhiteBalance { temperature, tint } => {
+                out.extend_from_slice(&temperature.to_le_bytes());
+                out.extend_from_slice(&tint.to_le_bytes());
+            }
+        }
+    }
+
+    fn read_bytes(input: &mut &[u8]) -> io::Result<Self> {
+        let op = match read_u8(input)? {
+            0 => GradeOp::RgbCurves([read_curve(input)?, read_curve(input)?, read_curve(input)?]),
+            1 => GradeOp::LumaCurve(read_curve(input)?),
+            2 => GradeOp::LiftGammaGain {
+                lift: read_f32s(input)?,
+                gamma: read_f32s(input)?,
+                gain: read_f32s(input)?,
+            },
+            3 => GradeOp::Saturation(read_f32(input)?),
+            4 => GradeOp::HueRotation(read_f32(input)?),
+            5 => GradeOp::WhiteBalance {
+                temperature: read_f32(input)?,
+                tint: read_f32(input)?,
+            },
+            tag => return Err(invalid_data(format!("unknown grade operation {}", tag))),
+        };
+        Ok(op)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8(input: &mut &[u8]) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(input: &mut &[u8]) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32(input: &mut &[u8]) -> io::Result<f32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+fn read_f32s(input: &mut &[u8]) -> io::Result<[f32; 3]> {
+    Ok([read_f32(input)?, read_f32(input)?, read_f32(input)?])
+}
+
+fn write_curve(out: &mut Vec<u8>, curve: &ToneCurve) {
+    out.extend_from_slice(&(curve.points.len() as u32).to_le_bytes());
+    for (x, y) in &curve.points {
+        out.extend_from_slice(&x.to_le_bytes());
+        out.extend_from_slice(&y.to_le_bytes());
+    }
+}
+
+fn read_curve(input: &mut &[u8]) -> io::Result<ToneCurve> {
+    let len = read_u32(input)? as usize;
+    if len > input.len() / 8 {
+        return Err(invalid_data(format!("tone curve of {} points", len)));
+    }
+    let points = (0..len)
+        .map(|_| Ok((read_f32(input)?, read_f32(input)?)))
+        .collect::<io::Result<_>>()?;
+    ToneCurve::new(points).map_err(|err| invalid_data(err.to_string()))
+}
+
+/// A sequence of grading operations applied in order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Grade {
+    pub ops: Vec<GradeOp>,
+}
+
+impl Grade {
+    pub fn new(ops: Vec<GradeOp>) -> Self {
+        Self { ops }
+    }
+
+    pub fn push(&mut self, op: GradeOp) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn map_rgb(&self, rgb: [f32; 3], color_space: &ColorSpace) -> [f32; 3] {
+        self.ops
+            .iter()
+            .fold(rgb, |rgb, op| op.map_rgb(rgb, color_space))
+    }
+
+    /// Samples the whole sequence into a 3D LUT of `size`.
+    pub fn bake(&self, size: usize, color_space: &ColorSpace) -> CubeLut {
+        CubeLut::from_fn(size, |rgb| self.map_rgb(rgb, color_space))
+    }
+
+    /// Applies the sequence to `frame` through a LUT of [`DEFAULT_LUT_SIZE`].
+    pub fn apply(&self, frame: &mut impl Frame, color_space: &ColorSpace) -> Result<()> {
+        self.apply_with(&SingleThread, frame, color_space)
+    }
+
+    /// [`Grade::apply`] on the threads of `executor`.
+    pub fn apply_with(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        color_space: &ColorSpace,
+    ) -> Result<()> {
+        self.bake(DEFAULT_LUT_SIZE, color_space).apply_with(
+            executor,
+            frame,
+            color_space,
+            Interpolation::Tetrahedral,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![FORMAT_VERSION];
+        out.extend_from_slice(&(self.ops.len() as u32).to_le_bytes());
+        for op in &self.ops {
+            op.write_bytes(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(mut input: &[u8]) -> Result<Self> {
+        Self::read_bytes(&mut input).map_err(AviUtlError::Load)
+    }
+
+    fn read_bytes(input: &mut &[u8]) -> io::Result<Self> {
+        let version = read_u8(input)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!("unknown version {}", version)));
+        }
+        let len = read_u32(input)?;
+        let ops = (0..len)
+            .map(|_| GradeOp::read_bytes(input))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { ops })
+    }
+
+    /// Writes [`Grade::to_bytes`] into `save` as `save_project` does, and returns the written length.
+    pub fn save_into(&self, save: &mut [u8]) -> Result<usize> {
+        let bytes = self.to_bytes();
+        let dst = save
+            .get_mut(..bytes.len())
+            .ok_or(AviUtlError::BufferLimitExceed)?;
+        dst.copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_grade() -> Grade {
+        let curve = |points: &[(f32, f32)]| ToneCurve::new(points.to_vec()).unwrap();
+        Grade::new(vec![
+            GradeOp::RgbCurves([
+                curve(&[(0.0, 0.0), (0.4, 0.5), (1.0, 1.0)]),
+                ToneCurve::identity(),
+                curve(&[(0.0, 0.1), (1.0, 0.9)]),
+            ]),
+            GradeOp::LumaCurve(curve(&[(0.0, 0.0), (0.25, 0.2), (0.75, 0.85), (1.0, 1.0)])),
+            GradeOp::LiftGammaGain {
+                lift: [0.01, 0.0, -0.02],
+                gamma: [1.0, 1.1, 0.9],
+                gain: [1.05, 1.0, 0.95],
+            },
+            GradeOp::Saturation(1.2),
+            GradeOp::HueRotation(-15.0),
+            GradeOp::WhiteBalance {
+                temperature: 0.3,
+                tint: -0.1,
+            },
+        ])
+    }
+
+    #[test]
+    fn monotone_points_give_monotone_curve() {
+        let cases: [&[(f32, f32)]; 4] = [
+            &[(0.0, 0.0), (0.1, 0.6), (0.2, 0.61), (1.0, 1.0)],
+            &[(0.0, 0.0), (0.5, 0.5), (0.55, 0.5), (1.0, 1.0)],
+            &[(0.0, 0.0), (0.3, 0.05), (0.35, 0.9), (1.0, 0.95)],
+            &[(0.0, 1.0), (0.2, 0.9), (0.8, 0.1), (1.0, 0.0)],
+        ];
+        for points in cases {
+            let curve = ToneCurve::new(points.to_vec()).unwrap();
+            let increasing = points[0].1 <= points[points.len() - 1].1;
+            let mut previous = curve.evaluate(-0.1);
+            for i in 0..=1000 {
+                let value = curve.evaluate(i as f32 / 1000.0);
+                if increasing {
+                    assert!(value >= previous - 1e-6, "{:?} falls at {}", points, i);
+                } else {
+                    assert!(value <= previous + 1e-6, "{:?} rises at {}", points, i);
+                }
+                previous = value;
+            }
+            for &(x, y) in points {
+                assert!((curve.evaluate(x) - y).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for grade in [Grade::default(), sample_grade()] {
+            assert_eq!(Grade::from_bytes(&grade.to_bytes()).unwrap(), grade);
+        }
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut bytes = sample_grade().to_bytes();
+        bytes[0] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Grade::from_bytes(&bytes),
+            Err(AviUtlError::Load(_))
+        ));
+        let bytes = sample_grade().to_bytes();
+        assert!(Grade::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
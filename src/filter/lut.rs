@@ -0,0 +1,429 @@
+//! Loading and applying `.cube` LUTs on [`Frame`]s.
+//!
+//! [`CubeLut`] follows the Adobe Cube LUT specification 1.0, holding either a 1D or a 3D table. It is applied to
+//! normalized RGB converted from YC48 by a [`ColorSpace`], and the result is converted back. The computation does not
+//! depend on the number of threads of [`Executor`], so the output is deterministic.
+
+use super::Frame;
+use crate::{
+    colorspace::ColorSpace,
+    executor::{for_each_line_mut, Executor, SingleThread},
+    AviUtlError, Result,
+};
+use std::{fs, path::Path};
+
+const MAX_1D_SIZE: usize = 65536;
+const MAX_3D_SIZE: usize = 256;
+
+/// How to interpolate between the entries of a 3D table. 1D tables are always interpolated linearly.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interpolation {
+    Trilinear,
+    /// Interpolates in the tetrahedron containing the input, which keeps the neutral axis exactly.
+    #[default]
+    Tetrahedral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LutDimension {
+    OneD,
+    ThreeD,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    dimension: LutDimension,
+    size: usize,
+    /// Entries of `[r, g, b]`. In 3D, red changes fastest and blue slowest.
+    table: Vec<[f32; 3]>,
+}
+
+fn parse_error(line: usize, message: impl Into<String>) -> AviUtlError {
+    AviUtlError::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+fn parse_floats<const N: usize>(line: usize, tokens: &[&str]) -> Result<[f32; N]> {
+    if tokens.len() != N {
+        return Err(parse_error(
+            line,
+            format!("expected {} values but found {}", N, tokens.len()),
+        ));
+    }
+    let mut values = [0.0; N];
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token
+            .parse()
+            .map_err(|_| parse_error(line, format!("invalid number {:?}", token)))?;
+    }
+    Ok(values)
+}
+
+fn parse_size(line: usize, tokens: &[&str], max: usize) -> Result<usize> {
+    let [token] = tokens else {
+        return Err(parse_error(line, "expected a size"));
+    };
+    match token.parse() {
+        Ok(size) if (2..=max).contains(&size) => Ok(size),
+        _ => Err(parse_error(
+            line,
+            format!("size {:?} is not between 2 and {}", token, max),
+        )),
+    }
+}
+
+impl CubeLut {
+    /// A 3D table which samples `f` on the grid of `size` in the domain between 0.0 and 1.0.
+    pub fn from_fn(size: usize, f: impl Fn([f32; 3]) -> [f32; 3]) -> Self {
+        assert!((2..=MAX_3D_SIZE).contains(&size));
+        let scale = (size - 1) as f32;
+        let table = (0..size * size * size)
+            .map(|i| {
+                f([
+                    (i % size) as f32 / scale,
+                    (i / size % size) as f32 / scale,
+                    (i / (size * size)) as f32 / scale,
+                ])
+            })
+            .collect();
+        Self {
+            title: None,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            dimension: LutDimension::ThreeD,
+            size,
+            table,
+        }
+    }
+
+    /// A 3D table which maps every color to itself.
+    pub fn identity(size: usize) -> Self {
+        Self::from_fn(size, |rgb| rgb)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(AviUtlError::Load)?;
+        Self::parse(&text)
+    }
+
+    /// Parses the text of a `.cube` file. Unknown keywords are ignored as the specification allows.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut shape: Option<(LutDimension, usize)> = None;
+        let mut table = vec![];
+        let mut last_line = 0;
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            last_line = number;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("TITLE") {
+                let rest = rest.trim();
+                let quoted = rest
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.strip_suffix('"'))
+                    .ok_or_else(|| parse_error(number, "title must be quoted"))?;
+                title = Some(quoted.to_owned());
+                continue;
+            }
+            let tokens: Vec<_> = line.split_whitespace().collect();
+            let (keyword, arguments) = (tokens[0], &tokens[1..]);
+            let is_keyword = keyword.starts_with(|c: char| c.is_ascii_alphabetic());
+            if is_keyword && !table.is_empty() {
+                return Err(parse_error(
+                    number,
+                    format!("keyword {} after table data", keyword),
+                ));
+            }
+            let declared = match keyword {
+                "LUT_1D_SIZE" => Some((
+                    LutDimension::OneD,
+                    parse_size(number, arguments, MAX_1D_SIZE)?,
+                )),
+                "LUT_3D_SIZE" => Some((
+                    LutDimension::ThreeD,
+                    parse_size(number, arguments, MAX_3D_SIZE)?,
+                )),
+                "DOMAIN_MIN" => {
+                    domain_min = parse_floats(number, arguments)?;
+                    None
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = parse_floats(number, arguments)?;
+                    None
+                }
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_floats(number, arguments)?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                    None
+                }
+                _ if is_keyword => None,
+                _ => {
+                    if shape.is_none() {
+                        return Err(parse_error(
+                            number,
+                            "table data before LUT_1D_SIZE or LUT_3D_SIZE",
+                        ));
+                    }
+                    table.push(parse_floats(number, &tokens)?);
+                    None
+                }
+            };
+            if let Some(declared) = declared {
+                if shape.is_some() {
+                    return Err(parse_error(number, "size is declared twice"));
+                }
+                shape = Some(declared);
+            }
+        }
+
+        let (dimension, size) =
+            shape.ok_or_else(|| parse_error(last_line, "missing LUT_1D_SIZE or LUT_3D_SIZE"))?;
+        let expected = match dimension {
+            LutDimension::OneD => size,
+            LutDimension::ThreeD => size * size * size,
+        };
+        if table.len() != expected {
+            return Err(parse_error(
+                last_line,
+                format!("expected {} entries but found {}", expected, table.len()),
+            ));
+        }
+        if domain_min
+            .iter()
+            .zip(&domain_max)
+            .any(|(min, max)| min >= max)
+        {
+            return Err(parse_error(
+                last_line,
+                "DOMAIN_MIN must be less than DOMAIN_MAX",
+            ));
+        }
+        Ok(Self {
+            title,
+            domain_min,
+            domain_max,
+            dimension,
+            size,
+            table,
+        })
+    }
+
+    pub fn dimension(&self) -> LutDimension {
+        self.dimension
+    }
+
+    /// Number of entries along each axis.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn table(&self) -> &[[f32; 3]] {
+        &self.table
+    }
+
+    /// Position of `rgb` in the table, between 0.0 and `size - 1` on each axis.
+    fn position(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        std::array::from_fn(|i| {
+            let t = (rgb[i] - self.domain_min[i]) / (self.domain_max[i] - self.domain_min[i]);
+            t.clamp(0.0, 1.0) * last
+        })
+    }
+
+    /// Maps normalized RGB `[r, g, b]` by the table.
+    pub fn lookup(&self, rgb: [f32; 3], interpolation: Interpolation) -> [f32; 3] {
+        let position = self.position(rgb);
+        let last = self.size - 1;
+        let base = position.map(|p| (p as usize).min(last - 1));
+        let frac: [f32; 3] = std::array::from_fn(|i| position[i] - base[i] as f32);
+        match self.dimension {
+            LutDimension::OneD => std::array::from_fn(|i| {
+                let (lower, upper) = (self.table[base[i]][i], self.table[base[i] + 1][i]);
+                lower + (upper - lower) * frac[i]
+            }),
+            LutDimension::ThreeD => {
+                let at = |r: usize, g: usize, b: usize| {
+                    self.table[(base[0] + r)
+                        + (base[1] + g) * self.size
+                        + (base[2] + b) * self.size * self.size]
+                };
+                match interpolation {
+                    Interpolation::Trilinear => trilinear(at, frac),
+                    Interpolation::Tetrahedral => tetrahedral(at, frac),
+                }
+            }
+        }
+    }
+
+    /// Applies the table to `frame` in normalized RGB of `color_space`.
+    pub fn apply(
+        &self,
+        frame: &mut impl Frame,
+        color_space: &ColorSpace,
+        interpolation: Interpolation,
+    ) -> Result<()> {
+        self.apply_with(&SingleThread, frame, color_space, interpolation)
+    }
+
+    /// [`CubeLut::apply`] on the threads of `executor`.
+    pub fn apply_with(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        color_space: &ColorSpace,
+        interpolation: Interpolation,
+    ) -> Result<()> {
+        for_each_line_mut(executor, frame, |_, line| {
+            for pixel in line {
+                let rgb = color_space.yc_to_rgb_f32(*pixel);
+                *pixel = color_space.rgb_f32_to_yc(self.lookup(rgb, interpolation));
+            }
+        })
+    }
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn trilinear(at: impl Fn(usize, usize, usize) -> [f32; 3], [fr, fg, fb]: [f32; 3]) -> [f32; 3] {
+    let c00 = mix(at(0, 0, 0), at(1, 0, 0), fr);
+    let c10 = mix(at(0, 1, 0), at(1, 1, 0), fr);
+    let c01 = mix(at(0, 0, 1), at(1, 0, 1), fr);
+    let c11 = mix(at(0, 1, 1), at(1, 1, 1), fr);
+    mix(mix(c00, c10, fg), mix(c01, c11, fg), fb)
+}
+
+fn tetrahedral(at: impl Fn(usize, usize, usize) -> [f32; 3], [fr, fg, fb]: [f32; 3]) -> [f32; 3] {
+    let c000 = at(0, 0, 0);
+    let c111 = at(1, 1, 1);
+    // Vertices from the origin to the opposite corner, through the edges of the largest fractions first.
+    let ((w1, c1), (w2, c2), w3) = if fr > fg {
+        if fg > fb {
+            ((fr, at(1, 0, 0)), (fg, at(1, 1, 0)), fb)
+        } else if fr > fb {
+            ((fr, at(1, 0, 0)), (fb, at(1, 0, 1)), fg)
+        } else {
+            ((fb, at(0, 0, 1)), (fr, at(1, 0, 1)), fg)
+        }
+    } else if fb > fg {
+        ((fb, at(0, 0, 1)), (fg, at(0, 1, 1)), fr)
+    } else if fb > fr {
+        ((fg, at(0, 1, 0)), (fb, at(0, 1, 1)), fr)
+    } else {
+        ((fg, at(0, 1, 0)), (fr, at(1, 1, 0)), fb)
+    };
+    std::array::from_fn(|i| {
+        c000[i] + w1 * (c1[i] - c000[i]) + w2 * (c2[i] - c1[i]) + w3 * (c111[i] - c2[i])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_line(text: &str) -> usize {
+        match CubeLut::parse(text) {
+            Err(AviUtlError::Parse { line, .. }) => line,
+            other => panic!("{:?} is not a parse error", other),
+        }
+    }
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            actual
+                .iter()
+                .zip(&expected)
+                .all(|(a, e)| (a - e).abs() < 1e-5),
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    const SAMPLES: [[f32; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 1.0, 1.0],
+        [0.5, 0.5, 0.5],
+        [0.1, 0.7, 0.3],
+        [0.9, 0.2, 0.55],
+        [0.33, 0.33, 0.8],
+    ];
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        assert_eq!(error_line("TITLE untitled\nLUT_1D_SIZE 2\n"), 1);
+        assert_eq!(error_line("# comment\n\nLUT_3D_SIZE 1\n"), 3);
+        assert_eq!(error_line("LUT_1D_SIZE 2\n0 0 0\n1 x 1\n"), 3);
+        assert_eq!(error_line("LUT_1D_SIZE 2\n0 0\n1 1 1\n"), 2);
+        assert_eq!(error_line("0 0 0\nLUT_1D_SIZE 2\n"), 1);
+        assert_eq!(error_line("LUT_1D_SIZE 2\nLUT_3D_SIZE 2\n"), 2);
+        assert_eq!(
+            error_line("LUT_1D_SIZE 2\n0 0 0\nDOMAIN_MIN 0 0 0\n1 1 1\n"),
+            3
+        );
+        assert_eq!(error_line("LUT_1D_SIZE 2\n0 0 0\n"), 2);
+        assert_eq!(
+            error_line("LUT_1D_SIZE 2\nDOMAIN_MIN 1 0 0\n0 0 0\n1 1 1"),
+            4
+        );
+    }
+
+    #[test]
+    fn identity_3d_keeps_colors() {
+        let lut = CubeLut::identity(17);
+        for rgb in SAMPLES {
+            assert_near(lut.lookup(rgb, Interpolation::Trilinear), rgb);
+            assert_near(lut.lookup(rgb, Interpolation::Tetrahedral), rgb);
+        }
+    }
+
+    #[test]
+    fn parsed_identity_keeps_colors() {
+        let mut text = String::from("TITLE \"identity\"\nLUT_3D_SIZE 2\n");
+        for i in 0..8 {
+            text += &format!("{} {} {}\n", i & 1, i >> 1 & 1, i >> 2 & 1);
+        }
+        let lut = CubeLut::parse(&text).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("identity"));
+        for rgb in SAMPLES {
+            assert_near(lut.lookup(rgb, Interpolation::Trilinear), rgb);
+            assert_near(lut.lookup(rgb, Interpolation::Tetrahedral), rgb);
+        }
+    }
+
+    #[test]
+    fn domain_is_honored() {
+        let text = "LUT_1D_SIZE 2\nDOMAIN_MIN 0 0.5 -1\nDOMAIN_MAX 2 1 1\n0 0 0\n1 1 1\n";
+        let lut = CubeLut::parse(text).unwrap();
+        assert_eq!(lut.domain_min, [0.0, 0.5, -1.0]);
+        assert_eq!(lut.domain_max, [2.0, 1.0, 1.0]);
+        assert_near(
+            lut.lookup([1.0, 0.75, 0.0], Interpolation::default()),
+            [0.5, 0.5, 0.5],
+        );
+        // Inputs out of the domain are clamped to its ends.
+        assert_near(
+            lut.lookup([3.0, 0.0, -2.0], Interpolation::default()),
+            [1.0, 0.0, 0.0],
+        );
+
+        let mut lut = CubeLut::identity(5);
+        lut.domain_min = [-1.0; 3];
+        lut.domain_max = [3.0; 3];
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            assert_near(lut.lookup([1.0, -1.0, 3.0], interpolation), [0.5, 0.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Planar working representations of YC frames.
+//!
+//! [`Frame`] stores its pixels as an array of [`PixelYc`]. [`PlaneView`] and [`PlaneViewMut`] see one component of
+//! a frame as a plane without copying, and [`PlanarFrame`] holds separated planes of `i16` or normalized `f32`.
+
+use super::{Frame, OwnedFrame};
+use crate::{PixelYc, Size};
+use std::{marker::PhantomData, ops::RangeInclusive};
+
+const COMPONENTS: usize = 3;
+
+/// A component plane of [`PixelYc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Plane {
+    Y,
+    Cb,
+    Cr,
+}
+
+impl Plane {
+    pub const ALL: [Plane; 3] = [Plane::Y, Plane::Cb, Plane::Cr];
+
+    /// Documented range of the component in YC48.
+    pub const fn range(self) -> RangeInclusive<i16> {
+        match self {
+            Plane::Y => PixelYc::Y_MIN..=PixelYc::Y_MAX,
+            Plane::Cb | Plane::Cr => PixelYc::C_MIN..=PixelYc::C_MAX,
+        }
+    }
+
+    pub const fn is_chroma(self) -> bool {
+        !matches!(self, Plane::Y)
+    }
+
+    pub const fn get(self, pixel: &PixelYc) -> i16 {
+        match self {
+            Plane::Y => pixel.y,
+            Plane::Cb => pixel.cb,
+            Plane::Cr => pixel.cr,
+        }
+    }
+
+    pub fn get_mut(self, pixel: &mut PixelYc) -> &mut i16 {
+        match self {
+            Plane::Y => &mut pixel.y,
+            Plane::Cb => &mut pixel.cb,
+            Plane::Cr => &mut pixel.cr,
+        }
+    }
+
+    const fn offset(self) -> usize {
+        match self {
+            Plane::Y => 0,
+            Plane::Cb => 1,
+            Plane::Cr => 2,
+        }
+    }
+}
+
+fn required_len(size: Size, stride: usize) -> usize {
+    if size.area() == 0 {
+        0
+    } else {
+        (size.height as usize - 1) * stride + size.width as usize
+    }
+}
+
+/// A sample type of [`PlanarFrame`].
+pub trait Sample: Copy + Default {
+    /// Converts a component value of YC48 into the sample.
+    fn from_component(value: i16, plane: Plane) -> Self;
+    /// Converts the sample into a component value of YC48, clamped into [`Plane::range`].
+    fn into_component(self, plane: Plane) -> i16;
+}
+
+impl Sample for i16 {
+    fn from_component(value: i16, _plane: Plane) -> Self {
+        value
+    }
+
+    fn into_component(self, plane: Plane) -> i16 {
+        let range = plane.range();
+        self.clamp(*range.start(), *range.end())
+    }
+}
+
+/// Luma is normalized into `0.0..=1.0`, and chroma into `-0.5..=0.5`.
+impl Sample for f32 {
+    fn from_component(value: i16, _plane: Plane) -> Self {
+        value as f32 / PixelYc::Y_MAX as f32
+    }
+
+    fn into_component(self, plane: Plane) -> i16 {
+        let range = plane.range();
+        (self * PixelYc::Y_MAX as f32)
+            .round()
+            .clamp(*range.start() as f32, *range.end() as f32) as i16
+    }
+}
+
+/// A read-only plane over a frame, without copying.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneView<'a> {
+    image: &'a [PixelYc],
+    size: Size,
+    stride: usize,
+    plane: Plane,
+}
+
+impl<'a> PlaneView<'a> {
+    pub(crate) fn new(image: &'a [PixelYc], size: Size, stride: usize, plane: Plane) -> Self {
+        assert!(required_len(size, stride) <= image.len());
+        Self {
+            image,
+            size,
+            stride,
+            plane,
+        }
+    }
+
+    pub fn plane(&self) -> Plane {
+        self.plane
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> i16 {
+        assert!(x < self.size.width as usize && y < self.size.height as usize);
+        self.plane.get(&self.image[y * self.stride + x])
+    }
+
+    pub fn row(&self, y: usize) -> impl Iterator<Item = i16> + 'a {
+        assert!(y < self.size.height as usize);
+        let width = self.size.width as usize;
+        let plane = self.plane;
+        self.image[y * self.stride..y * self.stride + width]
+            .iter()
+            .map(move |pixel| plane.get(pixel))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i16> + 'a {
+        let view = *self;
+        (0..self.size.height as usize).flat_map(move |y| view.row(y))
+    }
+}
+
+/// A writable plane over a frame, without copying.
+///
+/// Three views of the different planes can be held at once by [`Frame::planes_mut`].
+#[derive(Debug)]
+pub struct PlaneViewMut<'a> {
+    ptr: *mut i16,
+    size: Size,
+    stride: usize,
+    plane: Plane,
+    _phantom: PhantomData<&'a mut [PixelYc]>,
+}
+
+unsafe impl Send for PlaneViewMut<'_> {}
+
+impl<'a> PlaneViewMut<'a> {
+    /// # Safety
+    ///
+    /// `image` must hold `size` lines of `stride`, and no other view of the same `plane` may exist during `'a`.
+    unsafe fn from_raw(image: *mut PixelYc, size: Size, stride: usize, plane: Plane) -> Self {
+        Self {
+            ptr: image.cast::<i16>().add(plane.offset()),
+            size,
+            stride,
+            plane,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn new(image: &'a mut [PixelYc], size: Size, stride: usize, plane: Plane) -> Self {
+        assert!(required_len(size, stride) <= image.len());
+        unsafe { Self::from_raw(image.as_mut_ptr(), size, stride, plane) }
+    }
+
+    pub(crate) fn split(image: &'a mut [PixelYc], size: Size, stride: usize) -> [Self; 3] {
+        assert!(required_len(size, stride) <= image.len());
+        let ptr = image.as_mut_ptr();
+        // Safety: Each view touches only its own component, so they never overlap.
+        Plane::ALL.map(|plane| unsafe { Self::from_raw(ptr, size, stride, plane) })
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(x < self.size.width as usize && y < self.size.height as usize);
+        (y * self.stride + x) * COMPONENTS
+    }
+
+    pub fn plane(&self) -> Plane {
+        self.plane
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> i16 {
+        unsafe { *self.ptr.add(self.index(x, y)) }
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut i16 {
+        unsafe { &mut *self.ptr.add(self.index(x, y)) }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: i16) {
+        *self.get_mut(x, y) = value;
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> impl Iterator<Item = &mut i16> + '_ {
+        let start = self.index(0, y);
+        let ptr = self.ptr;
+        (0..self.size.width as usize).map(move |x| unsafe { &mut *ptr.add(start + x * COMPONENTS) })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut i16> + '_ {
+        let ptr = self.ptr;
+        let width = self.size.width as usize;
+        let stride = self.stride;
+        (0..self.size.area())
+            .map(move |i| unsafe { &mut *ptr.add((i / width * stride + i % width) * COMPONENTS) })
+    }
+
+    pub fn fill(&mut self, value: i16) {
+        self.iter_mut().for_each(|sample| *sample = value);
+    }
+}
+
+/// A frame which has separated planes of the sample type `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanarFrame<T> {
+    y: Vec<T>,
+    cb: Vec<T>,
+    cr: Vec<T>,
+    size: Size,
+}
+
+impl<T: Sample> PlanarFrame<T> {
+    pub fn new(size: Size) -> Self {
+        Self {
+            y: vec![T::default(); size.area()],
+            cb: vec![T::default(); size.area()],
+            cr: vec![T::default(); size.area()],
+            size,
+        }
+    }
+
+    /// Copies and converts the pixels of `frame` into planes.
+    pub fn from_frame(frame: &impl Frame) -> Self {
+        let size = frame.frame_size();
+        let mut planar = Self {
+            y: Vec::with_capacity(size.area()),
+            cb: Vec::with_capacity(size.area()),
+            cr: Vec::with_capacity(size.area()),
+            size,
+        };
+        for pixel in frame.lines().flatten() {
+            planar.y.push(T::from_component(pixel.y, Plane::Y));
+            planar.cb.push(T::from_component(pixel.cb, Plane::Cb));
+            planar.cr.push(T::from_component(pixel.cr, Plane::Cr));
+        }
+        planar
+    }
+
+    /// Writes the planes back into `frame`, clamping each component into the documented range.
+    pub fn write_into(&self, frame: &mut impl Frame) {
+        assert_eq!(self.size, frame.frame_size());
+        for (i, pixel) in frame.lines_mut().flatten().enumerate() {
+            *pixel = PixelYc {
+                y: self.y[i].into_component(Plane::Y),
+                cb: self.cb[i].into_component(Plane::Cb),
+                cr: self.cr[i].into_component(Plane::Cr),
+            };
+        }
+    }
+
+    pub fn to_frame(&self) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(self.size);
+        self.write_into(&mut frame);
+        frame
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn plane(&self, plane: Plane) -> &[T] {
+        match plane {
+            Plane::Y => &self.y,
+            Plane::Cb => &self.cb,
+            Plane::Cr => &self.cr,
+        }
+    }
+
+    pub fn plane_mut(&mut self, plane: Plane) -> &mut [T] {
+        match plane {
+            Plane::Y => &mut self.y,
+            Plane::Cb => &mut self.cb,
+            Plane::Cr => &mut self.cr,
+        }
+    }
+
+    /// Returns the planes in order of Y, Cb and Cr.
+    pub fn planes_mut(&mut self) -> [&mut [T]; 3] {
+        [&mut self.y, &mut self.cb, &mut self.cr]
+    }
+
+    pub fn get(&self, plane: Plane, x: usize, y: usize) -> T {
+        assert!(x < self.size.width as usize);
+        self.plane(plane)[y * self.size.width as usize + x]
+    }
+
+    pub fn set(&mut self, plane: Plane, x: usize, y: usize, value: T) {
+        assert!(x < self.size.width as usize);
+        let width = self.size.width as usize;
+        self.plane_mut(plane)[y * width + x] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::padded_frame;
+
+    const SIZE: Size = Size {
+        width: 4,
+        height: 3,
+    };
+
+    fn frame() -> OwnedFrame {
+        let mut frame = padded_frame(SIZE, |x, y| (x * 1000 + y * 100) as i16);
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                (pixel.cb, pixel.cr) = ((x * 100) as i16 - 200, 300 - (y * 250) as i16);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn planes_round_trip() {
+        let frame = frame();
+        let planar = PlanarFrame::<f32>::from_frame(&frame);
+        assert_eq!(planar.size(), SIZE);
+        assert_eq!(planar.get(Plane::Y, 2, 1), 2100.0 / 4096.0);
+        assert_eq!(planar.get(Plane::Cb, 0, 0), -200.0 / 4096.0);
+        assert_eq!(planar.to_frame(), OwnedFrame::from_frame(&frame));
+        assert_eq!(
+            PlanarFrame::<i16>::from_frame(&frame).to_frame(),
+            OwnedFrame::from_frame(&frame)
+        );
+    }
+
+    #[test]
+    fn write_clamps_components() {
+        let mut planar = PlanarFrame::<f32>::new(SIZE);
+        planar.set(Plane::Y, 0, 0, 2.0);
+        planar.set(Plane::Cb, 0, 0, -1.0);
+        planar.set(Plane::Cr, 0, 0, 0.25);
+        let frame = planar.to_frame();
+        assert_eq!(
+            frame.pixel(0, 0),
+            PixelYc {
+                y: PixelYc::Y_MAX,
+                cb: PixelYc::C_MIN,
+                cr: 1024
+            }
+        );
+        let mut planar = PlanarFrame::<i16>::new(SIZE);
+        planar.set(Plane::Y, 1, 0, i16::MIN);
+        assert_eq!(planar.to_frame().pixel(1, 0).y, PixelYc::Y_MIN);
+    }
+
+    #[test]
+    fn plane_views_touch_only_their_component() {
+        let mut frame = frame();
+        let [_, mut cb, _] = frame.planes_mut();
+        cb.fill(7);
+        cb.set(3, 2, -7);
+        assert_eq!(cb.get(3, 2), -7);
+        assert!(frame.lines().flatten().all(|pixel| pixel.cb.abs() == 7));
+        assert_eq!(frame.pixel(2, 1).y, 2100);
+        assert_eq!(frame.pixel(2, 1).cr, 50);
+        // The padding after the visible pixels is untouched.
+        assert_eq!(frame.image()[SIZE.width as usize].cb, 0);
+        let view = frame.plane(Plane::Y);
+        assert_eq!(view.row(1).collect::<Vec<_>>(), [100, 1100, 2100, 3100]);
+    }
+}
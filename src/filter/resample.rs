@@ -0,0 +1,448 @@
+//! Host-independent resampling of [`Frame`]s.
+//!
+//! [`Resampler`] scales a sub-pixel region of a frame by separable kernels, optionally with another kernel for
+//! chroma. The result depends only on the inputs, not on the number of threads of [`Executor`].
+
+use super::{planar::Plane, Frame};
+use crate::{
+    executor::{for_each_line_mut, for_each_row_mut, Executor, SingleThread},
+    PixelYc, Rect, Result, Size,
+};
+use std::f32::consts::PI;
+
+/// A resampling kernel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    Nearest,
+    Bilinear,
+    /// Mitchell-Netravali family of cubic kernels with parameters `b` and `c`.
+    Bicubic {
+        b: f32,
+        c: f32,
+    },
+    /// Windowed sinc with `lobes` lobes.
+    Lanczos {
+        lobes: u32,
+    },
+    /// Averages the source pixels weighted by the covered area.
+    Area,
+}
+
+impl Kernel {
+    pub const CATMULL_ROM: Self = Kernel::Bicubic { b: 0.0, c: 0.5 };
+    pub const MITCHELL: Self = Kernel::Bicubic {
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0,
+    };
+    pub const B_SPLINE: Self = Kernel::Bicubic { b: 1.0, c: 0.0 };
+    pub const LANCZOS3: Self = Kernel::Lanczos { lobes: 3 };
+
+    /// Radius of the kernel in source pixels at scale 1.
+    pub fn support(&self) -> f32 {
+        match *self {
+            Kernel::Nearest | Kernel::Area => 0.5,
+            Kernel::Bilinear => 1.0,
+            Kernel::Bicubic { .. } => 2.0,
+            Kernel::Lanczos { lobes } => lobes.max(1) as f32,
+        }
+    }
+
+    pub fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Kernel::Nearest | Kernel::Area => (x < 0.5) as u8 as f32,
+            Kernel::Bilinear => (1.0 - x).max(0.0),
+            Kernel::Bicubic { b, c } => {
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else if x < 2.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    0.0
+                }
+            }
+            Kernel::Lanczos { lobes } => {
+                let lobes = lobes.max(1) as f32;
+                if x == 0.0 {
+                    1.0
+                } else if x < lobes {
+                    let px = PI * x;
+                    lobes * px.sin() * (px / lobes).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// A source region in sub-pixel precision.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CropRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CropRect {
+    pub fn whole(size: Size) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: size.width as f32,
+            height: size.height as f32,
+        }
+    }
+}
+
+impl From<Rect> for CropRect {
+    fn from(rect: Rect) -> Self {
+        Self {
+            x: rect.point.x as f32,
+            y: rect.point.y as f32,
+            width: rect.size.width as f32,
+            height: rect.size.height as f32,
+        }
+    }
+}
+
+/// Contributions of source pixels to one destination pixel.
+#[derive(Debug, Clone)]
+struct Taps {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+fn compute_taps(
+    kernel: Kernel,
+    offset: f32,
+    length: f32,
+    source: usize,
+    target: usize,
+) -> Vec<Taps> {
+    let last = source.saturating_sub(1) as isize;
+    let scale = length / target as f32;
+    let filter_scale = scale.max(1.0);
+    (0..target)
+        .map(|i| {
+            let mut weights = vec![];
+            let mut start = None;
+            let mut push = |j: isize, weight: f32| {
+                let j = j.clamp(0, last) as usize;
+                // Clamped indices are merged into the edge pixel, so `j` never decreases.
+                let index = j - *start.get_or_insert(j);
+                if weights.len() <= index {
+                    weights.resize(index + 1, 0.0);
+                }
+                weights[index] += weight;
+            };
+            match kernel {
+                Kernel::Nearest => {
+                    let center = offset + (i as f32 + 0.5) * scale;
+                    push(center.floor() as isize, 1.0);
+                }
+                Kernel::Area => {
+                    let from = offset + i as f32 * scale;
+                    let to = from + scale;
+                    for j in from.floor() as isize..to.ceil() as isize {
+                        let covered = to.min(j as f32 + 1.0) - from.max(j as f32);
+                        if 0.0 < covered {
+                            push(j, covered);
+                        }
+                    }
+                }
+                _ => {
+                    let center = offset + (i as f32 + 0.5) * scale - 0.5;
+                    let radius = kernel.support() * filter_scale;
+                    for j in (center - radius).floor() as isize..=(center + radius).ceil() as isize
+                    {
+                        let weight = kernel.weight((j as f32 - center) / filter_scale);
+                        if weight != 0.0 {
+                            push(j, weight);
+                        }
+                    }
+                }
+            }
+            let sum: f32 = weights.iter().sum();
+            if sum != 0.0 {
+                weights.iter_mut().for_each(|weight| *weight /= sum);
+            }
+            Taps {
+                start: start.unwrap_or(0),
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// Scales frames by the kernels for luma and chroma.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resampler {
+    pub luma: Kernel,
+    pub chroma: Kernel,
+}
+
+impl Resampler {
+    pub const fn new(kernel: Kernel) -> Self {
+        Self {
+            luma: kernel,
+            chroma: kernel,
+        }
+    }
+
+    pub const fn with_chroma(self, chroma: Kernel) -> Self {
+        Self { chroma, ..self }
+    }
+
+    fn kernel(&self, plane: Plane) -> Kernel {
+        if plane.is_chroma() {
+            self.chroma
+        } else {
+            self.luma
+        }
+    }
+
+    /// Scales the `crop` region of `source` into `frame` of `target` size.
+    pub fn resample(
+        &self,
+        frame: &mut impl Frame,
+        target: Size,
+        source: &(impl Frame + Sync),
+        crop: CropRect,
+    ) -> Result<()> {
+        self.resample_with(&SingleThread, frame, target, source, crop)
+    }
+
+    /// [`Resampler::resample`] on the threads of `executor`.
+    pub fn resample_with(
+        &self,
+        executor: &impl Executor,
+        frame: &mut impl Frame,
+        target: Size,
+        source: &(impl Frame + Sync),
+        crop: CropRect,
+    ) -> Result<()> {
+        frame.set_frame_size(target)?;
+        let source_size = source.frame_size();
+        if target.area() == 0 || source_size.area() == 0 {
+            return Ok(());
+        }
+        let (target_width, target_height) = (target.width as usize, target.height as usize);
+        let horizontal = Plane::ALL.map(|plane| {
+            compute_taps(
+                self.kernel(plane),
+                crop.x,
+                crop.width,
+                source_size.width as usize,
+                target_width,
+            )
+        });
+        let vertical = Plane::ALL.map(|plane| {
+            compute_taps(
+                self.kernel(plane),
+                crop.y,
+                crop.height,
+                source_size.height as usize,
+                target_height,
+            )
+        });
+
+        // Horizontal pass into intermediate lines of all source lines used by the vertical pass.
+        let first_line = vertical
+            .iter()
+            .flatten()
+            .map(|taps| taps.start)
+            .min()
+            .unwrap_or(0);
+        let last_line = vertical
+            .iter()
+            .flatten()
+            .map(|taps| taps.start + taps.weights.len())
+            .max()
+            .unwrap_or(0);
+        let mut intermediate = vec![[0.0f32; 3]; (last_line - first_line) * target_width];
+        for_each_row_mut(
+            executor,
+            &mut intermediate,
+            last_line - first_line,
+            target_width,
+            target_width,
+            |y, row| {
+                let line = source.line(first_line + y);
+                for (x, out) in row.iter_mut().enumerate() {
+                    for plane in Plane::ALL {
+                        let taps = &horizontal[plane as usize][x];
+                        out[plane as usize] = taps
+                            .weights
+                            .iter()
+                            .zip(&line[taps.start..])
+                            .map(|(weight, pixel)| weight * plane.get(pixel) as f32)
+                            .sum();
+                    }
+                }
+            },
+        )?;
+
+        for_each_line_mut(executor, frame, |y, line| {
+            for (x, out) in line.iter_mut().enumerate() {
+                let mut sums = [0.0f32; 3];
+                for plane in Plane::ALL {
+                    let taps = &vertical[plane as usize][y];
+                    sums[plane as usize] = taps
+                        .weights
+                        .iter()
+                        .enumerate()
+                        .map(|(i, weight)| {
+                            let row = taps.start + i - first_line;
+                            weight * intermediate[row * target_width + x][plane as usize]
+                        })
+                        .sum();
+                }
+                *out = PixelYc {
+                    y: quantize(sums[0], Plane::Y),
+                    cb: quantize(sums[1], Plane::Cb),
+                    cr: quantize(sums[2], Plane::Cr),
+                };
+            }
+        })
+    }
+}
+
+/// Rounds `value` into the range of `plane`.
+pub(crate) fn quantize(value: f32, plane: Plane) -> i16 {
+    let range = plane.range();
+    value
+        .round()
+        .clamp(*range.start() as f32, *range.end() as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::Job, filter::OwnedFrame};
+
+    const KERNELS: [Kernel; 7] = [
+        Kernel::Nearest,
+        Kernel::Bilinear,
+        Kernel::CATMULL_ROM,
+        Kernel::MITCHELL,
+        Kernel::B_SPLINE,
+        Kernel::LANCZOS3,
+        Kernel::Area,
+    ];
+
+    struct Sequential(usize);
+
+    impl Executor for Sequential {
+        fn execute(&self, job: &Job) -> Result<()> {
+            for id in 0..self.0 {
+                job(id, self.0);
+            }
+            Ok(())
+        }
+    }
+
+    fn pattern(size: Size) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(size);
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = PixelYc {
+                    y: ((x * 977 + y * 611) % 4097) as i16,
+                    cb: ((x * 131 + y * 37) % 4097) as i16 - 2048,
+                    cr: ((x * 53 + y * 271) % 4097) as i16 - 2048,
+                };
+            }
+        }
+        frame
+    }
+
+    fn pixels(frame: &impl Frame) -> Vec<PixelYc> {
+        frame.lines().flatten().copied().collect()
+    }
+
+    #[test]
+    fn identity_keeps_pixels() {
+        let size = Size {
+            width: 13,
+            height: 9,
+        };
+        let source = pattern(size);
+        for kernel in KERNELS {
+            let mut frame = OwnedFrame::new(size);
+            Resampler::new(kernel)
+                .resample(&mut frame, size, &source, CropRect::whole(size))
+                .unwrap();
+            // Kernels with `b > 0` smooth by design, so only the interpolating ones keep every pixel.
+            let interpolating = !matches!(kernel, Kernel::Bicubic { b, .. } if b != 0.0);
+            if interpolating {
+                assert_eq!(pixels(&frame), pixels(&source), "{:?}", kernel);
+            }
+
+            let mut flat = OwnedFrame::new(size);
+            flat.lines_mut().flatten().for_each(|pixel| {
+                *pixel = PixelYc {
+                    y: 1234,
+                    cb: -321,
+                    cr: 567,
+                }
+            });
+            let mut frame = OwnedFrame::new(size);
+            Resampler::new(kernel)
+                .resample(&mut frame, size, &flat, CropRect::whole(size))
+                .unwrap();
+            assert_eq!(pixels(&frame), pixels(&flat), "{:?}", kernel);
+        }
+    }
+
+    #[test]
+    fn taps_sum_to_unity() {
+        for kernel in KERNELS {
+            for (offset, length, source, target) in [
+                (0.0, 16.0, 16, 16),
+                (0.0, 16.0, 16, 41),
+                (0.0, 41.0, 41, 16),
+                (2.5, 10.25, 16, 7),
+                (-1.0, 18.0, 16, 23),
+            ] {
+                for taps in compute_taps(kernel, offset, length, source, target) {
+                    let sum: f32 = taps.weights.iter().sum();
+                    assert!((sum - 1.0).abs() < 1e-5, "{:?} sums to {}", kernel, sum);
+                    assert!(taps.start + taps.weights.len() <= source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn threads_do_not_change_result() {
+        let source = pattern(Size {
+            width: 31,
+            height: 17,
+        });
+        let target = Size {
+            width: 20,
+            height: 29,
+        };
+        let crop = CropRect::whole(source.frame_size());
+        for kernel in KERNELS {
+            let resampler = Resampler::new(kernel).with_chroma(Kernel::Bilinear);
+            let mut single = OwnedFrame::new(target);
+            resampler
+                .resample(&mut single, target, &source, crop)
+                .unwrap();
+            let mut threaded = OwnedFrame::new(target);
+            resampler
+                .resample_with(&Sequential(5), &mut threaded, target, &source, crop)
+                .unwrap();
+            assert_eq!(pixels(&single), pixels(&threaded), "{:?}", kernel);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Scene-change detection over the timeline.
+//!
+//! [`SceneScan`] compares each frame with the previous one by the difference of luma histograms, the mean absolute
+//! difference (SAD) of a luma thumbnail and the edge change ratio. A cut is reported when the weighted score exceeds
+//! both a fixed minimum and the recent scores by a multiple of their deviation, so that busy scenes need larger
+//! changes than static ones.
+
+use super::{
+    editing::{Editing, FrameSource},
+    scope::Histogram,
+    EditFlag, Frame,
+};
+use crate::{AviUtlError, PixelYc, Result};
+use std::{collections::VecDeque, ops::ControlFlow, ops::RangeInclusive};
+
+const HISTOGRAM_BINS: usize = 64;
+/// Longer side of the thumbnail compared by SAD and edges.
+const THUMBNAIL_SIZE: usize = 64;
+/// Luma gradient regarded as an edge.
+const EDGE_THRESHOLD: i32 = PixelYc::Y_MAX as i32 / 16;
+
+/// Differences between consecutive frames, between 0.0 and 1.0 each.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SceneScore {
+    pub histogram: f32,
+    pub sad: f32,
+    pub edge_change: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneCut {
+    /// The first frame of the new scene.
+    pub frame: usize,
+    pub score: SceneScore,
+    /// Weighted score compared against the threshold.
+    pub combined: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneDetector {
+    /// Weights of the histogram difference, SAD and edge change ratio.
+    pub weights: [f32; 3],
+    /// Lowest combined score for a cut.
+    pub min_threshold: f32,
+    /// Standard deviations above the mean of the recent scores for a cut.
+    pub sensitivity: f32,
+    /// Number of recent scores for the adaptive threshold.
+    pub window: usize,
+    /// Fewest frames between cuts.
+    pub min_scene_length: usize,
+    pub source: FrameSource,
+}
+
+impl Default for SceneDetector {
+    fn default() -> Self {
+        Self {
+            weights: [0.4, 0.3, 0.3],
+            min_threshold: 0.3,
+            sensitivity: 3.0,
+            window: 30,
+            min_scene_length: 5,
+            source: FrameSource::default(),
+        }
+    }
+}
+
+/// Features of a frame compared with the next frame.
+#[derive(Debug, Clone)]
+struct FrameFeatures {
+    histogram: Histogram,
+    thumbnail: Vec<i32>,
+    edges: Vec<bool>,
+}
+
+impl FrameFeatures {
+    fn new(frame: &impl Frame) -> Self {
+        let size = frame.frame_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let step = width.max(height).div_ceil(THUMBNAIL_SIZE).max(1);
+        let (columns, rows) = (width.div_ceil(step), height.div_ceil(step));
+        let thumbnail: Vec<i32> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column * step, row * step)))
+            .map(|(x, y)| frame.pixel(x, y).y as i32)
+            .collect();
+        let at = |x: usize, y: usize| thumbnail[y.min(rows - 1) * columns + x.min(columns - 1)];
+        let edges = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (dx, dy) = (at(x + 1, y) - at(x, y), at(x, y + 1) - at(x, y));
+                dx.abs() + dy.abs() > EDGE_THRESHOLD
+            })
+            .collect();
+        Self {
+            histogram: Histogram::luma(frame, None, HISTOGRAM_BINS),
+            thumbnail,
+            edges,
+        }
+    }
+
+    fn score(&self, next: &Self) -> SceneScore {
+        let total = self.histogram.total().max(next.histogram.total()).max(1) as f32;
+        let histogram = self
+            .histogram
+            .bins()
+            .iter()
+            .zip(next.histogram.bins())
+            .map(|(&a, &b)| a.abs_diff(b) as f32)
+            .sum::<f32>()
+            / (2.0 * total);
+        if self.thumbnail.len() != next.thumbnail.len() {
+            return SceneScore {
+                histogram,
+                sad: 1.0,
+                edge_change: 1.0,
+            };
+        }
+        let sad = self
+            .thumbnail
+            .iter()
+            .zip(&next.thumbnail)
+            .map(|(a, b)| (a - b).unsigned_abs() as f32)
+            .sum::<f32>()
+            / (self.thumbnail.len().max(1) as f32 * PixelYc::Y_MAX as f32);
+        // Edges entering and exiting, each against the edges of its own frame.
+        let (mut entering, mut exiting) = (0, 0);
+        for (&before, &after) in self.edges.iter().zip(&next.edges) {
+            entering += (after && !before) as usize;
+            exiting += (before && !after) as usize;
+        }
+        let count = |edges: &[bool]| edges.iter().filter(|&&edge| edge).count().max(1) as f32;
+        let edge_change =
+            (entering as f32 / count(&next.edges)).max(exiting as f32 / count(&self.edges));
+        SceneScore {
+            histogram,
+            sad: sad.min(1.0),
+            edge_change: edge_change.min(1.0),
+        }
+    }
+}
+
+/// State of a detection over consecutive frames.
+#[derive(Debug, Clone)]
+pub struct SceneScan<'d> {
+    detector: &'d SceneDetector,
+    previous: Option<FrameFeatures>,
+    recent: VecDeque<f32>,
+    last_cut: Option<usize>,
+}
+
+impl SceneDetector {
+    pub fn start(&self) -> SceneScan<'_> {
+        SceneScan {
+            detector: self,
+            previous: None,
+            recent: VecDeque::new(),
+            last_cut: None,
+        }
+    }
+
+    pub fn combine(&self, score: &SceneScore) -> f32 {
+        let [histogram, sad, edge_change] = self.weights;
+        let total = (histogram + sad + edge_change).max(f32::EPSILON);
+        (histogram * score.histogram + sad * score.sad + edge_change * score.edge_change) / total
+    }
+
+    /// Detects cuts in `frames`, calling `progress` with the frames done and all before each frame. It fails with
+    /// [`AviUtlError::Cancelled`] if `progress` breaks.
+    pub fn detect(
+        &self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+        mut progress: impl FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> Result<Vec<SceneCut>> {
+        let total = frames.clone().count();
+        let mut scan = self.start();
+        let mut cuts = vec![];
+        for (done, index) in frames.enumerate() {
+            if progress(done, total).is_break() {
+                return Err(AviUtlError::Cancelled);
+            }
+            cuts.extend(scan.push(index, &editing.get_frame_from(self.source, index)?));
+        }
+        let _ = progress(total, total);
+        Ok(cuts)
+    }
+
+    /// Adds `flag`, such as [`EditFlag::KEYFRAME`] or [`EditFlag::MARK_FRAME`], to the frames of `cuts` after saving
+    /// the undo state.
+    pub fn apply(editing: &Editing, cuts: &[SceneCut], flag: EditFlag) -> Result<()> {
+        editing.insert_edit_flag(cuts.iter().map(|cut| cut.frame), flag)?;
+        Ok(())
+    }
+}
+
+impl SceneScan<'_> {
+    /// Compares `frame` at `index` with the frame pushed before, returning a cut if it starts a new scene.
+    pub fn push(&mut self, index: usize, frame: &impl Frame) -> Option<SceneCut> {
+        let features = FrameFeatures::new(frame);
+        let previous = self.previous.replace(features);
+        let score = previous?.score(self.previous.as_ref().unwrap());
+        let combined = self.detector.combine(&score);
+
+        let count = self.recent.len().max(1) as f32;
+        let mean = self.recent.iter().sum::<f32>() / count;
+        let deviation =
+            (self.recent.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / count).sqrt();
+        let threshold = self
+            .detector
+            .min_threshold
+            .max(mean + self.detector.sensitivity * deviation);
+        let apart = self
+            .last_cut
+            .is_none_or(|last| index >= last + self.detector.min_scene_length);
+
+        if self.recent.len() == self.detector.window.max(1) {
+            self.recent.pop_front();
+        }
+        if combined > threshold && apart {
+            self.last_cut = Some(index);
+            // Starts the statistics of the new scene afresh.
+            self.recent.clear();
+            Some(SceneCut {
+                frame: index,
+                score,
+                combined,
+            })
+        } else {
+            self.recent.push_back(combined);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::tests::padded_frame, Size};
+
+    const SIZE: Size = Size {
+        width: 48,
+        height: 36,
+    };
+
+    /// Frame `index` of a gradient panning slowly in scene 0, or of a fixed texture in scene 1.
+    fn textured(scene: usize, index: usize) -> impl Frame {
+        padded_frame(SIZE, |x, y| match scene {
+            0 => (x * 40 + y * 30 + index * 8) as i16,
+            _ => (4000 - (x * 7 + y * 13) % 17 * 150) as i16,
+        })
+    }
+
+    /// Cuts in frames of flat `lumas`.
+    fn flat_cuts(detector: &SceneDetector, lumas: &[i16]) -> Vec<usize> {
+        let mut scan = detector.start();
+        lumas
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &luma)| scan.push(index, &padded_frame(SIZE, |_, _| luma)))
+            .map(|cut| cut.frame)
+            .collect()
+    }
+
+    #[test]
+    fn detects_cut() {
+        let detector = SceneDetector::default();
+        let mut scan = detector.start();
+        let cuts: Vec<usize> = (0..30)
+            .filter_map(|index| scan.push(index, &textured((index >= 18) as usize, index)))
+            .map(|cut| cut.frame)
+            .collect();
+        assert_eq!(cuts, [18]);
+    }
+
+    #[test]
+    fn cuts_keep_min_scene_length() {
+        let lumas = [[1000; 10].as_slice(), &[2000; 2], &[3000; 10]].concat();
+        assert_eq!(flat_cuts(&SceneDetector::default(), &lumas), [10]);
+        let detector = SceneDetector {
+            min_scene_length: 2,
+            ..Default::default()
+        };
+        assert_eq!(flat_cuts(&detector, &lumas), [10, 12]);
+    }
+
+    #[test]
+    fn busy_scenes_need_larger_changes() {
+        let detector = SceneDetector::default();
+        let still = [[1000; 10].as_slice(), &[2000; 5]].concat();
+        assert_eq!(flat_cuts(&detector, &still), [10]);
+        // Flashing between 1000 and 3000 raises the threshold over the change to 2000, after the first flash.
+        let flashing: Vec<i16> = (0..20)
+            .map(|index| if index % 2 == 0 { 1000 } else { 3000 })
+            .chain([2000; 5])
+            .collect();
+        assert_eq!(flat_cuts(&detector, &flashing), [1]);
+    }
+}
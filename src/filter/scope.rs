@@ -0,0 +1,458 @@
+//! Host-independent analysis scopes of [`Frame`]s.
+//!
+//! Each scope is computed from a region of a frame into plain counts, and can be rendered into an [`OwnedFrame`] of
+//! its natural size, which can be scaled by [`Resampler`] for display.
+//!
+//! [`Resampler`]: super::resample::Resampler
+
+use super::{Frame, OwnedFrame};
+use crate::{
+    colorspace::{gray, ColorSpace},
+    PixelRgb, PixelYc, Point, Rect, Size,
+};
+
+/// Clips `region` by the bounds of `frame`, or the whole frame if `None`.
+fn clip_region(frame: &impl Frame, region: Option<Rect>) -> Option<Rect> {
+    let bounds = Rect {
+        size: frame.frame_size(),
+        point: Point::new(),
+    };
+    region.map_or(Some(bounds), |region| region.intersection(bounds))
+}
+
+/// Calls `f` with `(x, pixel)` of every pixel in `region`, where `x` is relative to the region.
+fn for_each_pixel(frame: &impl Frame, region: Option<Rect>, mut f: impl FnMut(usize, &PixelYc)) {
+    let Some(region) = clip_region(frame, region) else {
+        return;
+    };
+    let (left, right) = (region.left() as usize, region.right() as usize);
+    for y in region.top()..region.bottom() {
+        for (x, pixel) in frame.line(y as usize)[left..right].iter().enumerate() {
+            f(x, pixel);
+        }
+    }
+}
+
+/// Maps `value` in `0..=max` into `0..bins`.
+fn bin_of(value: i32, max: i32, bins: usize) -> usize {
+    (value.clamp(0, max) as usize * bins / (max as usize + 1)).min(bins - 1)
+}
+
+/// Brightness of `count` in log scale against `max`, between 0.0 and 1.0.
+fn intensity(count: u32, max: u32) -> f32 {
+    if count == 0 || max == 0 {
+        0.0
+    } else {
+        (count as f32).ln_1p() / (max as f32).ln_1p()
+    }
+}
+
+fn scale_rgb(color: PixelRgb, level: f32) -> [f32; 3] {
+    let level = level.clamp(0.0, 1.0) / 255.0;
+    [
+        color.r as f32 * level,
+        color.g as f32 * level,
+        color.b as f32 * level,
+    ]
+}
+
+const RED: PixelRgb = PixelRgb { r: 255, g: 0, b: 0 };
+const GREEN: PixelRgb = PixelRgb { r: 0, g: 255, b: 0 };
+const BLUE: PixelRgb = PixelRgb { r: 0, g: 0, b: 255 };
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Histogram {
+    bins: Vec<u32>,
+}
+
+impl Histogram {
+    /// Counts luma between 0 and 4096 into `bins` bins.
+    pub fn luma(frame: &impl Frame, region: Option<Rect>, bins: usize) -> Self {
+        assert!(bins > 0);
+        let mut counts = vec![0; bins];
+        for_each_pixel(frame, region, |_, pixel| {
+            counts[bin_of(pixel.y as i32, PixelYc::Y_MAX as i32, bins)] += 1;
+        });
+        Self { bins: counts }
+    }
+
+    pub fn bins(&self) -> &[u32] {
+        &self.bins
+    }
+
+    pub fn max(&self) -> u32 {
+        self.bins.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bins.iter().map(|&count| count as u64).sum()
+    }
+
+    /// Renders bars of the width of bins and `height`, scaled linearly by the most frequent bin.
+    pub fn render(&self, height: u32) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(Size {
+            width: self.bins.len() as u32,
+            height,
+        });
+        let max = self.max();
+        if max != 0 {
+            for (x, &count) in self.bins.iter().enumerate() {
+                let bar = (count as u64 * height as u64).div_ceil(max as u64) as usize;
+                for y in height as usize - bar..height as usize {
+                    *frame.pixel_mut(x, y) = gray(1.0);
+                }
+            }
+        }
+        frame
+    }
+}
+
+/// Histograms of 8-bit RGB components, of 256 bins each.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RgbHistogram {
+    pub r: Histogram,
+    pub g: Histogram,
+    pub b: Histogram,
+}
+
+impl RgbHistogram {
+    pub fn new(frame: &impl Frame, region: Option<Rect>, color_space: &ColorSpace) -> Self {
+        let mut counts = [[0; 256]; 3];
+        for_each_pixel(frame, region, |_, pixel| {
+            let rgb = color_space.yc_to_rgb(*pixel);
+            counts[0][rgb.r as usize] += 1;
+            counts[1][rgb.g as usize] += 1;
+            counts[2][rgb.b as usize] += 1;
+        });
+        let [r, g, b] = counts.map(|bins| Histogram {
+            bins: bins.to_vec(),
+        });
+        Self { r, g, b }
+    }
+
+    /// Renders bars of all components additively in their colors, scaled by the most frequent bin of all.
+    pub fn render(&self, height: u32) -> OwnedFrame {
+        let size = Size { width: 256, height };
+        let max = self.r.max().max(self.g.max()).max(self.b.max());
+        let mut rgb = vec![[0.0f32; 3]; size.area()];
+        if max != 0 {
+            for (channel, histogram) in [&self.r, &self.g, &self.b].into_iter().enumerate() {
+                for (x, &count) in histogram.bins.iter().enumerate() {
+                    let bar = (count as u64 * height as u64).div_ceil(max as u64) as usize;
+                    for y in height as usize - bar..height as usize {
+                        rgb[y * 256 + x][channel] = 1.0;
+                    }
+                }
+            }
+        }
+        render_rgb(size, &rgb)
+    }
+}
+
+fn render_rgb(size: Size, rgb: &[[f32; 3]]) -> OwnedFrame {
+    let mut frame = OwnedFrame::new(size);
+    let width = size.width as usize;
+    for (y, line) in frame.lines_mut().enumerate() {
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = ColorSpace::AVIUTL.rgb_f32_to_yc(rgb[y * width + x]);
+        }
+    }
+    frame
+}
+
+/// Counts of levels per column, where level 0 is the lowest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Waveform {
+    counts: Vec<u32>,
+    columns: usize,
+    levels: usize,
+}
+
+impl Waveform {
+    fn empty(columns: usize, levels: usize) -> Self {
+        Self {
+            counts: vec![0; columns * levels],
+            columns,
+            levels,
+        }
+    }
+
+    /// Counts luma between 0 and 4096 into `levels` levels for each column of the region.
+    pub fn luma(frame: &impl Frame, region: Option<Rect>, levels: usize) -> Self {
+        assert!(levels > 0);
+        let columns = clip_region(frame, region).map_or(0, |region| region.size.width as usize);
+        let mut waveform = Self::empty(columns, levels);
+        for_each_pixel(frame, region, |x, pixel| {
+            let level = bin_of(pixel.y as i32, PixelYc::Y_MAX as i32, levels);
+            waveform.counts[level * columns + x] += 1;
+        });
+        waveform
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    pub fn get(&self, column: usize, level: usize) -> u32 {
+        assert!(column < self.columns);
+        self.counts[level * self.columns + column]
+    }
+
+    pub fn max(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Renders counts in log scale into a frame of `columns` x `levels`, with the highest level at the top.
+    pub fn render(&self) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(self.render_size());
+        let max = self.max();
+        for (y, line) in frame.lines_mut().enumerate() {
+            let level = self.levels - 1 - y;
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = gray(intensity(self.get(x, level), max));
+            }
+        }
+        frame
+    }
+
+    fn render_size(&self) -> Size {
+        Size {
+            width: self.columns as u32,
+            height: self.levels as u32,
+        }
+    }
+}
+
+/// Waveforms of 8-bit RGB components, to be shown side by side as a parade.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RgbParade {
+    pub r: Waveform,
+    pub g: Waveform,
+    pub b: Waveform,
+}
+
+impl RgbParade {
+    pub fn new(
+        frame: &impl Frame,
+        region: Option<Rect>,
+        levels: usize,
+        color_space: &ColorSpace,
+    ) -> Self {
+        assert!(levels > 0);
+        let columns = clip_region(frame, region).map_or(0, |region| region.size.width as usize);
+        let mut waveforms = [(); 3].map(|_| Waveform::empty(columns, levels));
+        for_each_pixel(frame, region, |x, pixel| {
+            let rgb = color_space.yc_to_rgb(*pixel);
+            for (waveform, value) in waveforms.iter_mut().zip([rgb.r, rgb.g, rgb.b]) {
+                let level = bin_of(value as i32, 255, levels);
+                waveform.counts[level * columns + x] += 1;
+            }
+        });
+        let [r, g, b] = waveforms;
+        Self { r, g, b }
+    }
+
+    /// Renders the waveforms of red, green and blue from left to right in their colors.
+    pub fn render(&self) -> OwnedFrame {
+        let Size { width, height } = self.r.render_size();
+        let size = Size {
+            width: width * 3,
+            height,
+        };
+        let total_width = size.width as usize;
+        let max = self.r.max().max(self.g.max()).max(self.b.max());
+        let mut rgb = vec![[0.0f32; 3]; size.area()];
+        for (i, (waveform, color)) in [(&self.r, RED), (&self.g, GREEN), (&self.b, BLUE)]
+            .into_iter()
+            .enumerate()
+        {
+            for y in 0..height as usize {
+                let level = waveform.levels - 1 - y;
+                for x in 0..width as usize {
+                    rgb[y * total_width + i * width as usize + x] =
+                        scale_rgb(color, intensity(waveform.get(x, level), max));
+                }
+            }
+        }
+        render_rgb(size, &rgb)
+    }
+}
+
+/// Counts of chroma on a square grid, where Cb increases rightward and Cr increases upward.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Vectorscope {
+    counts: Vec<u32>,
+    size: usize,
+}
+
+impl Vectorscope {
+    /// Counts Cb and Cr between -2048 and 2048 into `size` x `size` cells.
+    pub fn new(frame: &impl Frame, region: Option<Rect>, size: usize) -> Self {
+        assert!(size > 0);
+        let mut counts = vec![0; size * size];
+        let range = (PixelYc::C_MAX - PixelYc::C_MIN) as i32;
+        for_each_pixel(frame, region, |_, pixel| {
+            let x = bin_of(pixel.cb as i32 - PixelYc::C_MIN as i32, range, size);
+            let y = bin_of(PixelYc::C_MAX as i32 - pixel.cr as i32, range, size);
+            counts[y * size + x] += 1;
+        });
+        Self { counts, size }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Count of the cell, where `(0, 0)` is the top-left.
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        assert!(x < self.size);
+        self.counts[y * self.size + x]
+    }
+
+    pub fn max(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Renders counts in log scale as brightness, colored by the chroma of each cell.
+    pub fn render(&self) -> OwnedFrame {
+        let size = self.size as u32;
+        let mut frame = OwnedFrame::new(Size {
+            width: size,
+            height: size,
+        });
+        let max = self.max();
+        let range = (PixelYc::C_MAX - PixelYc::C_MIN) as f32;
+        let center = |i: usize| (i as f32 + 0.5) / self.size as f32 * range;
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                let count = self.get(x, y);
+                if count == 0 {
+                    continue;
+                }
+                let level = intensity(count, max);
+                *pixel = PixelYc {
+                    cb: (center(x) + PixelYc::C_MIN as f32).round() as i16,
+                    cr: (PixelYc::C_MAX as f32 - center(y)).round() as i16,
+                    ..gray(level)
+                };
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::padded_frame;
+
+    const SIZE: Size = Size {
+        width: 4,
+        height: 3,
+    };
+
+    /// Lumas in each quarter from left to right, with white padding to be ignored.
+    fn ramp() -> OwnedFrame {
+        padded_frame(SIZE, |x, _| [0, 1100, 2100, 4096][x])
+    }
+
+    fn region(x: i32, y: i32, width: u32, height: u32) -> Option<Rect> {
+        Some(Rect {
+            size: Size { width, height },
+            point: Point { x, y },
+        })
+    }
+
+    #[test]
+    fn values_map_into_bins() {
+        assert_eq!(bin_of(0, 4096, 16), 0);
+        assert_eq!(bin_of(256, 4096, 16), 0);
+        assert_eq!(bin_of(257, 4096, 16), 1);
+        assert_eq!(bin_of(4096, 4096, 16), 15);
+        assert_eq!(bin_of(-10, 4096, 16), 0);
+        assert_eq!(bin_of(5000, 4096, 16), 15);
+        assert_eq!(bin_of(255, 255, 256), 255);
+    }
+
+    #[test]
+    fn histograms_count_the_region() {
+        let histogram = Histogram::luma(&ramp(), None, 4);
+        assert_eq!(histogram.bins(), [3, 3, 3, 3]);
+        assert_eq!(histogram.total(), 12);
+
+        let histogram = Histogram::luma(&ramp(), region(2, 1, 10, 10), 4);
+        assert_eq!(histogram.bins(), [0, 0, 2, 2]);
+        assert_eq!(Histogram::luma(&ramp(), region(5, 0, 2, 2), 4).total(), 0);
+
+        let rendered =
+            Histogram::luma(&padded_frame(SIZE, |x, y| (x * y * 1024) as i16), None, 2).render(4);
+        let heights: Vec<_> = (0..2)
+            .map(|x| {
+                (0..4)
+                    .filter(|&y| rendered.pixel(x, y).y == PixelYc::Y_MAX)
+                    .count()
+            })
+            .collect();
+        assert_eq!(heights, [4, 2]);
+    }
+
+    #[test]
+    fn rgb_histograms_agree_on_gray() {
+        let histogram = RgbHistogram::new(&ramp(), None, &ColorSpace::AVIUTL);
+        assert_eq!(histogram.r, histogram.g);
+        assert_eq!(histogram.g, histogram.b);
+        assert_eq!(histogram.r.bins()[0], 3);
+        assert_eq!(histogram.r.bins()[255], 3);
+        assert_eq!(histogram.r.total(), 12);
+    }
+
+    #[test]
+    fn waveforms_count_per_column() {
+        let waveform = Waveform::luma(&ramp(), region(1, 0, 3, 2), 4);
+        assert_eq!((waveform.columns(), waveform.levels()), (3, 4));
+        for (column, level) in [(0, 1), (1, 2), (2, 3)] {
+            for l in 0..4 {
+                let expected = if l == level { 2 } else { 0 };
+                assert_eq!(waveform.get(column, l), expected, "{} {}", column, l);
+            }
+        }
+        let rendered = waveform.render();
+        assert_eq!(rendered.pixel(2, 0).y, PixelYc::Y_MAX);
+        assert_eq!(rendered.pixel(2, 1).y, 0);
+
+        let parade = RgbParade::new(&ramp(), None, 2, &ColorSpace::AVIUTL);
+        assert_eq!(parade.r, parade.b);
+        assert_eq!(parade.g.get(0, 0), 3);
+        assert_eq!(parade.g.get(3, 1), 3);
+        assert_eq!(
+            parade.render().frame_size(),
+            Size {
+                width: 12,
+                height: 2
+            }
+        );
+    }
+
+    #[test]
+    fn vectorscope_places_chroma() {
+        let mut frame = padded_frame(SIZE, |_, _| 2048);
+        frame.pixel_mut(0, 0).cb = PixelYc::C_MAX;
+        frame.pixel_mut(0, 0).cr = PixelYc::C_MAX;
+        frame.pixel_mut(1, 0).cb = PixelYc::C_MIN;
+        frame.pixel_mut(1, 0).cr = PixelYc::C_MIN;
+        let scope = Vectorscope::new(&frame, None, 5);
+        assert_eq!(scope.get(4, 0), 1);
+        assert_eq!(scope.get(0, 4), 1);
+        assert_eq!(scope.get(2, 2), 10);
+        assert_eq!(scope.max(), 10);
+
+        let rendered = scope.render();
+        assert_eq!(rendered.pixel(2, 2).y, PixelYc::Y_MAX);
+        assert!(rendered.pixel(4, 0).cb > 0 && rendered.pixel(4, 0).cr > 0);
+        assert_eq!(rendered.pixel(1, 1).y, 0);
+    }
+}
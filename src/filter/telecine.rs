@@ -0,0 +1,448 @@
+//! Inverse telecine by finding the 3:2 pulldown cadence of the timeline.
+//!
+//! 3:2 pulldown turns four film frames A, B, C and D into five video frames of the fields AA, BB, BC, CD and DD. The
+//! third and the fourth frame are combed, and weaving their first field with the second field of the previous frame
+//! recovers B and C, of which B is a duplicate. [`TelecineScan`] measures the combing of each frame as is and matched
+//! with the previous frame, and finds the position of each frame in the cycle by the frames around it. The position
+//! follows one cadence through each run of frames, which only changes at edits of the timeline or where another
+//! cadence holds for a whole cycle, so that every cycle of a run has exactly one duplicate.
+//!
+//! [`TelecineAnalyzer::apply`] writes the positions to [`FrameStatus::index24fps`] and drops the duplicates by
+//! [`EditFlag::DEL_FRAME`]. Keeping only the first field of the combed frames is left to
+//! [`TelecineAnalyzer::drop_fields`], as it halves their vertical resolution.
+
+use super::{
+    deinterlace::FieldOrder, editing::Editing, frame_status::FrameStatus, EditFlag, Frame,
+    FrameInterlace, OwnedFrame,
+};
+use crate::{AviUtlError, PixelYc, Result};
+use std::{ops::ControlFlow, ops::Range, ops::RangeInclusive};
+
+/// Frames of a pulldown cycle.
+pub const CYCLE: usize = 5;
+/// Positions in the cycle whose fields come from two film frames.
+const COMBED: [usize; 2] = [2, 3];
+/// Position in the cycle of the frame duplicating the previous one after field matching.
+const DUPLICATE: usize = 2;
+
+/// Fields woven into a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldMatch {
+    /// Both fields of the frame itself.
+    #[default]
+    Current,
+    /// The first field of the frame and the second field of the previous frame.
+    Previous,
+}
+
+/// What was found for a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelecineFrame {
+    pub frame: usize,
+    /// Ratio of combed pixels in the frame as is.
+    pub combing: f32,
+    /// Ratio of combed pixels matched with the previous frame, or `None` at the start of a segment.
+    pub combing_previous: Option<f32>,
+    /// Whether an edit of the timeline precedes the frame.
+    pub cut: bool,
+    /// Position in the cycle, or `None` where no cadence was found.
+    pub phase: Option<usize>,
+    pub field_match: FieldMatch,
+    pub duplicate: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelecineAnalyzer {
+    pub field_order: FieldOrder,
+    /// Luma difference between neighboring lines regarded as combing.
+    pub comb_threshold: i16,
+    /// Ratio of combed pixels for a combed frame.
+    pub min_combed: f32,
+    /// Frames around each frame to find its position in the cycle.
+    pub window: usize,
+    /// Whether [`TelecineAnalyzer::apply`] also makes the frames matched with the previous frame keep only their first
+    /// field.
+    pub drop_fields: bool,
+}
+
+impl Default for TelecineAnalyzer {
+    fn default() -> Self {
+        Self {
+            field_order: FieldOrder::default(),
+            comb_threshold: PixelYc::Y_MAX / 32,
+            min_combed: 0.01,
+            window: 3 * CYCLE,
+            drop_fields: false,
+        }
+    }
+}
+
+/// Ratio of pixels combed by weaving the first field of `first` and the second field of `second`.
+fn combing(first: &impl Frame, second: &impl Frame, order: FieldOrder, threshold: i16) -> f32 {
+    let size = first.frame_size();
+    let line = |y: usize| {
+        if order.first().contains(y) {
+            first.line(y)
+        } else {
+            second.line(y)
+        }
+    };
+    let threshold = (threshold as i32).pow(2);
+    let mut combed = 0usize;
+    for y in 1..(size.height as usize).saturating_sub(1) {
+        let (above, current, below) = (line(y - 1), line(y), line(y + 1));
+        combed += above
+            .iter()
+            .zip(current)
+            .zip(below)
+            .filter(|((a, b), c)| (b.y as i32 - a.y as i32) * (b.y as i32 - c.y as i32) > threshold)
+            .count();
+    }
+    combed as f32 / size.area().max(1) as f32
+}
+
+/// Agreement of the `evidence` of a frame with its `position` in the cycle: 1 for combing where expected, -1 for
+/// combing where not expected or a clean frame where combing is expected, and 0 otherwise.
+fn agreement(evidence: i32, position: usize) -> i32 {
+    match (evidence, COMBED.contains(&position)) {
+        (1, true) => 1,
+        (1, false) | (-1, true) => -1,
+        _ => 0,
+    }
+}
+
+/// State of an analysis over consecutive frames.
+#[derive(Debug)]
+pub struct TelecineScan<'a> {
+    analyzer: &'a TelecineAnalyzer,
+    previous: Option<OwnedFrame>,
+    frames: Vec<TelecineFrame>,
+}
+
+impl TelecineAnalyzer {
+    pub fn start(&self) -> TelecineScan<'_> {
+        TelecineScan {
+            analyzer: self,
+            previous: None,
+            frames: vec![],
+        }
+    }
+
+    /// Analyzes the source frames of `frames`, calling `progress` with the frames done and all before each frame.
+    /// Frames not following the previous frame in the source are edits. It fails with [`AviUtlError::Cancelled`] if
+    /// `progress` breaks.
+    pub fn analyze(
+        &self,
+        editing: &Editing,
+        frames: RangeInclusive<usize>,
+        mut progress: impl FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> Result<Vec<TelecineFrame>> {
+        let total = frames.clone().count();
+        let mut scan = self.start();
+        let mut last_video = None;
+        for (done, index) in frames.enumerate() {
+            if progress(done, total).is_break() {
+                return Err(AviUtlError::Cancelled);
+            }
+            let video = editing.get_frame_status(index)?.video_id;
+            let cut = last_video.is_some_and(|last| video != last + 1);
+            last_video = Some(video);
+            scan.push(index, &editing.get_source_frame(index)?, cut);
+        }
+        let _ = progress(total, total);
+        Ok(scan.finish())
+    }
+
+    /// Writes the cadence of `frames`, sorted by frame, into their status after saving the undo state. Frames in the
+    /// cadence get their position as [`FrameStatus::index24fps`] and the duplicates get [`EditFlag::DEL_FRAME`], while
+    /// frames out of the cadence only have the position reset. With [`TelecineAnalyzer::drop_fields`], the frames
+    /// matched with the previous frame also keep only their first field by [`FrameStatus::interlace_mode`].
+    pub fn apply(&self, editing: &Editing, frames: &[TelecineFrame]) -> Result<()> {
+        let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+            return Ok(());
+        };
+        let first_field = match self.field_order {
+            FieldOrder::TopFirst => FrameInterlace::Odd,
+            FieldOrder::BottomFirst => FrameInterlace::Even,
+        };
+        editing.update_frame_status(
+            first.frame..=last.frame,
+            |frame, status: &mut FrameStatus| {
+                let Ok(i) = frames.binary_search_by_key(&frame, |telecine| telecine.frame) else {
+                    return;
+                };
+                let telecine = &frames[i];
+                status.index24fps = telecine.phase.unwrap_or(0);
+                if telecine.phase.is_some() {
+                    status
+                        .edit_flag
+                        .set(EditFlag::DEL_FRAME, telecine.duplicate);
+                    if self.drop_fields {
+                        status.interlace_mode = match telecine.field_match {
+                            FieldMatch::Current => FrameInterlace::Normal,
+                            FieldMatch::Previous => first_field,
+                        };
+                    }
+                }
+            },
+        )?;
+        Ok(())
+    }
+}
+
+impl TelecineScan<'_> {
+    /// Measures `frame` at `index`, which follows an edit if `cut`.
+    pub fn push(&mut self, index: usize, frame: &impl Frame, cut: bool) {
+        let analyzer = self.analyzer;
+        let previous = self
+            .previous
+            .as_ref()
+            .filter(|previous| !cut && previous.frame_size() == frame.frame_size());
+        let combing_previous = previous.map(|previous| {
+            combing(
+                frame,
+                previous,
+                analyzer.field_order,
+                analyzer.comb_threshold,
+            )
+        });
+        self.frames.push(TelecineFrame {
+            frame: index,
+            combing: combing(frame, frame, analyzer.field_order, analyzer.comb_threshold),
+            combing_previous,
+            cut: cut || (self.previous.is_some() && previous.is_none()),
+            phase: None,
+            field_match: FieldMatch::Current,
+            duplicate: false,
+        });
+        self.previous = Some(OwnedFrame::from_frame(frame));
+    }
+
+    /// Evidence of a frame for being combed in the cadence: 1 if matching the previous frame removes its combing,
+    /// -1 if it is clean, and 0 otherwise.
+    fn evidence(&self, frame: &TelecineFrame) -> i32 {
+        if frame.combing < self.analyzer.min_combed {
+            -1
+        } else if frame
+            .combing_previous
+            .is_some_and(|previous| previous < frame.combing / 2.0)
+        {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Offset of the cycle best explaining the evidence of `window` in a segment starting at `start`.
+    fn find_offset(&self, evidence: &[i32], window: Range<usize>, start: usize) -> Option<usize> {
+        if evidence[window.clone()].iter().filter(|&&e| e > 0).count() < 2 {
+            return None;
+        }
+        (0..CYCLE)
+            .map(|offset| {
+                let score: i32 = window
+                    .clone()
+                    .map(|i| agreement(evidence[i], (i - start + offset) % CYCLE))
+                    .sum();
+                (score, offset)
+            })
+            .filter(|&(score, _)| score > 0)
+            .max_by_key(|&(score, offset)| (score, std::cmp::Reverse(offset)))
+            .map(|(_, offset)| offset)
+    }
+
+    /// Offsets of the cycle for the frames of `segment`. The offset found around a frame only replaces the current
+    /// one when it holds for a whole cycle, and the first offset found also covers the frames before it. As the
+    /// offsets found lag behind a change of the cadence, the new offset also takes the frames before the change which
+    /// it explains at least as well as the old one.
+    fn segment_offsets(&self, evidence: &[i32], segment: Range<usize>) -> Vec<Option<usize>> {
+        let half = self.analyzer.window.max(CYCLE) / 2;
+        let start = segment.start;
+        let local: Vec<Option<usize>> = segment
+            .clone()
+            .map(|i| {
+                let window = i.saturating_sub(half).max(start)..(i + half + 1).min(segment.end);
+                self.find_offset(evidence, window, start)
+            })
+            .collect();
+        let evidence = &evidence[segment];
+        let mut current = None;
+        let mut switched = 0;
+        let mut offsets: Vec<Option<usize>> = Vec::with_capacity(local.len());
+        for k in 0..local.len() {
+            let held = local.get(k..k + CYCLE).is_some_and(|run| {
+                run.iter()
+                    .all(|&offset| offset.is_some() && offset == local[k])
+            });
+            if held && local[k] != current {
+                if let (Some(old), Some(new)) = (current, local[k]) {
+                    let explains =
+                        |j: usize, offset: usize| agreement(evidence[j], (j + offset) % CYCLE);
+                    let mut j = k;
+                    while j > switched && explains(j - 1, new) >= explains(j - 1, old) {
+                        j -= 1;
+                        offsets[j] = Some(new);
+                    }
+                }
+                current = local[k];
+                switched = k;
+            }
+            offsets.push(current);
+        }
+        let first = offsets.iter().find_map(|&offset| offset);
+        for offset in offsets.iter_mut().take_while(|offset| offset.is_none()) {
+            *offset = first;
+        }
+        offsets
+    }
+
+    /// Ends the analysis, deciding the position of every frame by the cadence of its run. The frame at
+    /// [`DUPLICATE`] is the duplicate of its cycle, even at the start of a segment where it cannot be matched.
+    pub fn finish(mut self) -> Vec<TelecineFrame> {
+        let evidence: Vec<i32> = self.frames.iter().map(|f| self.evidence(f)).collect();
+        let mut start = 0;
+        for end in 1..=self.frames.len() {
+            if end < self.frames.len() && !self.frames[end].cut {
+                continue;
+            }
+            let offsets = self.segment_offsets(&evidence, start..end);
+            let mut last_duplicate: Option<usize> = None;
+            for (i, offset) in (start..end).zip(offsets) {
+                let phase = offset.map(|offset| (i - start + offset) % CYCLE);
+                let frame = &mut self.frames[i];
+                frame.phase = phase;
+                frame.field_match = if phase.is_some_and(|p| COMBED.contains(&p)) && i > start {
+                    FieldMatch::Previous
+                } else {
+                    FieldMatch::Current
+                };
+                // A change of the cadence must not drop two frames of one cycle.
+                frame.duplicate =
+                    phase == Some(DUPLICATE) && last_duplicate.is_none_or(|last| i >= last + CYCLE);
+                if frame.duplicate {
+                    last_duplicate = Some(i);
+                }
+            }
+            start = end;
+        }
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::tests::padded_frame, Size};
+
+    const SIZE: Size = Size {
+        width: 48,
+        height: 36,
+    };
+
+    /// Film frames woven into the fields of each position in the cycle, as AA BB BC CD DD.
+    const FIELDS: [(usize, usize); CYCLE] = [(0, 0), (1, 1), (1, 2), (2, 3), (3, 3)];
+
+    /// Luma of film frame `film`, whose odd frames are brighter so that weaving neighbors combs.
+    fn film_luma(film: usize, x: usize, y: usize) -> i16 {
+        (500 + (x * 20 + y * 10 + film * 37) % 400 + film % 2 * 1500) as i16
+    }
+
+    /// Frame `index` of a 3:2 pulldown starting at `phase`.
+    fn telecined(index: usize, phase: usize) -> OwnedFrame {
+        let position = index + phase;
+        let (top, bottom) = FIELDS[position % CYCLE];
+        let film = position / CYCLE * 4;
+        padded_frame(SIZE, |x, y| {
+            film_luma(film + if y % 2 == 0 { top } else { bottom }, x, y)
+        })
+    }
+
+    /// Analyzes a pulldown of `len` frames starting at `phase` for each of `segments`, which are joined by cuts if
+    /// `cuts`.
+    fn analyze(segments: &[(usize, usize)], cuts: bool) -> Vec<TelecineFrame> {
+        let analyzer = TelecineAnalyzer::default();
+        let mut scan = analyzer.start();
+        let mut index = 0;
+        for (segment, &(phase, len)) in segments.iter().enumerate() {
+            for i in 0..len {
+                let cut = cuts && segment > 0 && i == 0;
+                scan.push(index, &telecined(i, phase), cut);
+                index += 1;
+            }
+        }
+        scan.finish()
+    }
+
+    /// Checks that `frames` follow the cadence starting at `phase` with one duplicate in every cycle.
+    fn assert_cadence(frames: &[TelecineFrame], phase: usize) {
+        for (i, frame) in frames.iter().enumerate() {
+            let expected = (i + phase) % CYCLE;
+            assert_eq!(frame.phase, Some(expected), "frame {}", frame.frame);
+            assert_eq!(
+                frame.duplicate,
+                expected == DUPLICATE,
+                "frame {}",
+                frame.frame
+            );
+            let matched = i > 0 && COMBED.contains(&expected);
+            assert_eq!(
+                frame.field_match == FieldMatch::Previous,
+                matched,
+                "frame {}",
+                frame.frame
+            );
+        }
+        for cycle in frames.chunks(CYCLE).filter(|cycle| cycle.len() == CYCLE) {
+            assert_eq!(cycle.iter().filter(|frame| frame.duplicate).count(), 1);
+        }
+    }
+
+    #[test]
+    fn finds_cadence() {
+        let frames = analyze(&[(1, 32)], true);
+        assert_cadence(&frames, 1);
+    }
+
+    #[test]
+    fn restarts_at_cut() {
+        let frames = analyze(&[(0, 17), (3, 24)], true);
+        assert!(frames[17].cut);
+        assert_cadence(&frames[..17], 0);
+        assert_cadence(&frames[17..], 3);
+    }
+
+    #[test]
+    fn follows_cadence_change_without_cut() {
+        let frames = analyze(&[(0, 20), (3, 25)], false);
+        assert!(frames.iter().all(|frame| !frame.cut));
+        assert_cadence(&frames[..20], 0);
+        // The new cadence starts at the first frame of the second pulldown, which cannot be matched.
+        assert_eq!(frames[20].phase, Some(3));
+        assert_cadence(&frames[21..], 4);
+        let duplicates: Vec<usize> = frames
+            .iter()
+            .filter(|f| f.duplicate)
+            .map(|f| f.frame)
+            .collect();
+        assert!(duplicates.windows(2).all(|pair| pair[1] - pair[0] >= CYCLE));
+    }
+
+    #[test]
+    fn keeps_cadence_over_clean_frame() {
+        let analyzer = TelecineAnalyzer::default();
+        let mut scan = analyzer.start();
+        for index in 0..30 {
+            let frame = if index == 12 {
+                padded_frame(SIZE, |x, y| film_luma(9, x, y))
+            } else {
+                telecined(index, 0)
+            };
+            scan.push(index, &frame, false);
+        }
+        let frames = scan.finish();
+        assert!(frames
+            .iter()
+            .enumerate()
+            .all(|(i, frame)| frame.phase == Some(i % CYCLE)));
+    }
+}
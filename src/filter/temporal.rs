@@ -0,0 +1,206 @@
+//! Access to neighboring frames for temporal filters.
+//!
+//! [`TemporalWindow`] resolves the frames `n - radius..=n + radius` around the current frame `n` by a [`Boundary`]
+//! policy, optionally skipping frames marked by [`EditFlag::DEL_FRAME`], and fetches them through the filtering
+//! cache of AviUtl, which it sizes for the window. The window repeats frames near the ends, so each frame is fetched
+//! once and shared by its neighbors, as fetching it again would alias the same cache buffer.
+
+use super::{api::Api, editing::Editing, frame_status::EditFlag, BorrowedMutFrame, ProcInfo};
+use crate::{Result, Size};
+use std::{collections::HashMap, rc::Rc};
+
+/// How to resolve neighbors beyond the ends of the timeline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Boundary {
+    /// Repeats the first or last frame.
+    #[default]
+    Clamp,
+    /// Reflects at the first or last frame without repeating it.
+    Mirror,
+}
+
+/// A frame in the window, which is read-only.
+#[derive(Debug)]
+pub struct Neighbor<'a> {
+    /// Offset from the current frame in the window, between `-radius` and `radius`.
+    pub offset: isize,
+    /// Index of the resolved frame in the timeline.
+    pub frame_index: usize,
+    frame: Rc<BorrowedMutFrame<'a>>,
+}
+
+impl<'a> Neighbor<'a> {
+    pub fn frame(&self) -> &BorrowedMutFrame<'a> {
+        &self.frame
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TemporalWindow {
+    radius: usize,
+    pub boundary: Boundary,
+    /// Whether to skip frames marked by [`EditFlag::DEL_FRAME`] when counting neighbors.
+    pub skip_deleted: bool,
+    cache_size: Option<Size>,
+    last_frame: Option<usize>,
+    seeked: bool,
+    indices: Vec<usize>,
+}
+
+impl TemporalWindow {
+    pub fn new(radius: usize) -> Self {
+        Self {
+            radius,
+            boundary: Boundary::default(),
+            skip_deleted: false,
+            cache_size: None,
+            last_frame: None,
+            seeked: true,
+            indices: vec![],
+        }
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    /// Number of frames in the window, `2 * radius + 1`.
+    pub fn len(&self) -> usize {
+        2 * self.radius + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Sizes the filtering cache for the window, to be called in [`super::FilterPlugin::init`] with the largest
+    /// frame size expected.
+    pub fn init(&mut self, api: &Api, max_size: Size) -> Result<()> {
+        api.set_yc_cache_size(max_size, self.len())?;
+        self.cache_size = Some(max_size);
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Forgets the previous frame, so that the next [`TemporalWindow::update`] is treated as a seek.
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+        self.seeked = true;
+        self.indices.clear();
+    }
+
+    /// Resolves the window around the current frame of `proc_info`, resizing the cache if the frame got larger.
+    pub fn update(&mut self, proc_info: &ProcInfo) -> Result<()> {
+        let max_size = proc_info.max_size;
+        if self
+            .cache_size
+            .is_none_or(|size| size.width < max_size.width || size.height < max_size.height)
+        {
+            self.init(proc_info.editing.api(), max_size)?;
+        }
+        let current = proc_info.current_frame;
+        self.seeked = self
+            .last_frame
+            .is_none_or(|last| current != last && current != last + 1);
+        self.last_frame = Some(current);
+        self.indices = self.resolve(&proc_info.editing, current, proc_info.total_frames)?;
+        Ok(())
+    }
+
+    /// Whether the last [`TemporalWindow::update`] jumped from the previous frame rather than advancing by one.
+    /// Filters accumulating state across frames should reset it then.
+    pub fn seeked(&self) -> bool {
+        self.seeked
+    }
+
+    /// Indices of the resolved frames from `-radius` to `radius`, empty before [`TemporalWindow::update`].
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    fn is_available(&self, editing: &Editing, frame: usize) -> Result<bool> {
+        if !self.skip_deleted {
+            return Ok(true);
+        }
+        let status = editing.get_frame_status(frame)?;
+        Ok(!status.edit_flag.contains(EditFlag::DEL_FRAME))
+    }
+
+    fn resolve(&self, editing: &Editing, current: usize, total: usize) -> Result<Vec<usize>> {
+        let mut before = vec![];
+        let mut frame = current;
+        while before.len() < self.radius && frame > 0 {
+            frame -= 1;
+            if self.is_available(editing, frame)? {
+                before.push(frame);
+            }
+        }
+        let mut after = vec![];
+        let mut frame = current;
+        while after.len() < self.radius && frame + 1 < total {
+            frame += 1;
+            if self.is_available(editing, frame)? {
+                after.push(frame);
+            }
+        }
+        let center = before.len() as isize;
+        let frames: Vec<_> = before
+            .into_iter()
+            .rev()
+            .chain([current])
+            .chain(after)
+            .collect();
+        let last = frames.len() as isize - 1;
+        let radius = self.radius as isize;
+        Ok((-radius..=radius)
+            .map(|offset| {
+                let position = center + offset;
+                let position = match self.boundary {
+                    Boundary::Clamp => position,
+                    Boundary::Mirror if position < 0 => -position,
+                    Boundary::Mirror if position > last => 2 * last - position,
+                    Boundary::Mirror => position,
+                };
+                frames[position.clamp(0, last) as usize]
+            })
+            .collect())
+    }
+
+    /// Fetches the filtered frames of the window from `-radius` to `radius`, each distinct frame once.
+    pub fn frames<'e>(&self, editing: &'e Editing) -> Result<Vec<Neighbor<'e>>> {
+        let radius = self.radius as isize;
+        let mut fetched: HashMap<usize, Rc<BorrowedMutFrame<'e>>> = HashMap::new();
+        self.indices
+            .iter()
+            .zip(-radius..=radius)
+            .map(|(&frame_index, offset)| {
+                let frame = match fetched.get(&frame_index) {
+                    Some(frame) => Rc::clone(frame),
+                    None => {
+                        let frame = Rc::new(editing.get_yc_filtering(frame_index)?);
+                        fetched.insert(frame_index, Rc::clone(&frame));
+                        frame
+                    }
+                };
+                Ok(Neighbor {
+                    offset,
+                    frame_index,
+                    frame,
+                })
+            })
+            .collect()
+    }
+
+    /// Fetches the filtered frame at `offset` from the current frame. `editing` stays borrowed exclusively by the
+    /// neighbor, so that no other neighbor can share its cache buffer; use [`TemporalWindow::frames`] for several.
+    pub fn get<'e>(&self, editing: &'e mut Editing, offset: isize) -> Result<Neighbor<'e>> {
+        let radius = self.radius as isize;
+        assert!((-radius..=radius).contains(&offset));
+        let frame_index = self.indices[(offset + radius) as usize];
+        Ok(Neighbor {
+            offset,
+            frame_index,
+            frame: Rc::new(editing.get_yc_filtering(frame_index)?),
+        })
+    }
+}
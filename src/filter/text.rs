@@ -0,0 +1,408 @@
+//! Host-independent text layout and rasterization on [`Frame`]s.
+//!
+//! Unlike [`Editing::draw_text`], which needs an `HFONT` and AviUtl, [`Font`] loads a TrueType or OpenType font from
+//! a file and renders UTF-8 text in pure Rust. Lines are broken at `\n` only.
+//!
+//! [`Editing::draw_text`]: super::editing::Editing::draw_text
+
+use super::{composite::OPAQUE, Frame};
+use crate::{
+    colorspace::ColorSpace, pixel::lerp, AviUtlError, PixelRgb, PixelYc, Point, Result, Size,
+};
+use ab_glyph::{Font as _, FontVec, GlyphId, PxScale, ScaleFont};
+use std::{fs, io, path::Path};
+
+/// Horizontal alignment of lines in a text block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+    pub color: PixelRgb,
+    /// Width in pixels around the glyphs.
+    pub width: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    pub color: PixelRgb,
+    pub offset: Point,
+    /// Opacity between 0 and [`OPAQUE`], multiplied with [`TextStyle::opacity`].
+    pub opacity: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    /// Height of the font in pixels.
+    pub size: f32,
+    pub color: PixelRgb,
+    /// Opacity between 0 and [`OPAQUE`].
+    pub opacity: u16,
+    pub align: Align,
+    /// Multiplier to the line height of the font.
+    pub line_spacing: f32,
+    pub outline: Option<Outline>,
+    pub shadow: Option<Shadow>,
+    /// The color space to convert the colors into YC.
+    pub color_space: ColorSpace,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 24.0,
+            color: PixelRgb {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+            opacity: OPAQUE,
+            align: Align::Left,
+            line_spacing: 1.0,
+            outline: None,
+            shadow: None,
+            color_space: ColorSpace::AVIUTL,
+        }
+    }
+}
+
+impl TextStyle {
+    /// Margin around the text block for the outline.
+    fn outline_margin(&self) -> usize {
+        self.outline
+            .map_or(0, |outline| outline.width.max(0.0).ceil() as usize)
+    }
+
+    fn shadow_offset(&self) -> (i32, i32) {
+        self.shadow
+            .map_or((0, 0), |shadow| (shadow.offset.x, shadow.offset.y))
+    }
+}
+
+/// A coverage mask of the rendered box, between 0.0 and 1.0.
+struct Mask {
+    data: Vec<f32>,
+    width: usize,
+    height: usize,
+}
+
+impl Mask {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            data: vec![0.0; width * height],
+            width,
+            height,
+        }
+    }
+
+    fn get(&self, x: isize, y: isize) -> f32 {
+        if (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y) {
+            self.data[y as usize * self.width + x as usize]
+        } else {
+            0.0
+        }
+    }
+
+    /// Grows the covered area by `radius` with anti-aliased edges.
+    fn dilate(&self, radius: f32) -> Self {
+        let reach = radius.ceil() as isize + 1;
+        let offsets: Vec<_> = (-reach..=reach)
+            .flat_map(|dy| {
+                (-reach..=reach).map(move |dx| (dx, dy, ((dx * dx + dy * dy) as f32).sqrt()))
+            })
+            .filter(|&(_, _, distance)| distance < radius + 1.0)
+            .collect();
+        let mut dilated = Self::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                // The edge of a neighbor of coverage `c` is estimated at `c - 0.5` from its center, and it moves
+                // by `radius` towards this pixel.
+                dilated.data[y * self.width + x] = offsets
+                    .iter()
+                    .map(|&(dx, dy, distance)| {
+                        let covered = self.get(x as isize + dx, y as isize + dy);
+                        if covered > 1.0 / 256.0 {
+                            (covered + radius - distance).clamp(0.0, 1.0)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .fold(0.0, f32::max);
+            }
+        }
+        dilated
+    }
+}
+
+struct LineLayout {
+    glyphs: Vec<(GlyphId, f32)>,
+    width: f32,
+}
+
+/// A TrueType or OpenType font.
+pub struct Font {
+    font: FontVec,
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Font")
+            .field("glyph_count", &self.font.glyph_count())
+            .finish()
+    }
+}
+
+impl Font {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let data = fs::read(path).map_err(AviUtlError::Load)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let font = FontVec::try_from_vec(data)
+            .map_err(|err| AviUtlError::Load(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+        Ok(Self { font })
+    }
+
+    fn layout(&self, text: &str, style: &TextStyle) -> (Vec<LineLayout>, f32) {
+        let scaled = self.font.as_scaled(PxScale::from(style.size));
+        let lines = text
+            .split('\n')
+            .map(|line| {
+                let mut glyphs = vec![];
+                let mut x = 0.0;
+                let mut previous = None;
+                for c in line.trim_end_matches('\r').chars() {
+                    let id = scaled.glyph_id(c);
+                    if let Some(previous) = previous {
+                        x += scaled.kern(previous, id);
+                    }
+                    glyphs.push((id, x));
+                    x += scaled.h_advance(id);
+                    previous = Some(id);
+                }
+                LineLayout { glyphs, width: x }
+            })
+            .collect();
+        let line_height = (scaled.height() + scaled.line_gap()) * style.line_spacing;
+        (lines, line_height)
+    }
+
+    /// Size of the text block without the outline and the shadow.
+    fn block_size(&self, lines: &[LineLayout], line_height: f32, style: &TextStyle) -> (f32, f32) {
+        let scaled = self.font.as_scaled(PxScale::from(style.size));
+        let width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+        let height = line_height * (lines.len() - 1) as f32 + scaled.height();
+        (width, height)
+    }
+
+    /// Size of the box which [`Font::draw_text`] renders, including the outline and the shadow.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Size {
+        let (lines, line_height) = self.layout(text, style);
+        let (width, height) = self.block_size(&lines, line_height, style);
+        let margin = 2 * style.outline_margin() as u32;
+        let (shadow_x, shadow_y) = style.shadow_offset();
+        Size {
+            width: width.ceil() as u32 + margin + shadow_x.unsigned_abs(),
+            height: height.ceil() as u32 + margin + shadow_y.unsigned_abs(),
+        }
+    }
+
+    /// Draws `text` with the top-left of the rendered box at `pos`, and returns the size of the box as
+    /// [`Font::measure`].
+    pub fn draw_text(
+        &self,
+        frame: &mut impl Frame,
+        pos: Point,
+        text: &str,
+        style: &TextStyle,
+    ) -> Size {
+        let size = self.measure(text, style);
+        let (lines, line_height) = self.layout(text, style);
+        let (block_width, _) = self.block_size(&lines, line_height, style);
+        let scale = PxScale::from(style.size);
+        let scaled = self.font.as_scaled(scale);
+        let margin = style.outline_margin() as f32;
+        let (shadow_x, shadow_y) = style.shadow_offset();
+        let origin_x = margin + (-shadow_x).max(0) as f32;
+        let origin_y = margin + (-shadow_y).max(0) as f32;
+
+        let mut glyphs = Mask::new(size.width as usize, size.height as usize);
+        for (i, line) in lines.iter().enumerate() {
+            let indent = match style.align {
+                Align::Left => 0.0,
+                Align::Center => (block_width - line.width) / 2.0,
+                Align::Right => block_width - line.width,
+            };
+            let baseline = origin_y + line_height * i as f32 + scaled.ascent();
+            for &(id, x) in &line.glyphs {
+                let glyph = id.with_scale_and_position(
+                    scale,
+                    ab_glyph::point(origin_x + indent + x, baseline),
+                );
+                let Some(outlined) = self.font.outline_glyph(glyph) else {
+                    continue;
+                };
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, coverage| {
+                    let x = bounds.min.x as isize + x as isize;
+                    let y = bounds.min.y as isize + y as isize;
+                    if (0..glyphs.width as isize).contains(&x)
+                        && (0..glyphs.height as isize).contains(&y)
+                    {
+                        let covered = &mut glyphs.data[y as usize * glyphs.width + x as usize];
+                        *covered = (*covered + coverage).min(1.0);
+                    }
+                });
+            }
+        }
+
+        let opacity = style.opacity.min(OPAQUE) as f32 / OPAQUE as f32;
+        let outlined = style.outline.map(|outline| glyphs.dilate(outline.width));
+        if let Some(shadow) = style.shadow {
+            let body = outlined.as_ref().unwrap_or(&glyphs);
+            let shadow_opacity = opacity * shadow.opacity.min(OPAQUE) as f32 / OPAQUE as f32;
+            blend_mask(
+                frame,
+                pos,
+                body,
+                (shadow_x as isize, shadow_y as isize),
+                style.color_space.rgb_to_yc(shadow.color),
+                shadow_opacity,
+            );
+        }
+        if let (Some(outline), Some(outlined)) = (style.outline, &outlined) {
+            let color = style.color_space.rgb_to_yc(outline.color);
+            blend_mask(frame, pos, outlined, (0, 0), color, opacity);
+        }
+        let color = style.color_space.rgb_to_yc(style.color);
+        blend_mask(frame, pos, &glyphs, (0, 0), color, opacity);
+        size
+    }
+}
+
+/// Blends `color` into `frame` by `mask` placed at `pos` and shifted by `offset`.
+fn blend_mask(
+    frame: &mut impl Frame,
+    pos: Point,
+    mask: &Mask,
+    offset: (isize, isize),
+    color: PixelYc,
+    opacity: f32,
+) {
+    let Size { width, height } = frame.frame_size();
+    for my in 0..mask.height as isize {
+        let y = pos.y as isize + my;
+        if !(0..height as isize).contains(&y) {
+            continue;
+        }
+        let line = frame.line_mut(y as usize);
+        for mx in 0..mask.width as isize {
+            let x = pos.x as isize + mx;
+            if !(0..width as isize).contains(&x) {
+                continue;
+            }
+            let alpha = mask.get(mx - offset.0, my - offset.1) * opacity;
+            if alpha > 0.0 {
+                let pixel = &mut line[x as usize];
+                *pixel = lerp(*pixel, color, alpha);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::padded_frame;
+
+    const SIZE: Size = Size {
+        width: 6,
+        height: 4,
+    };
+
+    /// A mask of `width` by `height` with the pixels at `covered` fully covered.
+    fn mask(width: usize, height: usize, covered: &[(usize, usize)]) -> Mask {
+        let mut mask = Mask::new(width, height);
+        for &(x, y) in covered {
+            mask.data[y * width + x] = 1.0;
+        }
+        mask
+    }
+
+    fn lumas(frame: &impl Frame) -> Vec<Vec<i16>> {
+        frame
+            .lines()
+            .map(|line| line.iter().map(|pixel| pixel.y).collect())
+            .collect()
+    }
+
+    #[test]
+    fn invalid_fonts_are_rejected() {
+        assert!(matches!(
+            Font::from_bytes(b"not a font".to_vec()),
+            Err(AviUtlError::Load(_))
+        ));
+        assert!(matches!(
+            Font::from_file("/nonexistent/font.ttf"),
+            Err(AviUtlError::Load(_))
+        ));
+    }
+
+    #[test]
+    fn dilation_grows_by_radius() {
+        let dilated = mask(5, 5, &[(2, 2)]).dilate(1.0);
+        assert_eq!(dilated.get(2, 2), 1.0);
+        assert_eq!(dilated.get(1, 2), 1.0);
+        assert_eq!(dilated.get(2, 3), 1.0);
+        assert!((dilated.get(1, 1) - (2.0 - 2f32.sqrt())).abs() < 1e-5);
+        assert_eq!(dilated.get(0, 2), 0.0);
+        assert_eq!(dilated.get(5, 2), 0.0);
+
+        let faint = Mask {
+            data: vec![0.001],
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(faint.dilate(2.0).get(0, 0), 0.0);
+    }
+
+    #[test]
+    fn masks_blend_with_offset_and_clipping() {
+        let mut frame = padded_frame(SIZE, |_, _| 0);
+        let white = PixelYc {
+            y: PixelYc::Y_MAX,
+            cb: 0,
+            cr: 0,
+        };
+        let covered = mask(3, 2, &[(0, 0), (2, 1)]);
+        blend_mask(
+            &mut frame,
+            Point { x: 4, y: -1 },
+            &covered,
+            (0, 0),
+            white,
+            1.0,
+        );
+        assert_eq!(lumas(&frame)[0], [0, 0, 0, 0, 0, 0]);
+        blend_mask(
+            &mut frame,
+            Point { x: 1, y: 1 },
+            &covered,
+            (1, 1),
+            white,
+            0.5,
+        );
+        let mut expected = vec![vec![0; 6]; 4];
+        expected[2][2] = PixelYc::Y_MAX / 2;
+        assert_eq!(lumas(&frame), expected);
+        assert!(frame.image()[SIZE.width as usize..][..40]
+            .iter()
+            .all(|pixel| pixel.y == PixelYc::Y_MAX));
+    }
+}
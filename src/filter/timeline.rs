@@ -0,0 +1,262 @@
+//! Range editing of the timeline.
+//!
+//! The timeline is the sequence of [`FrameStatus`] of all frames, which refer to the source video and audio and carry
+//! the interlace mode, the profile and the flags such as marks. Each operation computes the new sequence, saves the
+//! undo state once and writes only the frames that changed. If writing fails midway, the previous sequence is written
+//! back.
+//!
+//! The frames are moved by setting their whole [`FrameStatus`] rather than by
+//! [`Editing::copy_video_audio`], [`Editing::copy_video`] or [`Editing::copy_audio`]. A status carries the video and
+//! audio it refers to along with the rest of the frame, so setting it copies the frame as `copy_video_audio` does,
+//! and also allows writing back a saved timeline, which the copies cannot do once their sources are overwritten.
+
+use super::{editing::Editing, frame_status::FrameStatus, EditFlag};
+use crate::{AviUtlError, Result};
+use std::ops::RangeInclusive;
+
+impl Editing<'_> {
+    /// The status of all frames.
+    pub fn frame_statuses(&self) -> Result<Vec<FrameStatus>> {
+        (0..self.total_frames())
+            .map(|frame| self.get_frame_status(frame))
+            .collect()
+    }
+
+    /// Removes the frames of `range`, moving the following frames back.
+    pub fn delete_range(&self, range: RangeInclusive<usize>) -> Result<()> {
+        self.edit_timeline(|timeline| delete_range(timeline, range))
+    }
+
+    /// Inserts `len` frames before `at`, which show the frame before them as [`EditFlag::NULL_FRAME`]s. The gap
+    /// refers to the source of the frame before `at`, or of `at` itself at the start.
+    pub fn insert_gap(&self, at: usize, len: usize) -> Result<()> {
+        self.edit_timeline(|timeline| insert_gap(timeline, at, len))
+    }
+
+    /// Inserts a copy of the frames of `range` right after it.
+    pub fn duplicate_range(&self, range: RangeInclusive<usize>) -> Result<()> {
+        self.edit_timeline(|timeline| duplicate_range(timeline, range))
+    }
+
+    /// Moves the frames of `range` to start at `to` in the resulting timeline.
+    pub fn move_range(&self, range: RangeInclusive<usize>, to: usize) -> Result<()> {
+        self.edit_timeline(|timeline| move_range(timeline, range, to))
+    }
+
+    /// Reverses the order of the frames of `range`.
+    pub fn reverse_range(&self, range: RangeInclusive<usize>) -> Result<()> {
+        self.edit_timeline(|timeline| reverse_range(timeline, range))
+    }
+
+    /// Changes the length of `range` to `len` frames, moving the following frames. A shorter range loses its last
+    /// frames, and a longer range repeats its last frame.
+    pub fn ripple(&self, range: RangeInclusive<usize>, len: usize) -> Result<()> {
+        self.edit_timeline(|timeline| ripple(timeline, range, len))
+    }
+
+    /// Applies `edit` to the timeline in one undo state, restoring the timeline on failure.
+    fn edit_timeline(&self, edit: impl FnOnce(&mut Vec<FrameStatus>) -> Result<()>) -> Result<()> {
+        let before = self.frame_statuses()?;
+        let mut after = before.clone();
+        edit(&mut after)?;
+        if after == before {
+            return Ok(());
+        }
+        self.set_undo()?;
+        self.write_timeline(Some(&before), &after).inspect_err(|_| {
+            let _ = self.write_timeline(None, &before);
+        })
+    }
+
+    /// Writes `timeline`, skipping the frames equal in `current` if known.
+    fn write_timeline(
+        &self,
+        current: Option<&[FrameStatus]>,
+        timeline: &[FrameStatus],
+    ) -> Result<()> {
+        if self.set_total_frames(timeline.len()) != timeline.len() {
+            return Err(AviUtlError::Unsupported(format!(
+                "setting {} frames",
+                timeline.len()
+            )));
+        }
+        for (frame, status) in timeline.iter().enumerate() {
+            if current.and_then(|current| current.get(frame)) != Some(status) {
+                self.set_frame_status(frame, status.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn delete_range(timeline: &mut Vec<FrameStatus>, range: RangeInclusive<usize>) -> Result<()> {
+    let range = checked_range(timeline, range)?;
+    timeline.drain(range);
+    Ok(())
+}
+
+fn insert_gap(timeline: &mut Vec<FrameStatus>, at: usize, len: usize) -> Result<()> {
+    if at > timeline.len() || timeline.is_empty() {
+        return Err(AviUtlError::FrameIndexOutOfRange(at));
+    }
+    let mut gap = timeline[at.saturating_sub(1).min(timeline.len() - 1)].clone();
+    gap.edit_flag = EditFlag::NULL_FRAME;
+    timeline.splice(at..at, std::iter::repeat_n(gap, len));
+    Ok(())
+}
+
+fn duplicate_range(timeline: &mut Vec<FrameStatus>, range: RangeInclusive<usize>) -> Result<()> {
+    let range = checked_range(timeline, range)?;
+    let copy = timeline[range.clone()].to_vec();
+    timeline.splice(range.end() + 1..range.end() + 1, copy);
+    Ok(())
+}
+
+fn move_range(
+    timeline: &mut Vec<FrameStatus>,
+    range: RangeInclusive<usize>,
+    to: usize,
+) -> Result<()> {
+    let range = checked_range(timeline, range)?;
+    let moved: Vec<_> = timeline.drain(range).collect();
+    if to > timeline.len() {
+        return Err(AviUtlError::FrameIndexOutOfRange(to));
+    }
+    timeline.splice(to..to, moved);
+    Ok(())
+}
+
+fn reverse_range(timeline: &mut [FrameStatus], range: RangeInclusive<usize>) -> Result<()> {
+    let range = checked_range(timeline, range)?;
+    timeline[range].reverse();
+    Ok(())
+}
+
+fn ripple(timeline: &mut Vec<FrameStatus>, range: RangeInclusive<usize>, len: usize) -> Result<()> {
+    let range = checked_range(timeline, range)?;
+    let old_len = range.clone().count();
+    if len < old_len {
+        timeline.drain(range.start() + len..=*range.end());
+    } else {
+        let last = timeline[*range.end()].clone();
+        timeline.splice(
+            range.end() + 1..range.end() + 1,
+            std::iter::repeat_n(last, len - old_len),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `range` is a non-empty range of `timeline`.
+fn checked_range(
+    timeline: &[FrameStatus],
+    range: RangeInclusive<usize>,
+) -> Result<RangeInclusive<usize>> {
+    if range.is_empty() {
+        Err(AviUtlError::FrameIndexOutOfRange(*range.start()))
+    } else if *range.end() >= timeline.len() {
+        Err(AviUtlError::FrameIndexOutOfRange(*range.end()))
+    } else {
+        Ok(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FrameInterlace;
+
+    /// A timeline of `len` frames showing the source frames in order.
+    fn timeline(len: usize) -> Vec<FrameStatus> {
+        (0..len)
+            .map(|video_id| FrameStatus {
+                video_id,
+                audio_id: video_id,
+                interlace_mode: FrameInterlace::Normal,
+                index24fps: 0,
+                config_id: 0,
+                vcm_id: 0,
+                edit_flag: EditFlag::empty(),
+            })
+            .collect()
+    }
+
+    fn videos(timeline: &[FrameStatus]) -> Vec<usize> {
+        timeline.iter().map(|status| status.video_id).collect()
+    }
+
+    #[test]
+    fn delete_checks_range() {
+        let mut frames = timeline(5);
+        delete_range(&mut frames, 1..=2).unwrap();
+        assert_eq!(videos(&frames), [0, 3, 4]);
+        assert!(delete_range(&mut frames, 2..=3).is_err());
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 2..=1;
+        assert!(delete_range(&mut frames, empty).is_err());
+        assert_eq!(videos(&frames), [0, 3, 4]);
+    }
+
+    #[test]
+    fn insert_gap_at_ends() {
+        let mut frames = timeline(3);
+        insert_gap(&mut frames, 0, 2).unwrap();
+        assert_eq!(videos(&frames), [0, 0, 0, 1, 2]);
+        assert_eq!(frames[0].edit_flag, EditFlag::NULL_FRAME);
+        assert_eq!(frames[2].edit_flag, EditFlag::empty());
+
+        let mut frames = timeline(3);
+        insert_gap(&mut frames, 3, 1).unwrap();
+        assert_eq!(videos(&frames), [0, 1, 2, 2]);
+        assert_eq!(frames[3].edit_flag, EditFlag::NULL_FRAME);
+        assert!(insert_gap(&mut frames, 5, 1).is_err());
+        assert!(insert_gap(&mut vec![], 0, 1).is_err());
+    }
+
+    #[test]
+    fn duplicate_follows_range() {
+        let mut frames = timeline(4);
+        duplicate_range(&mut frames, 1..=2).unwrap();
+        assert_eq!(videos(&frames), [0, 1, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn move_counts_after_draining() {
+        let mut frames = timeline(5);
+        move_range(&mut frames, 0..=1, 3).unwrap();
+        assert_eq!(videos(&frames), [2, 3, 4, 0, 1]);
+
+        let mut frames = timeline(5);
+        move_range(&mut frames, 3..=4, 0).unwrap();
+        assert_eq!(videos(&frames), [3, 4, 0, 1, 2]);
+
+        // Only 3 frames remain after draining 2, so 4 is past the end.
+        let mut frames = timeline(5);
+        assert!(matches!(
+            move_range(&mut frames, 0..=1, 4),
+            Err(AviUtlError::FrameIndexOutOfRange(4))
+        ));
+    }
+
+    #[test]
+    fn reverse_in_place() {
+        let mut frames = timeline(5);
+        reverse_range(&mut frames, 1..=3).unwrap();
+        assert_eq!(videos(&frames), [0, 3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn ripple_shrinks_and_grows() {
+        let mut frames = timeline(6);
+        ripple(&mut frames, 1..=3, 1).unwrap();
+        assert_eq!(videos(&frames), [0, 1, 4, 5]);
+
+        let mut frames = timeline(4);
+        ripple(&mut frames, 1..=2, 4).unwrap();
+        assert_eq!(videos(&frames), [0, 1, 2, 2, 2, 3]);
+
+        let mut frames = timeline(4);
+        ripple(&mut frames, 1..=2, 2).unwrap();
+        assert_eq!(videos(&frames), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,319 @@
+//! Host-independent reorientation, cropping and padding of [`Frame`]s.
+//!
+//! Operations without suffix work in place and may change the frame size within [`Frame::max_size`]. Operations
+//! suffixed by `_into` write into another frame, resizing it by [`Frame::set_frame_size`].
+
+use super::{Frame, OwnedFrame};
+use crate::{AviUtlError, PixelYc, Point, Rect, Result, Size};
+
+/// Clockwise rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    pub const fn rotated_size(self, size: Size) -> Size {
+        match self {
+            Rotation::Deg180 => size,
+            Rotation::Deg90 | Rotation::Deg270 => Size {
+                width: size.height,
+                height: size.width,
+            },
+        }
+    }
+}
+
+/// Amount of pixels added around a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Padding {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Padding {
+    pub const fn uniform(amount: u32) -> Self {
+        Self {
+            left: amount,
+            top: amount,
+            right: amount,
+            bottom: amount,
+        }
+    }
+
+    pub const fn padded_size(self, size: Size) -> Size {
+        Size {
+            width: size.width + self.left + self.right,
+            height: size.height + self.top + self.bottom,
+        }
+    }
+}
+
+pub fn flip_horizontal(frame: &mut impl Frame) {
+    for line in frame.lines_mut() {
+        line.reverse();
+    }
+}
+
+pub fn flip_vertical(frame: &mut impl Frame) {
+    let Size { width, height } = frame.frame_size();
+    let stride = frame.stride();
+    let image = frame.image_mut();
+    for y in 0..height as usize / 2 {
+        let mirrored = height as usize - 1 - y;
+        let (upper, lower) = image.split_at_mut(mirrored * stride);
+        upper[y * stride..y * stride + width as usize]
+            .swap_with_slice(&mut lower[..width as usize]);
+    }
+}
+
+/// Rotates `frame` in place. It fails if the rotated size exceeds [`Frame::max_size`].
+pub fn rotate(frame: &mut impl Frame, rotation: Rotation) -> Result<()> {
+    if rotation == Rotation::Deg180 {
+        flip_horizontal(frame);
+        flip_vertical(frame);
+        return Ok(());
+    }
+    let source = OwnedFrame::from_frame(frame);
+    rotate_into(frame, &source, rotation)
+}
+
+pub fn rotate_into(dst: &mut impl Frame, src: &impl Frame, rotation: Rotation) -> Result<()> {
+    let size = src.frame_size();
+    let (width, height) = (size.width as usize, size.height as usize);
+    dst.set_frame_size(rotation.rotated_size(size))?;
+    for (y, line) in dst.lines_mut().enumerate() {
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = match rotation {
+                Rotation::Deg90 => src.pixel(y, height - 1 - x),
+                Rotation::Deg180 => src.pixel(width - 1 - x, height - 1 - y),
+                Rotation::Deg270 => src.pixel(width - 1 - y, x),
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Swaps the axes of `frame` in place. It fails if the transposed size exceeds [`Frame::max_size`].
+pub fn transpose(frame: &mut impl Frame) -> Result<()> {
+    let source = OwnedFrame::from_frame(frame);
+    transpose_into(frame, &source)
+}
+
+pub fn transpose_into(dst: &mut impl Frame, src: &impl Frame) -> Result<()> {
+    let size = src.frame_size();
+    dst.set_frame_size(Size {
+        width: size.height,
+        height: size.width,
+    })?;
+    for (y, line) in dst.lines_mut().enumerate() {
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = src.pixel(y, x);
+        }
+    }
+    Ok(())
+}
+
+fn clip_to_frame(rect: Rect, size: Size) -> Result<Rect> {
+    let bounds = Rect {
+        size,
+        point: Point::new(),
+    };
+    rect.intersection(bounds)
+        .ok_or_else(|| AviUtlError::Unsupported(format!("cropping outside of frame by {:?}", rect)))
+}
+
+/// Crops `frame` in place to `rect`, clipped by the frame bounds.
+pub fn crop(frame: &mut impl Frame, rect: Rect) -> Result<()> {
+    let rect = clip_to_frame(rect, frame.frame_size())?;
+    let stride = frame.stride();
+    let (left, top) = (rect.left() as usize, rect.top() as usize);
+    let width = rect.size.width as usize;
+    let image = frame.image_mut();
+    for y in 0..rect.size.height as usize {
+        let from = (top + y) * stride + left;
+        image.copy_within(from..from + width, y * stride);
+    }
+    frame.set_frame_size(rect.size)
+}
+
+pub fn crop_into(dst: &mut impl Frame, src: &impl Frame, rect: Rect) -> Result<()> {
+    let rect = clip_to_frame(rect, src.frame_size())?;
+    dst.set_frame_size(rect.size)?;
+    let (left, top) = (rect.left() as usize, rect.top() as usize);
+    for (y, line) in dst.lines_mut().enumerate() {
+        line.copy_from_slice(&src.line(top + y)[left..left + line.len()]);
+    }
+    Ok(())
+}
+
+fn fill_padding(frame: &mut impl Frame, padding: Padding, fill: PixelYc) {
+    let Size { width, height } = frame.frame_size();
+    let inner = (padding.top as usize)..(height - padding.bottom) as usize;
+    let right_start = (width - padding.right) as usize;
+    for (y, line) in frame.lines_mut().enumerate() {
+        if inner.contains(&y) {
+            line[..padding.left as usize].fill(fill);
+            line[right_start..].fill(fill);
+        } else {
+            line.fill(fill);
+        }
+    }
+}
+
+/// Pads `frame` in place with `fill`. It fails if the padded size exceeds [`Frame::max_size`].
+pub fn pad(frame: &mut impl Frame, padding: Padding, fill: PixelYc) -> Result<()> {
+    let size = frame.frame_size();
+    frame.set_frame_size(padding.padded_size(size))?;
+    let stride = frame.stride();
+    let width = size.width as usize;
+    let image = frame.image_mut();
+    // Moves from the bottom line because the destination is always behind the source.
+    for y in (0..size.height as usize).rev() {
+        let from = y * stride;
+        let to = (y + padding.top as usize) * stride + padding.left as usize;
+        image.copy_within(from..from + width, to);
+    }
+    fill_padding(frame, padding, fill);
+    Ok(())
+}
+
+pub fn pad_into(
+    dst: &mut impl Frame,
+    src: &impl Frame,
+    padding: Padding,
+    fill: PixelYc,
+) -> Result<()> {
+    dst.set_frame_size(padding.padded_size(src.frame_size()))?;
+    let left = padding.left as usize;
+    for (y, line) in src.lines().enumerate() {
+        dst.line_mut(y + padding.top as usize)[left..left + line.len()].copy_from_slice(line);
+    }
+    fill_padding(dst, padding, fill);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Size = Size {
+        width: 3,
+        height: 2,
+    };
+
+    /// A frame of `SIZE` whose luma is `10 * y + x`, which can hold up to 5x5 pixels.
+    fn frame() -> OwnedFrame {
+        let mut frame = OwnedFrame::with_max_size(
+            SIZE,
+            Size {
+                width: 5,
+                height: 5,
+            },
+        );
+        for (y, line) in frame.lines_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                pixel.y = (10 * y + x) as i16;
+            }
+        }
+        frame
+    }
+
+    fn lumas(frame: &impl Frame) -> Vec<Vec<i16>> {
+        frame
+            .lines()
+            .map(|line| line.iter().map(|pixel| pixel.y).collect())
+            .collect()
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        let cases = [
+            (Rotation::Deg90, vec![vec![10, 0], vec![11, 1], vec![12, 2]]),
+            (Rotation::Deg180, vec![vec![12, 11, 10], vec![2, 1, 0]]),
+            (
+                Rotation::Deg270,
+                vec![vec![2, 12], vec![1, 11], vec![0, 10]],
+            ),
+        ];
+        for (rotation, expected) in cases {
+            let mut rotated = frame();
+            rotate(&mut rotated, rotation).unwrap();
+            assert_eq!(lumas(&rotated), expected, "{:?}", rotation);
+            let mut dst = OwnedFrame::new(Size::new());
+            assert!(rotate_into(&mut dst, &frame(), rotation).is_err());
+        }
+        let mut frame = frame();
+        for _ in 0..4 {
+            rotate(&mut frame, Rotation::Deg90).unwrap();
+        }
+        assert_eq!(lumas(&frame), [[0, 1, 2], [10, 11, 12]]);
+    }
+
+    #[test]
+    fn rotation_needs_room() {
+        let mut tight = OwnedFrame::from_frame(&frame());
+        assert!(rotate(&mut tight, Rotation::Deg90).is_err());
+        rotate(&mut tight, Rotation::Deg180).unwrap();
+        assert_eq!(lumas(&tight), [[12, 11, 10], [2, 1, 0]]);
+    }
+
+    #[test]
+    fn transposes_and_flips() {
+        let mut transposed = frame();
+        transpose(&mut transposed).unwrap();
+        assert_eq!(lumas(&transposed), [[0, 10], [1, 11], [2, 12]]);
+        flip_vertical(&mut transposed);
+        assert_eq!(lumas(&transposed), [[2, 12], [1, 11], [0, 10]]);
+        flip_horizontal(&mut transposed);
+        assert_eq!(lumas(&transposed), [[12, 2], [11, 1], [10, 0]]);
+    }
+
+    #[test]
+    fn crops_within_frame() {
+        let rect = |x, y, width, height| Rect {
+            size: Size { width, height },
+            point: Point { x, y },
+        };
+        let mut cropped = frame();
+        crop(&mut cropped, rect(1, -1, 5, 5)).unwrap();
+        assert_eq!(lumas(&cropped), [[1, 2], [11, 12]]);
+        let mut dst = OwnedFrame::with_max_size(Size::new(), SIZE);
+        crop_into(&mut dst, &frame(), rect(0, 1, 2, 1)).unwrap();
+        assert_eq!(lumas(&dst), [[10, 11]]);
+        assert!(crop(&mut frame(), rect(3, 0, 2, 2)).is_err());
+    }
+
+    #[test]
+    fn pads_around() {
+        let fill = PixelYc {
+            y: -1,
+            cb: 0,
+            cr: 0,
+        };
+        let padding = Padding {
+            left: 1,
+            top: 1,
+            right: 0,
+            bottom: 2,
+        };
+        let expected = [
+            [-1, -1, -1, -1],
+            [-1, 0, 1, 2],
+            [-1, 10, 11, 12],
+            [-1, -1, -1, -1],
+            [-1, -1, -1, -1],
+        ];
+        let mut padded = frame();
+        pad(&mut padded, padding, fill).unwrap();
+        assert_eq!(lumas(&padded), expected);
+        let mut dst = OwnedFrame::new(padding.padded_size(SIZE));
+        pad_into(&mut dst, &frame(), padding, fill).unwrap();
+        assert_eq!(lumas(&dst), expected);
+        assert!(pad(&mut frame(), Padding::uniform(2), fill).is_err());
+    }
+}
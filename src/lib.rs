@@ -40,6 +40,10 @@ pub enum AviUtlError {
     ConfigFailure(String),
     #[error("frame {0} is out of range")]
     FrameIndexOutOfRange(usize),
+    #[error("parse error at line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("cancelled by the user")]
+    Cancelled,
     #[error("no implementation provided")]
     NoImpl,
 }
@@ -181,6 +185,21 @@ impl Rect {
     pub const fn bottom(self) -> i32 {
         self.point.y + self.size.height as i32
     }
+
+    /// Returns the overlapping area of two rectangles, or `None` if they do not overlap.
+    pub fn intersection(self, other: Self) -> Option<Self> {
+        let left = self.left().max(other.left());
+        let top = self.top().max(other.top());
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (left < right && top < bottom).then(|| Self {
+            size: Size {
+                width: (right - left) as u32,
+                height: (bottom - top) as u32,
+            },
+            point: Point { x: left, y: top },
+        })
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -290,9 +309,13 @@ impl<'a> IntoIterator for &'a FileFilters {
     }
 }
 
+pub mod audio;
 pub mod color;
+pub mod colorspace;
+pub mod executor;
 pub mod filter;
 pub mod input;
 pub mod output;
+pub mod pixel;
 
 // TODO: add prelude
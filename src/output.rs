@@ -1,4 +1,10 @@
-use crate::{from_win_str, AviUtlError, FileFilters, FrameRate, PixelFormat, Result, Size};
+use crate::{
+    audio::{
+        remix::RemixMatrix, resample::Resampler, AudioBuf, AudioFormat, ChannelLayout, Sample,
+        SampleFormat,
+    },
+    from_win_str, AviUtlError, FileFilters, FrameRate, PixelFormat, Result, Size,
+};
 use aviutl_plugin_sys::output::OutputInfo;
 use std::{borrow::Cow, ffi::CStr, ops::Range, ptr::NonNull};
 use windows::Win32::Foundation::{HINSTANCE, HWND};
@@ -44,7 +50,7 @@ impl<'a> Info<'a> {
             video_bytes_per_frame: info_ref.size.try_into().unwrap(),
             audio_sample_rate: info_ref.audio_rate.try_into().unwrap(),
             audio_channels: info_ref.audio_ch.try_into().unwrap(),
-            audio_samples: info_ref.audio_ch.try_into().unwrap(),
+            audio_samples: info_ref.audio_n.try_into().unwrap(),
             audio_bytes_per_sample: info_ref.audio_size.try_into().unwrap(),
             save_file: from_win_str(CStr::from_ptr(info_ref.save_file as *const _).to_bytes()),
             raw: NonNull::new(ptr).unwrap(),
@@ -65,16 +71,75 @@ impl<'a> Info<'a> {
         }
     }
 
-    pub fn get_audio(&self, range: Range<usize>) -> &[u8] {
+    /// Format of the audio returned by [`Info::get_audio`].
+    pub fn audio_format(&self) -> AudioFormat {
+        AudioFormat {
+            sample_rate: self.audio_sample_rate,
+            layout: ChannelLayout::from_channels(self.audio_channels as u16),
+            sample_format: SampleFormat::I16,
+        }
+    }
+
+    /// Reads the samples per channel in `range`, truncated at the end of the audio.
+    pub fn get_audio(&self, range: Range<usize>) -> AudioBuf<i16> {
+        let layout = ChannelLayout::from_channels(self.audio_channels as u16);
         let mut written_samples = 0;
-        unsafe {
-            let ptr = (self.raw.as_ref().func_get_audio)(
+        let ptr = unsafe {
+            (self.raw.as_ref().func_get_audio)(
                 range.start as _,
                 range.end.saturating_sub(range.start) as _,
                 &mut written_samples,
-            );
-            std::slice::from_raw_parts(ptr.cast(), written_samples as usize)
+            )
+        };
+        if ptr.is_null() {
+            return AudioBuf::new(layout, 0);
         }
+        let mut buf = AudioBuf::new(layout, written_samples.max(0) as usize);
+        let len = buf.interleaved().len();
+        buf.interleaved_mut()
+            .copy_from_slice(unsafe { std::slice::from_raw_parts(ptr.cast(), len) });
+        buf
+    }
+
+    /// Reads the whole audio in chunks of `chunk` samples per channel, passing them to `f` converted into `layout`
+    /// at `sample_rate`. It stops without error when the output is aborted.
+    pub fn read_audio_as<S: Sample>(
+        &self,
+        sample_rate: u32,
+        layout: ChannelLayout,
+        chunk: usize,
+        mut f: impl FnMut(AudioBuf<S>) -> Result<()>,
+    ) -> Result<()> {
+        let source = self.audio_format();
+        let remix = (source.layout != layout)
+            .then(|| RemixMatrix::standard(source.layout, layout))
+            .transpose()?;
+        let mut resampler = (source.sample_rate != sample_rate)
+            .then(|| Resampler::new(layout, source.sample_rate, sample_rate))
+            .transpose()?;
+        let mut start = 0;
+        while start < self.audio_samples {
+            if self.is_aborted() {
+                return Ok(());
+            }
+            let end = (start + chunk.max(1)).min(self.audio_samples);
+            let mut audio = self.get_audio(start..end).convert::<f32>();
+            if audio.is_empty() {
+                break;
+            }
+            start += audio.frames();
+            if let Some(remix) = &remix {
+                audio = remix.apply(&audio)?;
+            }
+            if let Some(resampler) = &mut resampler {
+                audio = resampler.process(&audio)?;
+            }
+            f(audio.convert())?;
+        }
+        if let Some(resampler) = &mut resampler {
+            f(resampler.flush::<f32>()?.convert())?;
+        }
+        Ok(())
     }
 
     pub fn is_aborted(&self) -> bool {
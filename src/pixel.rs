@@ -0,0 +1,217 @@
+//! Widened accumulator pixels for blending math on [`PixelYc`].
+//!
+//! Summing or scaling [`PixelYc`] directly saturates at the bounds of `i16`, so intermediate values should be kept
+//! in [`WidePixelYc`] or [`FloatPixelYc`] and converted back by `to_pixel` at the end.
+
+use crate::PixelYc;
+use derive_more::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// [`PixelYc`] with `i32` components.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WidePixelYc {
+    pub y: i32,
+    pub cb: i32,
+    pub cr: i32,
+}
+
+impl WidePixelYc {
+    pub const fn new() -> Self {
+        Self { y: 0, cb: 0, cr: 0 }
+    }
+
+    pub fn checked_mul(self, rhs: i32) -> Option<Self> {
+        Some(Self {
+            y: self.y.checked_mul(rhs)?,
+            cb: self.cb.checked_mul(rhs)?,
+            cr: self.cr.checked_mul(rhs)?,
+        })
+    }
+
+    pub fn saturating_mul(self, rhs: i32) -> Self {
+        Self {
+            y: self.y.saturating_mul(rhs),
+            cb: self.cb.saturating_mul(rhs),
+            cr: self.cr.saturating_mul(rhs),
+        }
+    }
+
+    /// Divides with rounding to nearest, ties away from zero.
+    pub fn div_round(self, rhs: i32) -> Self {
+        assert_ne!(rhs, 0, "division by zero");
+        fn div(lhs: i32, rhs: i32) -> i32 {
+            let half = rhs.abs() / 2;
+            let rounded = if (lhs < 0) != (rhs < 0) {
+                lhs as i64 - half as i64
+            } else {
+                lhs as i64 + half as i64
+            };
+            (rounded / rhs as i64) as i32
+        }
+        Self {
+            y: div(self.y, rhs),
+            cb: div(self.cb, rhs),
+            cr: div(self.cr, rhs),
+        }
+    }
+
+    /// Converts into [`PixelYc`], clamping the components into the documented ranges.
+    pub fn to_pixel(self) -> PixelYc {
+        PixelYc {
+            y: self.y.clamp(PixelYc::Y_MIN as i32, PixelYc::Y_MAX as i32) as i16,
+            cb: self.cb.clamp(PixelYc::C_MIN as i32, PixelYc::C_MAX as i32) as i16,
+            cr: self.cr.clamp(PixelYc::C_MIN as i32, PixelYc::C_MAX as i32) as i16,
+        }
+    }
+}
+
+impl Add for WidePixelYc {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            y: self.y.saturating_add(rhs.y),
+            cb: self.cb.saturating_add(rhs.cb),
+            cr: self.cr.saturating_add(rhs.cr),
+        }
+    }
+}
+
+impl AddAssign for WidePixelYc {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for WidePixelYc {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            y: self.y.saturating_sub(rhs.y),
+            cb: self.cb.saturating_sub(rhs.cb),
+            cr: self.cr.saturating_sub(rhs.cr),
+        }
+    }
+}
+
+impl SubAssign for WidePixelYc {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl From<PixelYc> for WidePixelYc {
+    fn from(pixel: PixelYc) -> Self {
+        Self {
+            y: pixel.y as i32,
+            cb: pixel.cb as i32,
+            cr: pixel.cr as i32,
+        }
+    }
+}
+
+/// [`PixelYc`] with `f32` components, in the same scale as YC48.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    AddAssign,
+    SubAssign,
+    MulAssign,
+    DivAssign,
+)]
+pub struct FloatPixelYc {
+    pub y: f32,
+    pub cb: f32,
+    pub cr: f32,
+}
+
+impl FloatPixelYc {
+    pub const fn new() -> Self {
+        Self {
+            y: 0.0,
+            cb: 0.0,
+            cr: 0.0,
+        }
+    }
+
+    /// Converts into [`PixelYc`], rounding and clamping the components into the documented ranges.
+    pub fn to_pixel(self) -> PixelYc {
+        PixelYc {
+            y: self
+                .y
+                .round()
+                .clamp(PixelYc::Y_MIN as f32, PixelYc::Y_MAX as f32) as i16,
+            cb: self
+                .cb
+                .round()
+                .clamp(PixelYc::C_MIN as f32, PixelYc::C_MAX as f32) as i16,
+            cr: self
+                .cr
+                .round()
+                .clamp(PixelYc::C_MIN as f32, PixelYc::C_MAX as f32) as i16,
+        }
+    }
+}
+
+impl From<PixelYc> for FloatPixelYc {
+    fn from(pixel: PixelYc) -> Self {
+        Self {
+            y: pixel.y as f32,
+            cb: pixel.cb as f32,
+            cr: pixel.cr as f32,
+        }
+    }
+}
+
+/// Sums the pixels multiplied by their weights.
+pub fn weighted_sum(pixels: impl IntoIterator<Item = (PixelYc, f32)>) -> FloatPixelYc {
+    pixels
+        .into_iter()
+        .fold(FloatPixelYc::new(), |acc, (pixel, weight)| {
+            acc + FloatPixelYc::from(pixel) * weight
+        })
+}
+
+/// Averages the pixels by their weights, or returns `None` if the weights sum up to zero.
+pub fn weighted_average(pixels: impl IntoIterator<Item = (PixelYc, f32)>) -> Option<PixelYc> {
+    let (sum, total) = pixels.into_iter().fold(
+        (FloatPixelYc::new(), 0.0),
+        |(acc, total), (pixel, weight)| (acc + FloatPixelYc::from(pixel) * weight, total + weight),
+    );
+    (total != 0.0).then(|| (sum / total).to_pixel())
+}
+
+/// Averages the pixels, or returns `None` if `pixels` is empty.
+pub fn average(pixels: impl IntoIterator<Item = PixelYc>) -> Option<PixelYc> {
+    let (sum, count) = pixels
+        .into_iter()
+        .fold((WidePixelYc::new(), 0), |(acc, count), pixel| {
+            (acc + pixel.into(), count + 1)
+        });
+    (count != 0).then(|| sum.div_round(count).to_pixel())
+}
+
+/// Interpolates linearly from `a` (at `t == 0.0`) to `b` (at `t == 1.0`).
+pub fn lerp(a: PixelYc, b: PixelYc, t: f32) -> PixelYc {
+    let a = FloatPixelYc::from(a);
+    let b = FloatPixelYc::from(b);
+    (a + (b - a) * t).to_pixel()
+}
+
+/// Interpolates linearly by the opacity `t` between 0 and 4096 as AviUtl does.
+pub fn lerp_fixed(a: PixelYc, b: PixelYc, t: u16) -> PixelYc {
+    let t = t.min(4096) as i32;
+    let a = WidePixelYc::from(a);
+    let b = WidePixelYc::from(b);
+    (a.saturating_mul(4096 - t) + b.saturating_mul(t))
+        .div_round(4096)
+        .to_pixel()
+}